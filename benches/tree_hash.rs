@@ -0,0 +1,37 @@
+//! Compare a full tree hash with an incremental one on a large tree.
+//! Run with `cargo bench --bench tree_hash`.
+
+use mls_protospec::prelude::*;
+use std::time::Instant;
+
+const DEFAULT_CIPHERSUITE: CiphersuiteName =
+    CiphersuiteName::MLS10_128_DHKEMX25519_AES128GCM_SHA256_Ed25519;
+const NUM_LEAVES: usize = 10_000;
+const ITERATIONS: u32 = 100;
+
+fn main() {
+    let mut tree = Tree::new(DEFAULT_CIPHERSUITE);
+    for _ in 0..NUM_LEAVES {
+        tree.add_leaf();
+    }
+
+    // All nodes are dirty at this point.
+    let start = Instant::now();
+    tree.tree_hash().unwrap();
+    let full = start.elapsed();
+    println!("Full tree hash ({} leaves): {:?}", NUM_LEAVES, full);
+
+    // Every new leaf only dirties its direct path.
+    let mut incremental = std::time::Duration::default();
+    for _ in 0..ITERATIONS {
+        tree.add_leaf();
+        let start = Instant::now();
+        tree.tree_hash().unwrap();
+        incremental += start.elapsed();
+    }
+    println!(
+        "Incremental tree hash ({} leaves): {:?}",
+        NUM_LEAVES,
+        incremental / ITERATIONS
+    );
+}
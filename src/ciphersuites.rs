@@ -1,8 +1,10 @@
 use evercrypt::{aead, digest, hmac, signature};
-use hpke;
-use crate::traits::Encode;
+use hpke::{self, Hpke};
+use crate::encode_util::{decode_slice, encode_slice, Cursor, LenType};
+use crate::traits::{Decode, DecodeError, Encode};
+use crate::util::equal_ct;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Ciphersuite {
     pub(crate) name: Name,
     pub(crate) hash: digest::Mode,
@@ -17,7 +19,7 @@ pub struct Ciphersuite {
 impl Encode for Ciphersuite {
     fn encode(&self) -> Vec<u8> {
         let mut out = Vec::new();
-        out.extend_from_slice(&(self.name as u16).to_be_bytes());
+        out.extend_from_slice(&u16::from(&self.name).to_be_bytes());
         out
     }
 }
@@ -32,12 +34,19 @@ impl Default for Ciphersuite {
 #[allow(non_camel_case_types)]
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Name {
-    MLS10_128_DHKEMX25519_AES128GCM_SHA256_Ed25519 = 0x0001,
-    MLS10_128_DHKEMP256_AES128GCM_SHA256_P256 = 0x0002,
-    MLS10_128_DHKEMX25519_CHACHA20POLY1305_SHA256_Ed25519 = 0x0003,
-    MLS10_256_DHKEMX448_AES256GCM_SHA512_Ed448 = 0x0004,
-    MLS10_256_DHKEMP521_AES256GCM_SHA512_P521 = 0x0005,
-    MLS10_256_DHKEMX448_CHACHA20POLY1305_SHA512_Ed448 = 0x0006,
+    MLS10_128_DHKEMX25519_AES128GCM_SHA256_Ed25519,
+    MLS10_128_DHKEMP256_AES128GCM_SHA256_P256,
+    MLS10_128_DHKEMX25519_CHACHA20POLY1305_SHA256_Ed25519,
+    MLS10_256_DHKEMX448_AES256GCM_SHA512_Ed448,
+    MLS10_256_DHKEMP521_AES256GCM_SHA512_P521,
+    MLS10_256_DHKEMX448_CHACHA20POLY1305_SHA512_Ed448,
+    /// A ciphersuite this implementation doesn't support. It can only be
+    /// advertised, e.g. in a capabilities extension.
+    Unknown(u16),
+}
+
+fn unsupported(name: &Name) -> ! {
+    panic!("Unsupported ciphersuite {:?}", name)
 }
 
 fn get_hash_from_suite(name: &Name) -> digest::Mode {
@@ -48,6 +57,7 @@ fn get_hash_from_suite(name: &Name) -> digest::Mode {
         Name::MLS10_256_DHKEMX448_AES256GCM_SHA512_Ed448 => digest::Mode::Sha512,
         Name::MLS10_256_DHKEMP521_AES256GCM_SHA512_P521 => digest::Mode::Sha512,
         Name::MLS10_256_DHKEMX448_CHACHA20POLY1305_SHA512_Ed448 => digest::Mode::Sha512,
+        Name::Unknown(_) => unsupported(name),
     }
 }
 
@@ -59,6 +69,7 @@ fn get_aead_from_suite(name: &Name) -> aead::Mode {
         Name::MLS10_256_DHKEMX448_AES256GCM_SHA512_Ed448 => aead::Mode::Aes256Gcm,
         Name::MLS10_256_DHKEMP521_AES256GCM_SHA512_P521 => aead::Mode::Aes256Gcm,
         Name::MLS10_256_DHKEMX448_CHACHA20POLY1305_SHA512_Ed448 => aead::Mode::Chacha20Poly1305,
+        Name::Unknown(_) => unsupported(name),
     }
 }
 
@@ -91,6 +102,7 @@ fn get_kdf_from_suite(name: &Name) -> hmac::Mode {
         Name::MLS10_256_DHKEMX448_AES256GCM_SHA512_Ed448
         | Name::MLS10_256_DHKEMP521_AES256GCM_SHA512_P521
         | Name::MLS10_256_DHKEMX448_CHACHA20POLY1305_SHA512_Ed448 => hmac::Mode::Sha512,
+        Name::Unknown(_) => unsupported(name),
     }
 }
 
@@ -106,6 +118,7 @@ fn get_hpke_aead_from_suite(name: &Name) -> hpke::aead::Mode {
         Name::MLS10_256_DHKEMX448_CHACHA20POLY1305_SHA512_Ed448 => {
             hpke::aead::Mode::ChaCha20Poly1305
         }
+        Name::Unknown(_) => unsupported(name),
     }
 }
 
@@ -121,6 +134,7 @@ fn get_hpke_kdf_from_suite(name: &Name) -> hpke::kdf::Mode {
         Name::MLS10_256_DHKEMX448_CHACHA20POLY1305_SHA512_Ed448 => {
             hpke::kdf::Mode::HkdfSha512
         }
+        Name::Unknown(_) => unsupported(name),
     }
 }
 
@@ -140,26 +154,229 @@ impl Ciphersuite {
     pub fn get_name(&self) -> &Name {
         &self.name
     }
+
+    /// The output length of the KDF (`KDF.Nh`).
+    pub(crate) fn hash_length(&self) -> usize {
+        hmac::get_tag_size(self.kdf)
+    }
+
+    // MAC(key, data) is HMAC with the hash of the ciphersuite.
+    pub(crate) fn mac(&self, key: &[u8], data: &[u8]) -> Vec<u8> {
+        hmac::hmac(self.kdf, key, data, None)
+    }
+
+    /// Check `tag` against `MAC(key, data)` in constant time.
+    pub(crate) fn verify_mac(&self, key: &[u8], data: &[u8], tag: &[u8]) -> bool {
+        equal_ct(&self.mac(key, data), tag)
+    }
+
+    fn hpke(&self) -> Hpke {
+        Hpke::new(hpke::Mode::Base, self.kem, self.hpke_kdf, self.hpke_aead)
+    }
+
+    /// Derive an HPKE key pair `(private_key, public_key)` from `ikm`.
+    pub(crate) fn derive_hpke_key_pair(&self, ikm: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        self.hpke().derive_key_pair(ikm)
+    }
+
+    pub(crate) fn aead_key_length(&self) -> usize {
+        aead::key_size(self.aead)
+    }
+
+    pub(crate) fn aead_nonce_length(&self) -> usize {
+        aead::nonce_size(self.aead)
+    }
+
+    /// AEAD encryption. The tag is appended to the ciphertext.
+    pub(crate) fn aead_seal(&self, key: &[u8], nonce: &[u8], aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+        let (mut ciphertext, tag) = aead::encrypt(self.aead, key, plaintext, nonce, aad)
+            .expect("AEAD encryption with a valid key and nonce can't fail.");
+        ciphertext.extend_from_slice(&tag);
+        ciphertext
+    }
+
+    /// AEAD decryption of a ciphertext with appended tag.
+    pub(crate) fn aead_open(
+        &self,
+        key: &[u8],
+        nonce: &[u8],
+        aad: &[u8],
+        ciphertext: &[u8],
+    ) -> Result<Vec<u8>, AeadError> {
+        let tag_length = aead::tag_size(self.aead);
+        if ciphertext.len() < tag_length {
+            return Err(AeadError::DecryptionFailed);
+        }
+        let (ciphertext, tag) = ciphertext.split_at(ciphertext.len() - tag_length);
+        aead::decrypt(self.aead, key, ciphertext, tag, nonce, aad)
+            .map_err(|_| AeadError::DecryptionFailed)
+    }
+
+    /// HPKE base mode single-shot encryption.
+    pub(crate) fn hpke_seal(
+        &self,
+        public_key: &[u8],
+        info: &[u8],
+        aad: &[u8],
+        plaintext: &[u8],
+    ) -> HpkeCiphertext {
+        let (kem_output, ciphertext) =
+            self.hpke()
+                .seal(public_key, info, aad, plaintext, None, None, None);
+        HpkeCiphertext {
+            kem_output,
+            ciphertext,
+        }
+    }
+
+    /// HPKE base mode single-shot decryption.
+    pub(crate) fn hpke_open(
+        &self,
+        private_key: &[u8],
+        info: &[u8],
+        aad: &[u8],
+        ciphertext: &HpkeCiphertext,
+    ) -> Result<Vec<u8>, HpkeError> {
+        self.hpke()
+            .open(
+                &ciphertext.kem_output,
+                private_key,
+                info,
+                aad,
+                &ciphertext.ciphertext,
+                None,
+                None,
+                None,
+            )
+            .map_err(|_| HpkeError::DecryptionFailed)
+    }
+
+    /// Set up an HPKE base mode context to `public_key` and export a secret
+    /// of `length` bytes from it. Returns the KEM output and the secret.
+    pub(crate) fn hpke_export_sender(
+        &self,
+        public_key: &[u8],
+        info: &[u8],
+        exporter_context: &[u8],
+        length: usize,
+    ) -> (Vec<u8>, Vec<u8>) {
+        let (kem_output, context) = self
+            .hpke()
+            .setup_sender(public_key, info, None, None, None);
+        (kem_output, context.export(exporter_context, length))
+    }
+
+    /// Set up the receiver side of an HPKE base mode context from
+    /// `kem_output` and export a secret of `length` bytes from it.
+    pub(crate) fn hpke_export_receiver(
+        &self,
+        kem_output: &[u8],
+        private_key: &[u8],
+        info: &[u8],
+        exporter_context: &[u8],
+        length: usize,
+    ) -> Result<Vec<u8>, HpkeError> {
+        let context = self
+            .hpke()
+            .setup_receiver(kem_output, private_key, info, None, None, None)
+            .map_err(|_| HpkeError::DecryptionFailed)?;
+        Ok(context.export(exporter_context, length))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HpkeError {
+    DecryptionFailed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AeadError {
+    DecryptionFailed,
+}
+
+// Section 7.4
+// struct {
+//     opaque kem_output<0..2^16-1>;
+//     opaque ciphertext<0..2^16-1>;
+// } HPKECiphertext;
+#[derive(Debug, Clone, PartialEq)]
+pub struct HpkeCiphertext {
+    kem_output: Vec<u8>,
+    ciphertext: Vec<u8>,
+}
+
+impl Encode for HpkeCiphertext {
+    fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        encode_slice(LenType::L16, &self.kem_output, &mut out);
+        encode_slice(LenType::L16, &self.ciphertext, &mut out);
+        out
+    }
+}
+
+impl Decode for HpkeCiphertext {
+    fn decode(cursor: &mut Cursor) -> Result<Self, DecodeError> {
+        Ok(Self {
+            kem_output: decode_slice(LenType::L16, cursor)?,
+            ciphertext: decode_slice(LenType::L16, cursor)?,
+        })
+    }
 }
 
 impl From<&Name> for u16 {
     fn from(s: &Name) -> u16 {
-        *s as u16
+        match s {
+            Name::MLS10_128_DHKEMX25519_AES128GCM_SHA256_Ed25519 => 0x0001,
+            Name::MLS10_128_DHKEMP256_AES128GCM_SHA256_P256 => 0x0002,
+            Name::MLS10_128_DHKEMX25519_CHACHA20POLY1305_SHA256_Ed25519 => 0x0003,
+            Name::MLS10_256_DHKEMX448_AES256GCM_SHA512_Ed448 => 0x0004,
+            Name::MLS10_256_DHKEMP521_AES256GCM_SHA512_P521 => 0x0005,
+            Name::MLS10_256_DHKEMX448_CHACHA20POLY1305_SHA512_Ed448 => 0x0006,
+            Name::Unknown(v) => *v,
+        }
     }
 }
 
-impl From<u16> for Ciphersuite {
+impl From<u16> for Name {
     fn from(v: u16) -> Self {
-        let name = match v {
+        match v {
             0x0001 => Name::MLS10_128_DHKEMX25519_AES128GCM_SHA256_Ed25519,
             0x0002 => Name::MLS10_128_DHKEMP256_AES128GCM_SHA256_P256,
             0x0003 => Name::MLS10_128_DHKEMX25519_CHACHA20POLY1305_SHA256_Ed25519,
             0x0004 => Name::MLS10_256_DHKEMX448_AES256GCM_SHA512_Ed448,
             0x0005 => Name::MLS10_256_DHKEMP521_AES256GCM_SHA512_P521,
             0x0006 => Name::MLS10_256_DHKEMX448_CHACHA20POLY1305_SHA512_Ed448,
-            _ => panic!("Not implemented."),
-        };
-        Self::new(name)
+            _ => Name::Unknown(v),
+        }
+    }
+}
+
+impl From<u16> for Ciphersuite {
+    fn from(v: u16) -> Self {
+        Self::new(Name::from(v))
+    }
+}
+
+/// Only supported ciphersuites can be decoded. Unknown ones are only
+/// accepted as a `Name`.
+impl Decode for Ciphersuite {
+    fn decode(cursor: &mut Cursor) -> Result<Self, DecodeError> {
+        match Name::decode(cursor)? {
+            Name::Unknown(_) => Err(DecodeError::InvalidValue),
+            name => Ok(Self::new(name)),
+        }
+    }
+}
+
+impl Encode for Name {
+    fn encode(&self) -> Vec<u8> {
+        u16::from(self).encode()
+    }
+}
+
+impl Decode for Name {
+    fn decode(cursor: &mut Cursor) -> Result<Self, DecodeError> {
+        Ok(Name::from(u16::decode(cursor)?))
     }
 }
 
@@ -173,3 +390,21 @@ impl From<Name> for Ciphersuite {
 fn test_ciphersuite() {
     assert_eq!(Ciphersuite::from(1), Ciphersuite::default());
 }
+
+#[test]
+fn test_mac() {
+    // RFC 4231 Test Case 2
+    let ciphersuite = Ciphersuite::default();
+    let tag = ciphersuite.mac(b"Jefe", b"what do ya want for nothing?");
+    assert_eq!(
+        tag,
+        vec![
+            0x5b, 0xdc, 0xc1, 0x46, 0xbf, 0x60, 0x75, 0x4e, 0x6a, 0x04, 0x24, 0x26, 0x08, 0x95,
+            0x75, 0xc7, 0x5a, 0x00, 0x3f, 0x08, 0x9d, 0x27, 0x39, 0x83, 0x9d, 0xec, 0x58, 0xb9,
+            0x64, 0xec, 0x38, 0x43,
+        ]
+    );
+    assert!(ciphersuite.verify_mac(b"Jefe", b"what do ya want for nothing?", &tag));
+    assert!(!ciphersuite.verify_mac(b"Jefe", b"what do ya want for something?", &tag));
+    assert!(!ciphersuite.verify_mac(b"Jefe", b"what do ya want for nothing?", &tag[..16]));
+}
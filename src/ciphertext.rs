@@ -0,0 +1,299 @@
+//! Encrypted message framing
+
+use crate::ciphersuites::Ciphersuite;
+use crate::encode_util::*;
+use crate::framing::{
+    decode_mac, encode_mac, ContentType, MLSPlaintext, MLSPlaintextContent, Sender,
+};
+use crate::key_schedule::KeySchedule;
+use crate::secret_tree::{Error as SecretTreeError, RatchetType, SecretTree};
+use crate::traits::{Decode, DecodeError, Encode};
+use evercrypt::rand_util::get_random_vec;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    /// Only messages from members can be encrypted.
+    InvalidSender,
+    DecryptionFailed,
+    DecodingError(DecodeError),
+    SecretTreeError(SecretTreeError),
+}
+
+impl From<DecodeError> for Error {
+    fn from(e: DecodeError) -> Self {
+        Error::DecodingError(e)
+    }
+}
+
+impl From<SecretTreeError> for Error {
+    fn from(e: SecretTreeError) -> Self {
+        Error::SecretTreeError(e)
+    }
+}
+
+// Section 9 Message Framing
+// struct {
+//     opaque group_id<0..255>;
+//     uint64 epoch;
+//     ContentType content_type;
+//     opaque authenticated_data<0..2^32-1>;
+//     opaque encrypted_sender_data<0..255>;
+//     opaque ciphertext<0..2^32-1>;
+// } MLSCiphertext;
+#[derive(Debug, Clone)]
+pub struct MLSCiphertext {
+    pub(crate) group_id: Vec<u8>,
+    pub(crate) epoch: u64,
+    pub(crate) content_type: ContentType,
+    pub(crate) authenticated_data: Vec<u8>,
+    pub(crate) encrypted_sender_data: Vec<u8>,
+    pub(crate) ciphertext: Vec<u8>,
+}
+
+// Section 9.2 Sender Data Encryption
+// struct {
+//     uint32 sender;
+//     uint32 generation;
+//     opaque reuse_guard[4];
+// } MLSSenderData;
+struct MLSSenderData {
+    sender: u32,
+    generation: u32,
+    reuse_guard: Vec<u8>,
+}
+
+impl Encode for MLSSenderData {
+    fn encode(&self) -> Vec<u8> {
+        let mut out = self.sender.encode();
+        out.extend(self.generation.encode());
+        out.extend(&self.reuse_guard);
+        out
+    }
+}
+
+impl Decode for MLSSenderData {
+    fn decode(cursor: &mut Cursor) -> Result<Self, DecodeError> {
+        Ok(Self {
+            sender: u32::decode(cursor)?,
+            generation: u32::decode(cursor)?,
+            reuse_guard: cursor.read(4)?.to_vec(),
+        })
+    }
+}
+
+impl MLSCiphertext {
+    pub fn get_group_id(&self) -> &[u8] {
+        &self.group_id
+    }
+    pub fn get_epoch(&self) -> u64 {
+        self.epoch
+    }
+    pub fn get_content_type(&self) -> ContentType {
+        self.content_type
+    }
+
+    // struct {
+    //     opaque group_id<0..255>;
+    //     uint64 epoch;
+    //     ContentType content_type;
+    //     opaque authenticated_data<0..2^32-1>;
+    // } MLSCiphertextContentAAD;
+    fn content_aad(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        encode_slice(LenType::L8, &self.group_id, &mut out);
+        out.extend(self.epoch.encode());
+        out.push(self.content_type as u8);
+        encode_slice(LenType::L32, &self.authenticated_data, &mut out);
+        out
+    }
+
+    // struct {
+    //     opaque group_id<0..255>;
+    //     uint64 epoch;
+    //     ContentType content_type;
+    // } MLSSenderDataAAD;
+    fn sender_data_aad(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        encode_slice(LenType::L8, &self.group_id, &mut out);
+        out.extend(self.epoch.encode());
+        out.push(self.content_type as u8);
+        out
+    }
+
+    // ciphertext_sample = ciphertext[0..KDF.Nh-1]
+    // sender_data_key = ExpandWithLabel(sender_data_secret, "key", ciphertext_sample, AEAD.Nk)
+    // sender_data_nonce = ExpandWithLabel(sender_data_secret, "nonce", ciphertext_sample, AEAD.Nn)
+    fn sender_data_key_nonce(
+        &self,
+        ciphersuite: &Ciphersuite,
+        sender_data_secret: &[u8],
+    ) -> (Vec<u8>, Vec<u8>) {
+        let key_schedule = KeySchedule::new(ciphersuite);
+        let sample_length = ciphersuite.hash_length().min(self.ciphertext.len());
+        let sample = &self.ciphertext[..sample_length];
+        let key = key_schedule.expand_with_label(
+            sender_data_secret,
+            "key",
+            sample,
+            ciphersuite.aead_key_length(),
+        );
+        let nonce = key_schedule.expand_with_label(
+            sender_data_secret,
+            "nonce",
+            sample,
+            ciphersuite.aead_nonce_length(),
+        );
+        (key, nonce)
+    }
+
+    /// Encrypt a signed `plaintext` from a member.
+    /// Application messages use the application ratchet, everything else the
+    /// handshake ratchet of the sender.
+    pub(crate) fn encrypt(
+        plaintext: &MLSPlaintext,
+        ciphersuite: &Ciphersuite,
+        secret_tree: &mut SecretTree,
+        sender_data_secret: &[u8],
+        padding_length: usize,
+    ) -> Result<Self, Error> {
+        let sender = match plaintext.sender {
+            Sender::Member(leaf_index) => leaf_index,
+            _ => return Err(Error::InvalidSender),
+        };
+        let content_type = plaintext.content.get_type();
+        let (key, mut nonce, generation) =
+            secret_tree.next_sender_key(sender, ratchet_type(content_type))?;
+        let reuse_guard = get_random_vec(4);
+        xor_reuse_guard(&mut nonce, &reuse_guard);
+
+        // struct {
+        //     select (MLSCiphertext.content_type) { ... }
+        //     opaque signature<0..2^16-1>;
+        //     optional<MAC> confirmation_tag;
+        //     opaque padding<0..2^16-1>;
+        // } MLSCiphertextContent;
+        let mut content = Vec::new();
+        plaintext.content.encode_body(&mut content);
+        encode_slice(LenType::L16, &plaintext.signature, &mut content);
+        encode_mac(&plaintext.confirmation_tag, &mut content);
+        encode_slice(LenType::L16, &vec![0u8; padding_length], &mut content);
+
+        let mut ciphertext = Self {
+            group_id: plaintext.group_id.clone(),
+            epoch: plaintext.epoch,
+            content_type,
+            authenticated_data: plaintext.authenticated_data.clone(),
+            encrypted_sender_data: Vec::new(),
+            ciphertext: Vec::new(),
+        };
+        ciphertext.ciphertext =
+            ciphersuite.aead_seal(&key, &nonce, &ciphertext.content_aad(), &content);
+
+        let sender_data = MLSSenderData {
+            sender,
+            generation,
+            reuse_guard,
+        };
+        let (sender_data_key, sender_data_nonce) =
+            ciphertext.sender_data_key_nonce(ciphersuite, sender_data_secret);
+        ciphertext.encrypted_sender_data = ciphersuite.aead_seal(
+            &sender_data_key,
+            &sender_data_nonce,
+            &ciphertext.sender_data_aad(),
+            &sender_data.encode(),
+        );
+        Ok(ciphertext)
+    }
+
+    /// Decrypt the ciphertext. The signature of the resulting plaintext is
+    /// not checked.
+    pub(crate) fn decrypt(
+        &self,
+        ciphersuite: &Ciphersuite,
+        secret_tree: &mut SecretTree,
+        sender_data_secret: &[u8],
+    ) -> Result<MLSPlaintext, Error> {
+        let (sender_data_key, sender_data_nonce) =
+            self.sender_data_key_nonce(ciphersuite, sender_data_secret);
+        let sender_data = ciphersuite
+            .aead_open(
+                &sender_data_key,
+                &sender_data_nonce,
+                &self.sender_data_aad(),
+                &self.encrypted_sender_data,
+            )
+            .map_err(|_| Error::DecryptionFailed)?;
+        let sender_data = MLSSenderData::decode_all(&sender_data)?;
+
+        let ratchet_type = ratchet_type(self.content_type);
+        let (key, mut nonce) =
+            secret_tree.receiver_key(sender_data.sender, ratchet_type, sender_data.generation)?;
+        xor_reuse_guard(&mut nonce, &sender_data.reuse_guard);
+        let content = ciphersuite
+            .aead_open(&key, &nonce, &self.content_aad(), &self.ciphertext)
+            .map_err(|_| Error::DecryptionFailed)?;
+        // The key is only deleted once the content is authentic. Otherwise a
+        // corrupted message would burn its generation.
+        secret_tree.consume_receiver_key(
+            sender_data.sender,
+            ratchet_type,
+            sender_data.generation,
+        )?;
+
+        let mut cursor = Cursor::new(&content);
+        let mut plaintext = MLSPlaintext::new(
+            &self.group_id,
+            self.epoch,
+            Sender::Member(sender_data.sender),
+            &self.authenticated_data,
+            MLSPlaintextContent::decode_body(self.content_type, &mut cursor)?,
+        );
+        plaintext.signature = decode_slice(LenType::L16, &mut cursor)?;
+        plaintext.confirmation_tag = decode_mac(&mut cursor)?;
+        let _padding = decode_slice(LenType::L16, &mut cursor)?;
+        if !cursor.is_empty() {
+            return Err(Error::DecodingError(DecodeError::TrailingBytes));
+        }
+        Ok(plaintext)
+    }
+}
+
+fn ratchet_type(content_type: ContentType) -> RatchetType {
+    match content_type {
+        ContentType::Application => RatchetType::Application,
+        _ => RatchetType::Handshake,
+    }
+}
+
+/// XOR the first bytes of the nonce with the reuse guard.
+fn xor_reuse_guard(nonce: &mut [u8], reuse_guard: &[u8]) {
+    for (n, g) in nonce.iter_mut().zip(reuse_guard.iter()) {
+        *n ^= g;
+    }
+}
+
+impl Encode for MLSCiphertext {
+    fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        encode_slice(LenType::L8, &self.group_id, &mut out);
+        out.extend(self.epoch.encode());
+        out.push(self.content_type as u8);
+        encode_slice(LenType::L32, &self.authenticated_data, &mut out);
+        encode_slice(LenType::L8, &self.encrypted_sender_data, &mut out);
+        encode_slice(LenType::L32, &self.ciphertext, &mut out);
+        out
+    }
+}
+
+impl Decode for MLSCiphertext {
+    fn decode(cursor: &mut Cursor) -> Result<Self, DecodeError> {
+        Ok(Self {
+            group_id: decode_slice(LenType::L8, cursor)?,
+            epoch: u64::decode(cursor)?,
+            content_type: ContentType::decode(cursor)?,
+            authenticated_data: decode_slice(LenType::L32, cursor)?,
+            encrypted_sender_data: decode_slice(LenType::L8, cursor)?,
+            ciphertext: decode_slice(LenType::L32, cursor)?,
+        })
+    }
+}
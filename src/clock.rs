@@ -0,0 +1,33 @@
+//! Time source for validating lifetimes.
+//! Everything that checks a lifetime takes a `Clock` such that tests (or
+//! applications with their own notion of time) can provide the time.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub trait Clock: std::fmt::Debug {
+    /// The current time in seconds since the UNIX epoch.
+    fn now(&self) -> u64;
+}
+
+/// The system's wall clock.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("The system time is before the UNIX epoch.")
+            .as_secs()
+    }
+}
+
+/// A clock that always returns the same time.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FixedClock(pub u64);
+
+impl Clock for FixedClock {
+    fn now(&self) -> u64 {
+        self.0
+    }
+}
@@ -0,0 +1,190 @@
+use crate::commit::*;
+use crate::framing::MLSPlaintextContent;
+use crate::group_context::GroupContext;
+use crate::key_schedule::{EpochSecrets, KeySchedule};
+use evercrypt::rand_util::get_random_vec;
+
+/// Everything needed from the current epoch to create a commit.
+pub struct CommitParams<'t, 'a> {
+    pub tree: &'t Tree,
+    pub group_context: &'a GroupContext,
+    pub epoch_secrets: &'a EpochSecrets,
+    pub interim_transcript_hash: &'a [u8],
+    /// Our own leaf index.
+    pub own_leaf: u32,
+    pub proposal_store: &'a ProposalStore,
+    /// Proposals from the store to commit by reference.
+    pub proposal_refs: &'a [ProposalRef],
+    /// Proposals to commit inline.
+    pub proposals: &'a [Proposal],
+    pub authenticated_data: &'a [u8],
+    /// Where the secrets of committed PSKs are looked up.
+    pub psk_store: &'a dyn PskStore,
+    /// Key packages are validated at the current time of the clock.
+    pub clock: &'a dyn Clock,
+}
+
+/// The commit and the state of the next epoch.
+/// Nothing in the current epoch's state is changed by creating a commit.
+/// The new tree is a diff on top of the current one.
+#[derive(Debug)]
+pub struct CreateCommitResult<'a> {
+    pub(crate) plaintext: MLSPlaintext,
+    pub(crate) tree: TreeDiff<'a>,
+    pub(crate) group_context: GroupContext,
+    pub(crate) epoch_secrets: EpochSecrets,
+    pub(crate) interim_transcript_hash: Vec<u8>,
+    pub(crate) joiner_secret: Vec<u8>,
+    pub(crate) own_leaf: u32,
+    /// The path secrets of our direct path if the commit has a path.
+    pub(crate) path_secrets: Option<Vec<Vec<u8>>>,
+    /// The committed proposals with their senders.
+    pub(crate) proposals: Vec<(Sender, Proposal)>,
+    /// New members as `(leaf_index, key_package)`.
+    pub(crate) added: Vec<(u32, KeyPackage)>,
+    pub(crate) psks: Vec<PreSharedKeyId>,
+}
+
+impl<'a> CreateCommitResult<'a> {
+    pub fn get_plaintext(&self) -> &MLSPlaintext {
+        &self.plaintext
+    }
+    pub fn get_tree(&self) -> &TreeDiff<'a> {
+        &self.tree
+    }
+    pub fn get_group_context(&self) -> &GroupContext {
+        &self.group_context
+    }
+}
+
+// Section 11.2 Commit
+/// Create a commit covering the given proposals.
+/// A path is generated if required or if `force_path` is set.
+pub fn create_commit<'t>(
+    params: CommitParams<'t, '_>,
+    force_path: bool,
+) -> Result<CreateCommitResult<'t>, Error> {
+    let tree = params.tree;
+    let ciphersuite = tree.get_ciphersuite();
+    let key_schedule = KeySchedule::new(ciphersuite);
+    let committer = Sender::Member(params.own_leaf);
+    let credential = tree
+        .get_key_package(params.own_leaf)
+        .ok_or(TreeError::BlankLeaf)?
+        .get_credential()
+        .clone();
+
+    // Gather all proposals.
+    let mut proposals_or_refs: Vec<ProposalOrRef> = params
+        .proposal_refs
+        .iter()
+        .map(|r| ProposalOrRef::Reference(r.clone()))
+        .collect();
+    proposals_or_refs.extend(
+        params
+            .proposals
+            .iter()
+            .map(|p| ProposalOrRef::Proposal(p.clone())),
+    );
+    let proposals = resolve_proposals(&proposals_or_refs, committer, params.proposal_store)?;
+    check_proposals(
+        tree,
+        &params.group_context.extensions,
+        params.own_leaf,
+        &proposals,
+        params.clock,
+    )?;
+
+    // Apply them to a diff of the tree.
+    let mut new_tree = tree.diff();
+    let mut extensions = params.group_context.extensions.clone();
+    let applied = apply_proposals(&mut new_tree, &mut extensions, &proposals)?;
+    let new_members: Vec<u32> = applied.added.iter().map(|(i, _)| *i).collect();
+
+    let own_path = if applied.path_required || force_path {
+        let leaf_secret = get_random_vec(ciphersuite.hash_length());
+        Some(new_tree.apply_own_path(params.own_leaf, &leaf_secret)?)
+    } else {
+        None
+    };
+    let tree_hash = new_tree.tree_hash()?;
+
+    // The path secrets are encrypted under the provisional group context.
+    let epoch = params.group_context.epoch + 1;
+    let provisional_group_context = GroupContext::new(
+        &params.group_context.group_id,
+        epoch,
+        &tree_hash,
+        &params.group_context.confirmed_transcript_hash,
+        &extensions,
+    );
+    let path = match &own_path {
+        Some(own_path) => Some(new_tree.encrypt_path_secrets(
+            params.own_leaf,
+            own_path,
+            &provisional_group_context.encode(),
+            &new_members,
+        )?),
+        None => None,
+    };
+
+    let commit = Commit {
+        proposals: proposals_or_refs,
+        path,
+    };
+    let mut plaintext = MLSPlaintext::new(
+        &params.group_context.group_id,
+        params.group_context.epoch,
+        committer,
+        params.authenticated_data,
+        MLSPlaintextContent::Commit(commit),
+    );
+    plaintext.sign(&credential, params.group_context)?;
+
+    // Derive the next epoch.
+    let confirmed_transcript_hash =
+        confirmed_transcript_hash(ciphersuite, params.interim_transcript_hash, &plaintext);
+    let group_context = GroupContext::new(
+        &params.group_context.group_id,
+        epoch,
+        &tree_hash,
+        &confirmed_transcript_hash,
+        &extensions,
+    );
+    let commit_secret = match &own_path {
+        Some(own_path) => own_path.commit_secret.clone(),
+        None => key_schedule.zero_secret(),
+    };
+    let joiner_secret =
+        key_schedule.joiner_secret(&params.epoch_secrets.init_secret, &commit_secret);
+    let psk_secret = psk_secret(ciphersuite, &applied.psks, params.psk_store)?;
+    let epoch_secrets =
+        key_schedule.epoch_secrets(&joiner_secret, psk_secret.as_deref(), &group_context);
+
+    plaintext.confirmation_tag = Some(confirmation_tag(
+        ciphersuite,
+        &epoch_secrets.confirmation_key,
+        &confirmed_transcript_hash,
+    ));
+    let interim_transcript_hash =
+        interim_transcript_hash(ciphersuite, &confirmed_transcript_hash, &plaintext);
+    plaintext.set_membership_tag(
+        ciphersuite,
+        &params.epoch_secrets.membership_key,
+        params.group_context,
+    );
+
+    Ok(CreateCommitResult {
+        plaintext,
+        tree: new_tree,
+        group_context,
+        epoch_secrets,
+        interim_transcript_hash,
+        joiner_secret,
+        own_leaf: params.own_leaf,
+        path_secrets: own_path.map(|p| p.path_secrets),
+        proposals,
+        added: applied.added,
+        psks: applied.psks,
+    })
+}
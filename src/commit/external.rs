@@ -0,0 +1,204 @@
+use crate::commit::*;
+use crate::extensions::{required_capabilities, ExtensionType};
+use crate::framing::MLSPlaintextContent;
+use crate::group_context::GroupContext;
+use crate::key_schedule::{EpochSecrets, KeySchedule};
+use crate::proposal::{ExternalInitProposal, RemoveProposal};
+use crate::welcome::GroupInfo;
+use evercrypt::rand_util::get_random_vec;
+
+/// Everything a non-member needs to create an external commit.
+pub struct ExternalCommitParams<'a> {
+    /// The published GroupInfo of the current epoch.
+    pub group_info: &'a GroupInfo,
+    /// The tree if it's not in the GroupInfo.
+    pub ratchet_tree: Option<&'a Tree>,
+    /// Our new key package with the private init key.
+    pub key_package: KeyPackage,
+    /// Remove our old leaf, the leaf with the same identity as
+    /// `key_package`, if there is one.
+    pub resync: bool,
+    pub authenticated_data: &'a [u8],
+    /// Key packages are validated at the current time of the clock.
+    pub clock: &'a dyn Clock,
+}
+
+/// The external commit and the state of the epoch it leads to.
+#[derive(Debug)]
+pub struct CreateExternalCommitResult {
+    pub(crate) plaintext: MLSPlaintext,
+    pub(crate) tree: Tree,
+    pub(crate) group_context: GroupContext,
+    pub(crate) epoch_secrets: EpochSecrets,
+    pub(crate) interim_transcript_hash: Vec<u8>,
+    pub(crate) own_leaf: u32,
+}
+
+impl CreateExternalCommitResult {
+    pub fn get_plaintext(&self) -> &MLSPlaintext {
+        &self.plaintext
+    }
+    pub fn get_tree(&self) -> &Tree {
+        &self.tree
+    }
+    pub fn get_group_context(&self) -> &GroupContext {
+        &self.group_context
+    }
+    pub fn get_own_leaf(&self) -> u32 {
+        self.own_leaf
+    }
+}
+
+/// Check the proposals of an external commit by the owner of `key_package`
+/// to a group with the group context `extensions`.
+/// There must be exactly one ExternalInit. Besides PSKs only the old leaf of
+/// the committer may be removed. Like any new member the committer must
+/// have a valid key package at the current time of `clock` and support the
+/// required capabilities of the group.
+/// Returns the `kem_output` of the ExternalInit.
+pub(crate) fn check_external_proposals<S: NodeStore>(
+    tree: &Tree<S>,
+    extensions: &[Extension],
+    key_package: &KeyPackage,
+    proposals: &[(Sender, Proposal)],
+    clock: &dyn Clock,
+) -> Result<Vec<u8>, Error> {
+    key_package.validate(clock).map_err(Error::InvalidKeyPackage)?;
+    if matches!(required_capabilities(extensions), Some(r) if !key_package.supports(r)) {
+        return Err(Error::InvalidExternalCommit);
+    }
+    let identity = key_package.get_credential().get_identity();
+    let mut kem_output = None;
+    for (_, proposal) in proposals.iter() {
+        match proposal {
+            Proposal::ExternalInit(p) if kem_output.is_none() => {
+                kem_output = Some(p.get_kem_output().to_vec())
+            }
+            Proposal::Remove(p) => match tree.get_key_package(p.get_removed()) {
+                Some(kp) if kp.get_credential().get_identity() == identity => (),
+                _ => return Err(Error::InvalidExternalCommit),
+            },
+            Proposal::PreSharedKey(_) => (),
+            _ => return Err(Error::InvalidExternalCommit),
+        }
+    }
+    kem_output.ok_or(Error::InvalidExternalCommit)
+}
+
+// Section 11.2.1 External Initialization
+/// Join a group with an external commit, without a Welcome.
+/// The `init_secret` of the new epoch is exported from an HPKE context to
+/// the group's `external_pub`. We add ourselves to the tree and send an
+/// UpdatePath from our new leaf.
+pub fn create_external_commit(
+    params: ExternalCommitParams,
+) -> Result<CreateExternalCommitResult, Error> {
+    let group_info = params.group_info;
+    let ciphersuite = params.key_package.get_cipher_suite().clone();
+    let key_schedule = KeySchedule::new(&ciphersuite);
+    let mut tree = group_info.verified_tree(&ciphersuite, params.ratchet_tree, params.clock)?;
+    let group_context = group_info.group_context();
+    let external_pub = match group_info
+        .get_other_extensions()
+        .iter()
+        .find(|e| e.get_type() == ExtensionType::ExternalPub)
+    {
+        Some(Extension::ExternalPub(e)) => e.get_external_pub().to_vec(),
+        _ => return Err(Error::MissingExternalPub),
+    };
+    let (kem_output, init_secret) = key_schedule.external_init(&external_pub);
+
+    // The proposals are all sent inline.
+    let mut proposals = vec![Proposal::ExternalInit(ExternalInitProposal::new(&kem_output))];
+    if params.resync {
+        let identity = params.key_package.get_credential().get_identity();
+        let old_leaf = tree
+            .members()
+            .find(|(_, kp)| kp.get_credential().get_identity() == identity)
+            .map(|(i, _)| i);
+        if let Some(old_leaf) = old_leaf {
+            proposals.push(Proposal::Remove(RemoveProposal::new(old_leaf)));
+        }
+    }
+    let proposals: Vec<(Sender, Proposal)> = proposals
+        .into_iter()
+        .map(|p| (Sender::NewMember, p))
+        .collect();
+    check_external_proposals(
+        &tree,
+        &group_context.extensions,
+        &params.key_package,
+        &proposals,
+        params.clock,
+    )?;
+    let mut extensions = group_context.extensions.clone();
+    apply_proposals(&mut tree, &mut extensions, &proposals)?;
+
+    // Add ourselves and generate a path.
+    let credential = params.key_package.get_credential().clone();
+    let own_leaf = tree.add_member(params.key_package)?;
+    let leaf_secret = get_random_vec(ciphersuite.hash_length());
+    let own_path = tree.apply_own_path(own_leaf, &leaf_secret)?;
+    let tree_hash = tree.tree_hash()?;
+    let epoch = group_context.epoch + 1;
+    let provisional_group_context = GroupContext::new(
+        &group_context.group_id,
+        epoch,
+        &tree_hash,
+        &group_context.confirmed_transcript_hash,
+        &extensions,
+    );
+    let path =
+        tree.encrypt_path_secrets(own_leaf, &own_path, &provisional_group_context.encode(), &[])?;
+
+    let commit = Commit {
+        proposals: proposals
+            .into_iter()
+            .map(|(_, p)| ProposalOrRef::Proposal(p))
+            .collect(),
+        path: Some(path),
+    };
+    let mut plaintext = MLSPlaintext::new(
+        &group_context.group_id,
+        group_context.epoch,
+        Sender::NewMember,
+        params.authenticated_data,
+        MLSPlaintextContent::Commit(commit),
+    );
+    plaintext.sign(&credential, &group_context)?;
+
+    // Derive the next epoch. We only know the interim transcript hash of the
+    // current epoch from the GroupInfo.
+    let current_interim_transcript_hash = interim_transcript_hash_from_tag(
+        &ciphersuite,
+        &group_context.confirmed_transcript_hash,
+        &group_info.confirmation_tag,
+    );
+    let confirmed_transcript_hash =
+        confirmed_transcript_hash(&ciphersuite, &current_interim_transcript_hash, &plaintext);
+    let new_group_context = GroupContext::new(
+        &group_context.group_id,
+        epoch,
+        &tree_hash,
+        &confirmed_transcript_hash,
+        &extensions,
+    );
+    let joiner_secret = key_schedule.joiner_secret(&init_secret, &own_path.commit_secret);
+    let epoch_secrets = key_schedule.epoch_secrets(&joiner_secret, None, &new_group_context);
+    plaintext.confirmation_tag = Some(confirmation_tag(
+        &ciphersuite,
+        &epoch_secrets.confirmation_key,
+        &confirmed_transcript_hash,
+    ));
+    let interim_transcript_hash =
+        interim_transcript_hash(&ciphersuite, &confirmed_transcript_hash, &plaintext);
+
+    Ok(CreateExternalCommitResult {
+        plaintext,
+        tree,
+        group_context: new_group_context,
+        epoch_secrets,
+        interim_transcript_hash,
+        own_leaf,
+    })
+}
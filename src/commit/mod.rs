@@ -0,0 +1,327 @@
+//! Commits
+//! Creating commits is in `create`, processing commits from other members
+//! in `process`. Non-members join with external commits from `external`.
+
+use crate::ciphersuites::Ciphersuite;
+use crate::clock::Clock;
+use crate::credentials::Error as CredentialError;
+use crate::encode_util::*;
+use crate::extensions::Extension;
+use crate::framing::{encode_mac, MLSPlaintext, Sender};
+use crate::key_package::KeyPackage;
+use crate::key_package::{Error as KeyPackageError, ProtocolVersion};
+use crate::proposal::{validate_proposals, InvalidProposal, Proposal, ValidationContext};
+use crate::proposal_store::{ProposalRef, ProposalStore};
+use crate::psk::{psk_secret, Error as PskError, PreSharedKeyId, PskStore};
+use crate::traits::{Decode, DecodeError, Encode};
+use crate::tree::{Error as TreeError, NodeStore, Tree, TreeDiff, UpdatePath};
+use crate::welcome::Error as WelcomeError;
+use evercrypt::digest::hash;
+
+mod create;
+pub use create::*;
+
+mod process;
+pub use process::*;
+
+mod external;
+pub use external::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    UnknownProposal,
+    InvalidProposals(Vec<InvalidProposal>),
+    InvalidProposalSender,
+    WrongEpoch,
+    NotACommit,
+    InvalidSender,
+    InvalidSignature,
+    InvalidMembershipTag,
+    InvalidConfirmationTag,
+    MissingPath,
+    OwnLeafRemoved,
+    TreeError(TreeError),
+    PskError(PskError),
+    /// The GroupInfo for an external commit is invalid.
+    GroupInfoError(WelcomeError),
+    /// The GroupInfo has no `external_pub` extension.
+    MissingExternalPub,
+    /// An external commit must have exactly one ExternalInit and may only
+    /// remove the committer's old leaf.
+    InvalidExternalCommit,
+    ExternalInitFailed,
+    CredentialError(CredentialError),
+    /// The key package of the joiner of an external commit is invalid.
+    InvalidKeyPackage(KeyPackageError),
+}
+
+impl From<TreeError> for Error {
+    fn from(e: TreeError) -> Self {
+        Error::TreeError(e)
+    }
+}
+
+impl From<PskError> for Error {
+    fn from(e: PskError) -> Self {
+        Error::PskError(e)
+    }
+}
+
+impl From<WelcomeError> for Error {
+    fn from(e: WelcomeError) -> Self {
+        Error::GroupInfoError(e)
+    }
+}
+
+impl From<CredentialError> for Error {
+    fn from(e: CredentialError) -> Self {
+        Error::CredentialError(e)
+    }
+}
+
+// Section 11.2 Commit
+// enum {
+//   reserved(0),
+//   proposal(1)
+//   reference(2),
+//   (255)
+// } ProposalOrRefType;
+//
+// struct {
+//   ProposalOrRefType type;
+//   select (ProposalOrRef.type) {
+//     case proposal:  Proposal proposal;
+//     case reference: ProposalRef reference;
+//   }
+// } ProposalOrRef;
+const PROPOSAL: u8 = 1;
+const REFERENCE: u8 = 2;
+
+#[derive(Debug, Clone)]
+pub enum ProposalOrRef {
+    Proposal(Proposal),
+    Reference(ProposalRef),
+}
+
+impl Encode for ProposalOrRef {
+    fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        match self {
+            ProposalOrRef::Proposal(proposal) => {
+                out.push(PROPOSAL);
+                out.extend(proposal.encode());
+            }
+            ProposalOrRef::Reference(reference) => {
+                out.push(REFERENCE);
+                out.extend(reference.encode());
+            }
+        }
+        out
+    }
+}
+
+impl Decode for ProposalOrRef {
+    fn decode(cursor: &mut Cursor) -> Result<Self, DecodeError> {
+        match u8::decode(cursor)? {
+            PROPOSAL => Ok(ProposalOrRef::Proposal(Proposal::decode(cursor)?)),
+            REFERENCE => Ok(ProposalOrRef::Reference(ProposalRef::decode(cursor)?)),
+            _ => Err(DecodeError::InvalidValue),
+        }
+    }
+}
+
+// struct {
+//     ProposalOrRef proposals<0..2^32-1>;
+//     optional<UpdatePath> path;
+// } Commit;
+#[derive(Debug, Clone)]
+pub struct Commit {
+    pub(crate) proposals: Vec<ProposalOrRef>,
+    pub(crate) path: Option<UpdatePath>,
+}
+
+impl Commit {
+    pub fn get_proposals(&self) -> &[ProposalOrRef] {
+        &self.proposals
+    }
+    pub fn get_path(&self) -> Option<&UpdatePath> {
+        self.path.as_ref()
+    }
+}
+
+impl Encode for Commit {
+    fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        encode_vec(LenType::L32, &self.proposals, &mut out);
+        out.extend(self.path.encode());
+        out
+    }
+}
+
+impl Decode for Commit {
+    fn decode(cursor: &mut Cursor) -> Result<Self, DecodeError> {
+        Ok(Self {
+            proposals: decode_vec(LenType::L32, cursor)?,
+            path: Option::<UpdatePath>::decode(cursor)?,
+        })
+    }
+}
+
+/// Look up the proposals of a commit, inline or by reference.
+/// Inline proposals are sent by the `committer`.
+pub(crate) fn resolve_proposals(
+    proposals: &[ProposalOrRef],
+    committer: Sender,
+    proposal_store: &ProposalStore,
+) -> Result<Vec<(Sender, Proposal)>, Error> {
+    proposals
+        .iter()
+        .map(|p| match p {
+            ProposalOrRef::Proposal(proposal) => Ok((committer, proposal.clone())),
+            ProposalOrRef::Reference(reference) => match proposal_store.get(reference) {
+                Some(queued) => Ok((queued.get_sender(), queued.get_proposal().clone())),
+                None => Err(Error::UnknownProposal),
+            },
+        })
+        .collect()
+}
+
+/// Validate the `proposals` committed by the member at `committer` in a
+/// group with the group context `extensions` at the current time of `clock`.
+pub(crate) fn check_proposals(
+    tree: &Tree,
+    extensions: &[Extension],
+    committer: u32,
+    proposals: &[(Sender, Proposal)],
+    clock: &dyn Clock,
+) -> Result<(), Error> {
+    let context = ValidationContext {
+        tree,
+        ciphersuite: tree.get_ciphersuite(),
+        version: ProtocolVersion::Mls10,
+        committer,
+        extensions,
+        clock,
+    };
+    validate_proposals(&context, proposals).map_err(Error::InvalidProposals)
+}
+
+/// What applying a list of proposals changed.
+pub(crate) struct AppliedProposals {
+    /// New members as `(leaf_index, key_package)`.
+    pub(crate) added: Vec<(u32, KeyPackage)>,
+    pub(crate) psks: Vec<PreSharedKeyId>,
+    pub(crate) path_required: bool,
+}
+
+// Section 11.2 Commit
+// The proposals are applied in the order GroupContextExtensions, Update,
+// Remove, Add, PreSharedKey.
+/// Apply `proposals` to `tree` and the group context `extensions`.
+pub(crate) fn apply_proposals<S: NodeStore>(
+    tree: &mut Tree<S>,
+    extensions: &mut Vec<Extension>,
+    proposals: &[(Sender, Proposal)],
+) -> Result<AppliedProposals, Error> {
+    for (_, proposal) in proposals.iter() {
+        if let Proposal::GroupContextExtensions(p) = proposal {
+            *extensions = p.get_extensions().to_vec();
+        }
+    }
+    for (sender, proposal) in proposals.iter() {
+        if let Proposal::Update(p) = proposal {
+            let leaf_index = match sender {
+                Sender::Member(leaf_index) => *leaf_index,
+                _ => return Err(Error::InvalidProposalSender),
+            };
+            tree.update_member(leaf_index, p.get_key_package().clone())?;
+        }
+    }
+    for (_, proposal) in proposals.iter() {
+        if let Proposal::Remove(p) = proposal {
+            tree.remove_member(p.get_removed())?;
+        }
+    }
+    let mut added = Vec::new();
+    for (_, proposal) in proposals.iter() {
+        if let Proposal::Add(p) = proposal {
+            let key_package = p.get_key_package().clone();
+            added.push((tree.add_member(key_package.clone())?, key_package));
+        }
+    }
+    let psks = proposals
+        .iter()
+        .filter_map(|(_, proposal)| match proposal {
+            Proposal::PreSharedKey(p) => Some(p.get_psk().clone()),
+            _ => None,
+        })
+        .collect();
+
+    // A path is required for an empty commit and if there's an Update or Remove.
+    let path_required = proposals.is_empty()
+        || proposals
+            .iter()
+            .any(|(_, p)| matches!(p, Proposal::Update(_) | Proposal::Remove(_)));
+
+    Ok(AppliedProposals {
+        added,
+        psks,
+        path_required,
+    })
+}
+
+// Section 8.2 Transcript Hashes
+// confirmed_transcript_hash_[n] =
+//     Hash(interim_transcript_hash_[n-1] || MLSPlaintextCommitContent_[n]);
+pub(crate) fn confirmed_transcript_hash(
+    ciphersuite: &Ciphersuite,
+    interim_transcript_hash: &[u8],
+    commit: &MLSPlaintext,
+) -> Vec<u8> {
+    let mut input = interim_transcript_hash.to_vec();
+    input.extend(commit.encode_commit_content());
+    hash(ciphersuite.hash, &input)
+}
+
+// interim_transcript_hash_[n] =
+//     Hash(confirmed_transcript_hash_[n] || MLSPlaintextCommitAuthData_[n]);
+pub(crate) fn interim_transcript_hash(
+    ciphersuite: &Ciphersuite,
+    confirmed_transcript_hash: &[u8],
+    commit: &MLSPlaintext,
+) -> Vec<u8> {
+    let mut input = confirmed_transcript_hash.to_vec();
+    input.extend(commit.encode_commit_auth_data());
+    hash(ciphersuite.hash, &input)
+}
+
+/// The interim transcript hash of an epoch from the confirmation tag of the
+/// commit that started it, e.g. from a GroupInfo.
+pub(crate) fn interim_transcript_hash_from_tag(
+    ciphersuite: &Ciphersuite,
+    confirmed_transcript_hash: &[u8],
+    confirmation_tag: &[u8],
+) -> Vec<u8> {
+    let mut input = confirmed_transcript_hash.to_vec();
+    encode_mac(&Some(confirmation_tag.to_vec()), &mut input);
+    hash(ciphersuite.hash, &input)
+}
+
+// confirmation_tag = MAC(confirmation_key, confirmed_transcript_hash)
+pub(crate) fn confirmation_tag(
+    ciphersuite: &Ciphersuite,
+    confirmation_key: &[u8],
+    confirmed_transcript_hash: &[u8],
+) -> Vec<u8> {
+    ciphersuite.mac(confirmation_key, confirmed_transcript_hash)
+}
+
+/// Check a `confirmation_tag` in constant time.
+pub(crate) fn verify_confirmation_tag(
+    ciphersuite: &Ciphersuite,
+    confirmation_key: &[u8],
+    confirmed_transcript_hash: &[u8],
+    confirmation_tag: &[u8],
+) -> bool {
+    ciphersuite.verify_mac(confirmation_key, confirmed_transcript_hash, confirmation_tag)
+}
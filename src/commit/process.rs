@@ -0,0 +1,226 @@
+use crate::commit::*;
+use crate::framing::MLSPlaintextContent;
+use crate::group_context::GroupContext;
+use crate::key_schedule::{EpochSecrets, KeySchedule};
+
+/// Everything needed from the current epoch to process a commit.
+pub struct ProcessCommitParams<'t, 'a> {
+    pub tree: &'t Tree,
+    pub group_context: &'a GroupContext,
+    pub epoch_secrets: &'a EpochSecrets,
+    pub interim_transcript_hash: &'a [u8],
+    /// Our own leaf index.
+    pub own_leaf: u32,
+    pub proposal_store: &'a ProposalStore,
+    /// Where the secrets of committed PSKs are looked up.
+    pub psk_store: &'a dyn PskStore,
+    /// Key packages are validated at the current time of the clock.
+    pub clock: &'a dyn Clock,
+}
+
+/// The state of the next epoch after processing a commit.
+/// The new tree is a diff on top of the current one.
+#[derive(Debug)]
+pub struct ProcessCommitResult<'a> {
+    pub(crate) tree: TreeDiff<'a>,
+    pub(crate) group_context: GroupContext,
+    pub(crate) epoch_secrets: EpochSecrets,
+    pub(crate) interim_transcript_hash: Vec<u8>,
+    /// The leaf of the committer. For external commits this is the new leaf
+    /// of the joiner.
+    pub(crate) committer: u32,
+    /// The committed proposals with their senders.
+    pub(crate) proposals: Vec<(Sender, Proposal)>,
+    /// New members as `(leaf_index, key_package)`.
+    pub(crate) added: Vec<(u32, KeyPackage)>,
+    /// The PSKs injected into the key schedule.
+    pub(crate) psks: Vec<PreSharedKeyId>,
+}
+
+impl<'a> ProcessCommitResult<'a> {
+    pub fn get_tree(&self) -> &TreeDiff<'a> {
+        &self.tree
+    }
+    pub fn get_group_context(&self) -> &GroupContext {
+        &self.group_context
+    }
+    pub fn get_committer(&self) -> u32 {
+        self.committer
+    }
+    pub fn get_proposals(&self) -> &[(Sender, Proposal)] {
+        &self.proposals
+    }
+    pub fn get_psks(&self) -> &[PreSharedKeyId] {
+        &self.psks
+    }
+}
+
+// Section 11.2 Commit
+/// Process a commit `plaintext` from another member or an external commit
+/// from a new member.
+/// All changes are made on copies of the current state and a diff of the
+/// tree. If any check fails the error is returned and the current epoch is
+/// unchanged.
+pub fn process_commit<'t>(
+    params: ProcessCommitParams<'t, '_>,
+    plaintext: &MLSPlaintext,
+) -> Result<ProcessCommitResult<'t>, Error> {
+    let tree = params.tree;
+    let ciphersuite = tree.get_ciphersuite();
+    let key_schedule = KeySchedule::new(ciphersuite);
+
+    if plaintext.get_group_id() != params.group_context.get_group_id()
+        || plaintext.get_epoch() != params.group_context.get_epoch()
+    {
+        return Err(Error::WrongEpoch);
+    }
+    let commit = match plaintext.get_content() {
+        MLSPlaintextContent::Commit(commit) => commit,
+        _ => return Err(Error::NotACommit),
+    };
+    let sender = plaintext.get_sender();
+
+    // Authenticate the commit. New members can't have a membership tag and
+    // sign with the key package in their path.
+    let credential = match sender {
+        Sender::Member(leaf_index) if leaf_index != params.own_leaf => {
+            if !plaintext.verify_membership_tag(
+                ciphersuite,
+                &params.epoch_secrets.membership_key,
+                params.group_context,
+            ) {
+                return Err(Error::InvalidMembershipTag);
+            }
+            tree.get_key_package(leaf_index)
+                .ok_or(Error::InvalidSender)?
+                .get_credential()
+        }
+        Sender::NewMember => commit
+            .path
+            .as_ref()
+            .ok_or(Error::MissingPath)?
+            .get_leaf_key_package()
+            .get_credential(),
+        _ => return Err(Error::InvalidSender),
+    };
+    if !plaintext.verify_signature(credential, params.group_context) {
+        return Err(Error::InvalidSignature);
+    }
+
+    // Apply the proposals to a diff of the tree.
+    let proposals = resolve_proposals(&commit.proposals, sender, params.proposal_store)?;
+    let mut new_tree = tree.diff();
+    let mut extensions = params.group_context.extensions.clone();
+    let (sender_leaf, init_secret, applied) = match (sender, &commit.path) {
+        (Sender::Member(leaf_index), _) => {
+            check_proposals(tree, &extensions, leaf_index, &proposals, params.clock)?;
+            let applied = apply_proposals(&mut new_tree, &mut extensions, &proposals)?;
+            (leaf_index, params.epoch_secrets.init_secret.clone(), applied)
+        }
+        // The joiner is added before its path is applied.
+        (_, Some(path)) => {
+            if commit
+                .proposals
+                .iter()
+                .any(|p| matches!(p, ProposalOrRef::Reference(_)))
+            {
+                return Err(Error::InvalidExternalCommit);
+            }
+            let key_package = path.get_leaf_key_package();
+            let kem_output = check_external_proposals(
+                tree,
+                &extensions,
+                key_package,
+                &proposals,
+                params.clock,
+            )?;
+            let init_secret = key_schedule
+                .external_init_secret(&params.epoch_secrets.external_secret, &kem_output)
+                .map_err(|_| Error::ExternalInitFailed)?;
+            let mut applied = apply_proposals(&mut new_tree, &mut extensions, &proposals)?;
+            let leaf_index = new_tree.add_member(key_package.clone())?;
+            applied.added.push((leaf_index, key_package.clone()));
+            (leaf_index, init_secret, applied)
+        }
+        (_, None) => return Err(Error::MissingPath),
+    };
+    let new_members: Vec<u32> = applied.added.iter().map(|(i, _)| *i).collect();
+
+    // Apply the path.
+    match &commit.path {
+        Some(path) => new_tree.apply_update_path(sender_leaf, path)?,
+        None if applied.path_required => return Err(Error::MissingPath),
+        None => (),
+    }
+    // A removed member can't decrypt the path, so it can't derive the new
+    // epoch and check the confirmation tag. The commit is authenticated up to
+    // here, including the confirmation tag if there's a membership tag.
+    // Our leaf may already be taken by a new member, e.g. by the joiner of a
+    // resync.
+    if proposals
+        .iter()
+        .any(|(_, p)| matches!(p, Proposal::Remove(r) if r.get_removed() == params.own_leaf))
+    {
+        return Err(Error::OwnLeafRemoved);
+    }
+    let epoch = params.group_context.epoch + 1;
+    let commit_secret = match &commit.path {
+        Some(path) => {
+            let provisional_group_context = GroupContext::new(
+                &params.group_context.group_id,
+                epoch,
+                &new_tree.tree_hash()?,
+                &params.group_context.confirmed_transcript_hash,
+                &extensions,
+            );
+            new_tree.decrypt_update_path(
+                sender_leaf,
+                params.own_leaf,
+                path,
+                &provisional_group_context.encode(),
+                &new_members,
+            )?
+        }
+        None => key_schedule.zero_secret(),
+    };
+
+    // Derive the next epoch and check that the committer got the same.
+    let confirmed_transcript_hash =
+        confirmed_transcript_hash(ciphersuite, params.interim_transcript_hash, plaintext);
+    let group_context = GroupContext::new(
+        &params.group_context.group_id,
+        epoch,
+        &new_tree.tree_hash()?,
+        &confirmed_transcript_hash,
+        &extensions,
+    );
+    let joiner_secret = key_schedule.joiner_secret(&init_secret, &commit_secret);
+    let psk_secret = psk_secret(ciphersuite, &applied.psks, params.psk_store)?;
+    let epoch_secrets =
+        key_schedule.epoch_secrets(&joiner_secret, psk_secret.as_deref(), &group_context);
+    let valid_confirmation_tag = match plaintext.get_confirmation_tag() {
+        Some(tag) => verify_confirmation_tag(
+            ciphersuite,
+            &epoch_secrets.confirmation_key,
+            &confirmed_transcript_hash,
+            tag,
+        ),
+        None => false,
+    };
+    if !valid_confirmation_tag {
+        return Err(Error::InvalidConfirmationTag);
+    }
+    let interim_transcript_hash =
+        interim_transcript_hash(ciphersuite, &confirmed_transcript_hash, plaintext);
+
+    Ok(ProcessCommitResult {
+        tree: new_tree,
+        group_context,
+        epoch_secrets,
+        interim_transcript_hash,
+        committer: sender_leaf,
+        proposals,
+        added: applied.added,
+        psks: applied.psks,
+    })
+}
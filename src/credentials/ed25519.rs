@@ -1,5 +1,6 @@
 use crate::credentials::*;
 use evercrypt::prelude::ed25519;
+use std::convert::TryInto;
 
 impl BasicCredential {
     pub fn ed25519() -> Self {
@@ -12,3 +13,22 @@ impl BasicCredential {
         }
     }
 }
+
+pub(crate) fn sign(private_key: &[u8], data: &[u8]) -> Result<Vec<u8>, Error> {
+    let sk: [u8; 32] = private_key
+        .try_into()
+        .map_err(|_| Error::InvalidPrivateKey)?;
+    Ok(ed25519::eddsa_sign(&sk, data).to_vec())
+}
+
+pub(crate) fn verify(public_key: &[u8], data: &[u8], signature: &[u8]) -> bool {
+    let pk: [u8; 32] = match public_key.try_into() {
+        Ok(pk) => pk,
+        Err(_) => return false,
+    };
+    let signature: [u8; 64] = match signature.try_into() {
+        Ok(s) => s,
+        Err(_) => return false,
+    };
+    ed25519::eddsa_verify(&pk, &signature, data)
+}
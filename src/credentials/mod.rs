@@ -1,18 +1,53 @@
-use crate::{encode_util::*, traits::Encode};
+use crate::{
+    encode_util::*,
+    traits::{Decode, DecodeError, Encode},
+};
+use std::rc::Rc;
 
 pub mod ed25519;
 
-type CredentialType = u16;
+pub type CredentialType = u16;
+
+// Section 7.1
+// enum {
+//     reserved(0),
+//     basic(1),
+//     x509(2),
+//     (65535)
+// } CredentialType;
+pub const BASIC: CredentialType = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    /// The credential has no valid private key, e.g. because it was decoded.
+    InvalidPrivateKey,
+}
 
 pub trait CredentialTrait: Encode + std::fmt::Debug {
-    fn sign(&self, data: &[u8]) -> Vec<u8>;
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>, Error>;
     fn verify(&self, data: &[u8], signature: &[u8]) -> bool;
+    fn get_identity(&self) -> &[u8];
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Credential {
     credential_type: CredentialType,
-    pub(crate) credential: dyn CredentialTrait,
+    pub(crate) credential: Rc<dyn CredentialTrait>,
+}
+
+impl Credential {
+    pub fn basic(credential: BasicCredential) -> Self {
+        Self {
+            credential_type: BASIC,
+            credential: Rc::new(credential),
+        }
+    }
+}
+
+impl Credential {
+    pub fn get_identity(&self) -> &[u8] {
+        self.credential.get_identity()
+    }
 }
 
 impl Encode for Credential {
@@ -24,6 +59,15 @@ impl Encode for Credential {
     }
 }
 
+impl Decode for Credential {
+    fn decode(cursor: &mut Cursor) -> Result<Self, DecodeError> {
+        match u16::decode(cursor)? {
+            BASIC => Ok(Self::basic(BasicCredential::decode(cursor)?)),
+            _ => Err(DecodeError::InvalidValue),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct BasicCredential {
     identity: Vec<u8>,
@@ -31,12 +75,16 @@ pub struct BasicCredential {
     private_key: Vec<u8>,
 }
 
+// Only Ed25519 is supported for basic credentials right now.
 impl CredentialTrait for BasicCredential {
-    fn sign(&self, data: &[u8]) -> Vec<u8> {
-        unimplemented!()
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        ed25519::sign(&self.private_key, data)
     }
     fn verify(&self, data: &[u8], signature: &[u8]) -> bool {
-        unimplemented!()
+        ed25519::verify(&self.public_key, data, signature)
+    }
+    fn get_identity(&self) -> &[u8] {
+        &self.identity
     }
 }
 
@@ -48,3 +96,16 @@ impl Encode for BasicCredential {
         out
     }
 }
+
+/// A decoded credential only has the public key.
+impl Decode for BasicCredential {
+    fn decode(cursor: &mut Cursor) -> Result<Self, DecodeError> {
+        let identity = decode_slice(LenType::L16, cursor)?;
+        let public_key = decode_slice(LenType::L16, cursor)?;
+        Ok(Self {
+            identity,
+            public_key,
+            private_key: Vec::new(),
+        })
+    }
+}
@@ -1,4 +1,4 @@
-use crate::traits::Encode;
+use crate::traits::{Decode, DecodeError, Encode};
 
 // === General encoding functions that might be useful ===
 
@@ -25,6 +25,23 @@ impl_encode_int!(u16);
 impl_encode_int!(u32);
 impl_encode_int!(u64);
 
+macro_rules! impl_decode_int {
+    ($t:ty) => {
+        impl Decode for $t {
+            fn decode(cursor: &mut Cursor) -> Result<Self, DecodeError> {
+                let mut bytes = [0u8; std::mem::size_of::<$t>()];
+                bytes.copy_from_slice(cursor.read(std::mem::size_of::<$t>())?);
+                Ok(<$t>::from_be_bytes(bytes))
+            }
+        }
+    };
+}
+
+impl_decode_int!(u8);
+impl_decode_int!(u16);
+impl_decode_int!(u32);
+impl_decode_int!(u64);
+
 fn encode_length(len_type: LenType, l: usize, out: &mut Vec<u8>) {
     match len_type {
         LenType::L8 => out.extend((l as u8).encode()),
@@ -39,13 +56,93 @@ pub(crate) fn encode_slice(len_type: LenType, b: &[u8], mut out: &mut Vec<u8>) {
     out.extend(b);
 }
 
-pub(crate) fn encode_u32_slice(len_type: LenType, b_in: &[u32], mut out: &mut Vec<u8>) {
-    encode_length(len_type, b_in.len(), &mut out);
+/// Encode a slice of `u32` with a length prefix of the encoded byte length.
+pub(crate) fn encode_u32_slice(len_type: LenType, b_in: &[u32], out: &mut Vec<u8>) {
+    encode_length(len_type, 4 * b_in.len(), out);
     for b in b_in.iter() {
         out.extend(&b.to_be_bytes());
     }
 }
 
+/// Encode a vector of encodable elements with a length prefix of the
+/// encoded byte length.
+pub(crate) fn encode_vec<T: Encode>(len_type: LenType, v: &[T], out: &mut Vec<u8>) {
+    let encoded: Vec<u8> = v.iter().flat_map(|e| e.encode()).collect();
+    encode_slice(len_type, &encoded, out);
+}
+
+// === General decoding functions ===
+
+/// A read position in a byte slice that is consumed while decoding.
+pub struct Cursor<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Cursor<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, position: 0 }
+    }
+
+    /// Read the next `len` bytes.
+    pub(crate) fn read(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        if self.bytes.len() - self.position < len {
+            return Err(DecodeError::EndOfStream);
+        }
+        let out = &self.bytes[self.position..self.position + len];
+        self.position += len;
+        Ok(out)
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.position == self.bytes.len()
+    }
+}
+
+fn decode_length(len_type: LenType, cursor: &mut Cursor) -> Result<usize, DecodeError> {
+    Ok(match len_type {
+        LenType::L8 => u8::decode(cursor)? as usize,
+        LenType::L16 => u16::decode(cursor)? as usize,
+        LenType::L32 => u32::decode(cursor)? as usize,
+        LenType::L64 => u64::decode(cursor)? as usize,
+    })
+}
+
+pub(crate) fn decode_slice(len_type: LenType, cursor: &mut Cursor) -> Result<Vec<u8>, DecodeError> {
+    let len = decode_length(len_type, cursor)?;
+    Ok(cursor.read(len)?.to_vec())
+}
+
+pub(crate) fn decode_u32_slice(
+    len_type: LenType,
+    cursor: &mut Cursor,
+) -> Result<Vec<u32>, DecodeError> {
+    let len = decode_length(len_type, cursor)?;
+    if len % 4 != 0 {
+        return Err(DecodeError::InvalidValue);
+    }
+    let mut inner = Cursor::new(cursor.read(len)?);
+    let mut out = Vec::with_capacity(len / 4);
+    while !inner.is_empty() {
+        out.push(u32::decode(&mut inner)?);
+    }
+    Ok(out)
+}
+
+/// Decode a vector of decodable elements that was encoded with `encode_vec`.
+pub(crate) fn decode_vec<T: Decode>(
+    len_type: LenType,
+    cursor: &mut Cursor,
+) -> Result<Vec<T>, DecodeError> {
+    let len = decode_length(len_type, cursor)?;
+    let mut inner = Cursor::new(cursor.read(len)?);
+    let mut out = Vec::new();
+    while !inner.is_empty() {
+        out.push(T::decode(&mut inner)?);
+    }
+    Ok(out)
+}
+
 impl<T> Encode for Option<T>
 where
     T: Encode,
@@ -64,3 +161,16 @@ where
         out
     }
 }
+
+impl<T> Decode for Option<T>
+where
+    T: Decode,
+{
+    fn decode(cursor: &mut Cursor) -> Result<Self, DecodeError> {
+        match u8::decode(cursor)? {
+            0x0 => Ok(None),
+            0x1 => Ok(Some(T::decode(cursor)?)),
+            _ => Err(DecodeError::InvalidValue),
+        }
+    }
+}
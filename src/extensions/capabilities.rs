@@ -0,0 +1,99 @@
+use crate::ciphersuites::Name;
+use crate::credentials::CredentialType;
+use crate::encode_util::*;
+use crate::extensions::{ExtensionType, RequiredCapabilitiesExtension};
+use crate::key_package::ProtocolVersion;
+use crate::proposal::ProposalType;
+use crate::traits::{Decode, DecodeError, Encode};
+
+// Section 7.2 Client Capabilities
+// struct {
+//     ProtocolVersion versions<0..255>;
+//     CipherSuite ciphersuites<0..255>;
+//     ExtensionType extensions<0..255>;
+//     ProposalType proposals<0..255>;
+//     CredentialType credentials<0..255>;
+// } Capabilities;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CapabilitiesExtension {
+    versions: Vec<ProtocolVersion>,
+    ciphersuites: Vec<Name>,
+    extensions: Vec<ExtensionType>,
+    proposals: Vec<ProposalType>,
+    credentials: Vec<CredentialType>,
+}
+
+impl CapabilitiesExtension {
+    pub fn new(
+        versions: Vec<ProtocolVersion>,
+        ciphersuites: Vec<Name>,
+        extensions: Vec<ExtensionType>,
+        proposals: Vec<ProposalType>,
+        credentials: Vec<CredentialType>,
+    ) -> Self {
+        Self {
+            versions,
+            ciphersuites,
+            extensions,
+            proposals,
+            credentials,
+        }
+    }
+    pub fn get_versions(&self) -> &[ProtocolVersion] {
+        &self.versions
+    }
+    pub fn get_ciphersuites(&self) -> &[Name] {
+        &self.ciphersuites
+    }
+    pub fn get_extensions(&self) -> &[ExtensionType] {
+        &self.extensions
+    }
+    pub fn get_proposals(&self) -> &[ProposalType] {
+        &self.proposals
+    }
+    pub fn get_credentials(&self) -> &[CredentialType] {
+        &self.credentials
+    }
+
+    /// Whether all extension, proposal and credential types in `required`
+    /// are supported.
+    pub fn supports(&self, required: &RequiredCapabilitiesExtension) -> bool {
+        required
+            .get_extensions()
+            .iter()
+            .all(|e| self.extensions.contains(e))
+            && required
+                .get_proposals()
+                .iter()
+                .all(|p| self.proposals.contains(p))
+            && required
+                .get_credentials()
+                .iter()
+                .all(|c| self.credentials.contains(c))
+    }
+}
+
+impl Encode for CapabilitiesExtension {
+    fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        encode_vec(LenType::L8, &self.versions, &mut out);
+        encode_vec(LenType::L8, &self.ciphersuites, &mut out);
+        encode_vec(LenType::L8, &self.extensions, &mut out);
+        encode_vec(LenType::L8, &self.proposals, &mut out);
+        encode_vec(LenType::L8, &self.credentials, &mut out);
+        out
+    }
+}
+
+impl Decode for CapabilitiesExtension {
+    fn decode(cursor: &mut Cursor) -> Result<Self, DecodeError> {
+        Ok(Self {
+            versions: decode_vec(LenType::L8, cursor)?,
+            ciphersuites: decode_vec(LenType::L8, cursor)?,
+            extensions: decode_vec(LenType::L8, cursor)?,
+            proposals: decode_vec(LenType::L8, cursor)?,
+            credentials: decode_vec(LenType::L8, cursor)?,
+        })
+    }
+}
@@ -0,0 +1,39 @@
+use crate::encode_util::*;
+use crate::traits::{Decode, DecodeError, Encode};
+
+// Section 11.2 External Public Key
+// struct {
+//     HPKEPublicKey external_pub;
+// } ExternalPub;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExternalPubExtension {
+    external_pub: Vec<u8>,
+}
+
+impl ExternalPubExtension {
+    pub fn new(external_pub: &[u8]) -> Self {
+        Self {
+            external_pub: external_pub.to_vec(),
+        }
+    }
+    pub fn get_external_pub(&self) -> &[u8] {
+        &self.external_pub
+    }
+}
+
+impl Encode for ExternalPubExtension {
+    fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        encode_slice(LenType::L16, &self.external_pub, &mut out);
+        out
+    }
+}
+
+impl Decode for ExternalPubExtension {
+    fn decode(cursor: &mut Cursor) -> Result<Self, DecodeError> {
+        Ok(Self {
+            external_pub: decode_slice(LenType::L16, cursor)?,
+        })
+    }
+}
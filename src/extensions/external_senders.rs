@@ -0,0 +1,51 @@
+use crate::credentials::Credential;
+use crate::encode_util::*;
+use crate::traits::{Decode, DecodeError, Encode};
+
+// Section 11.1 External Proposals
+// struct {
+//     Credential external_senders<0..2^32-1>;
+// } ExternalSenders;
+
+/// The credentials of the senders outside the group that may send
+/// proposals. `Sender::External` refers to them by index.
+#[derive(Debug, Clone)]
+pub struct ExternalSendersExtension {
+    senders: Vec<Credential>,
+}
+
+impl ExternalSendersExtension {
+    pub fn new(senders: Vec<Credential>) -> Self {
+        Self { senders }
+    }
+    pub fn get_senders(&self) -> &[Credential] {
+        &self.senders
+    }
+    /// The credential of the external sender at `sender_index`.
+    pub fn get_sender(&self, sender_index: u32) -> Option<&Credential> {
+        self.senders.get(sender_index as usize)
+    }
+}
+
+/// Credentials are compared by their public encoding.
+impl PartialEq for ExternalSendersExtension {
+    fn eq(&self, other: &Self) -> bool {
+        self.encode() == other.encode()
+    }
+}
+
+impl Encode for ExternalSendersExtension {
+    fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        encode_vec(LenType::L32, &self.senders, &mut out);
+        out
+    }
+}
+
+impl Decode for ExternalSendersExtension {
+    fn decode(cursor: &mut Cursor) -> Result<Self, DecodeError> {
+        Ok(Self {
+            senders: decode_vec(LenType::L32, cursor)?,
+        })
+    }
+}
@@ -0,0 +1,37 @@
+use crate::encode_util::*;
+use crate::traits::{Decode, DecodeError, Encode};
+
+// Section 7.4 KeyPackage Identifiers
+// opaque key_id<0..2^16-1>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyIdExtension {
+    key_id: Vec<u8>,
+}
+
+impl KeyIdExtension {
+    pub fn new(key_id: &[u8]) -> Self {
+        Self {
+            key_id: key_id.to_vec(),
+        }
+    }
+    pub fn get_key_id(&self) -> &[u8] {
+        &self.key_id
+    }
+}
+
+impl Encode for KeyIdExtension {
+    fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        encode_slice(LenType::L16, &self.key_id, &mut out);
+        out
+    }
+}
+
+impl Decode for KeyIdExtension {
+    fn decode(cursor: &mut Cursor) -> Result<Self, DecodeError> {
+        Ok(Self {
+            key_id: decode_slice(LenType::L16, cursor)?,
+        })
+    }
+}
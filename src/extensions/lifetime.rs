@@ -0,0 +1,53 @@
+use crate::clock::Clock;
+use crate::encode_util::*;
+use crate::traits::{Decode, DecodeError, Encode};
+
+// Section 7.3 Lifetime
+// struct {
+//     uint64 not_before;
+//     uint64 not_after;
+// } Lifetime;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LifetimeExtension {
+    not_before: u64,
+    not_after: u64,
+}
+
+impl LifetimeExtension {
+    /// Both times are seconds since the UNIX epoch.
+    pub fn new(not_before: u64, not_after: u64) -> Self {
+        Self {
+            not_before,
+            not_after,
+        }
+    }
+    /// A lifetime that starts now and is valid for `validity` seconds.
+    pub fn from_now(clock: &dyn Clock, validity: u64) -> Self {
+        let now = clock.now();
+        Self::new(now, now.saturating_add(validity))
+    }
+    pub fn get_not_before(&self) -> u64 {
+        self.not_before
+    }
+    pub fn get_not_after(&self) -> u64 {
+        self.not_after
+    }
+}
+
+impl Encode for LifetimeExtension {
+    fn encode(&self) -> Vec<u8> {
+        let mut out = self.not_before.encode();
+        out.extend(self.not_after.encode());
+        out
+    }
+}
+
+impl Decode for LifetimeExtension {
+    fn decode(cursor: &mut Cursor) -> Result<Self, DecodeError> {
+        Ok(Self {
+            not_before: u64::decode(cursor)?,
+            not_after: u64::decode(cursor)?,
+        })
+    }
+}
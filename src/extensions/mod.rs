@@ -0,0 +1,343 @@
+//! Extensions for key packages, the group context and group info.
+
+use crate::encode_util::*;
+use crate::traits::{Decode, DecodeError, Encode};
+
+mod capabilities;
+pub use capabilities::*;
+
+mod lifetime;
+pub use lifetime::*;
+
+mod key_id;
+pub use key_id::*;
+
+mod parent_hash;
+pub use parent_hash::*;
+
+mod ratchet_tree;
+pub use ratchet_tree::*;
+
+mod required_capabilities;
+pub use required_capabilities::*;
+
+mod external_pub;
+pub use external_pub::*;
+
+mod external_senders;
+pub use external_senders::*;
+
+// Section 7
+// // See IANA registry for registered values
+// uint16 ExtensionType;
+// struct {
+//     ExtensionType extension_type;
+//     opaque extension_data<0..2^16-1>;
+// } Extension;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ExtensionType {
+    Capabilities,
+    Lifetime,
+    KeyId,
+    ParentHash,
+    RatchetTree,
+    RequiredCapabilities,
+    ExternalPub,
+    ExternalSenders,
+    Unknown(u16),
+}
+
+impl From<u16> for ExtensionType {
+    fn from(v: u16) -> Self {
+        match v {
+            1 => ExtensionType::Capabilities,
+            2 => ExtensionType::Lifetime,
+            3 => ExtensionType::KeyId,
+            4 => ExtensionType::ParentHash,
+            5 => ExtensionType::RatchetTree,
+            6 => ExtensionType::RequiredCapabilities,
+            7 => ExtensionType::ExternalPub,
+            8 => ExtensionType::ExternalSenders,
+            _ => ExtensionType::Unknown(v),
+        }
+    }
+}
+
+impl From<ExtensionType> for u16 {
+    fn from(t: ExtensionType) -> u16 {
+        match t {
+            ExtensionType::Capabilities => 1,
+            ExtensionType::Lifetime => 2,
+            ExtensionType::KeyId => 3,
+            ExtensionType::ParentHash => 4,
+            ExtensionType::RatchetTree => 5,
+            ExtensionType::RequiredCapabilities => 6,
+            ExtensionType::ExternalPub => 7,
+            ExtensionType::ExternalSenders => 8,
+            ExtensionType::Unknown(v) => v,
+        }
+    }
+}
+
+impl Encode for ExtensionType {
+    fn encode(&self) -> Vec<u8> {
+        u16::from(*self).encode()
+    }
+}
+
+impl Decode for ExtensionType {
+    fn decode(cursor: &mut Cursor) -> Result<Self, DecodeError> {
+        Ok(ExtensionType::from(u16::decode(cursor)?))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Extension {
+    Capabilities(CapabilitiesExtension),
+    Lifetime(LifetimeExtension),
+    KeyId(KeyIdExtension),
+    ParentHash(ParentHashExtension),
+    RatchetTree(RatchetTreeExtension),
+    RequiredCapabilities(RequiredCapabilitiesExtension),
+    ExternalPub(ExternalPubExtension),
+    ExternalSenders(ExternalSendersExtension),
+    /// An extension this crate doesn't know. The data is kept as is.
+    Unknown(u16, Vec<u8>),
+}
+
+impl Extension {
+    pub fn get_type(&self) -> ExtensionType {
+        match self {
+            Extension::Capabilities(_) => ExtensionType::Capabilities,
+            Extension::Lifetime(_) => ExtensionType::Lifetime,
+            Extension::KeyId(_) => ExtensionType::KeyId,
+            Extension::ParentHash(_) => ExtensionType::ParentHash,
+            Extension::RatchetTree(_) => ExtensionType::RatchetTree,
+            Extension::RequiredCapabilities(_) => ExtensionType::RequiredCapabilities,
+            Extension::ExternalPub(_) => ExtensionType::ExternalPub,
+            Extension::ExternalSenders(_) => ExtensionType::ExternalSenders,
+            Extension::Unknown(t, _) => ExtensionType::Unknown(*t),
+        }
+    }
+
+    /// The encoded `extension_data`.
+    pub fn get_data(&self) -> Vec<u8> {
+        match self {
+            Extension::Capabilities(e) => e.encode(),
+            Extension::Lifetime(e) => e.encode(),
+            Extension::KeyId(e) => e.encode(),
+            Extension::ParentHash(e) => e.encode(),
+            Extension::RatchetTree(e) => e.encode(),
+            Extension::RequiredCapabilities(e) => e.encode(),
+            Extension::ExternalPub(e) => e.encode(),
+            Extension::ExternalSenders(e) => e.encode(),
+            Extension::Unknown(_, data) => data.clone(),
+        }
+    }
+}
+
+impl Encode for Extension {
+    fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend(&self.get_type().encode());
+        encode_slice(LenType::L16, &self.get_data(), &mut out);
+        out
+    }
+}
+
+impl Decode for Extension {
+    fn decode(cursor: &mut Cursor) -> Result<Self, DecodeError> {
+        let extension_type = ExtensionType::decode(cursor)?;
+        let data = decode_slice(LenType::L16, cursor)?;
+        Ok(match extension_type {
+            ExtensionType::Capabilities => {
+                Extension::Capabilities(CapabilitiesExtension::decode_all(&data)?)
+            }
+            ExtensionType::Lifetime => Extension::Lifetime(LifetimeExtension::decode_all(&data)?),
+            ExtensionType::KeyId => Extension::KeyId(KeyIdExtension::decode_all(&data)?),
+            ExtensionType::ParentHash => {
+                Extension::ParentHash(ParentHashExtension::decode_all(&data)?)
+            }
+            ExtensionType::RatchetTree => {
+                Extension::RatchetTree(RatchetTreeExtension::decode_all(&data)?)
+            }
+            ExtensionType::RequiredCapabilities => Extension::RequiredCapabilities(
+                RequiredCapabilitiesExtension::decode_all(&data)?,
+            ),
+            ExtensionType::ExternalPub => {
+                Extension::ExternalPub(ExternalPubExtension::decode_all(&data)?)
+            }
+            ExtensionType::ExternalSenders => {
+                Extension::ExternalSenders(ExternalSendersExtension::decode_all(&data)?)
+            }
+            ExtensionType::Unknown(t) => Extension::Unknown(t, data),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    DuplicateExtension(ExtensionType),
+    InvalidContext(ExtensionType, ExtensionContext),
+    ExtensionsTooShort,
+}
+
+/// The structures extensions can appear in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExtensionContext {
+    KeyPackage,
+    GroupContext,
+    GroupInfo,
+}
+
+impl ExtensionContext {
+    /// The minimum length of the encoded extension vector in this context.
+    fn min_length(&self) -> usize {
+        match self {
+            // Extension extensions<8..2^32-1>;
+            ExtensionContext::KeyPackage => 8,
+            // Extension extensions<0..2^32-1>;
+            ExtensionContext::GroupContext | ExtensionContext::GroupInfo => 0,
+        }
+    }
+}
+
+impl ExtensionType {
+    /// Check whether an extension of this type may appear in `context`.
+    /// Unknown extensions are allowed everywhere.
+    pub fn allowed_in(&self, context: ExtensionContext) -> bool {
+        match self {
+            ExtensionType::Capabilities
+            | ExtensionType::Lifetime
+            | ExtensionType::KeyId
+            | ExtensionType::ParentHash => context == ExtensionContext::KeyPackage,
+            ExtensionType::RequiredCapabilities | ExtensionType::ExternalSenders => {
+                context == ExtensionContext::GroupContext
+            }
+            ExtensionType::RatchetTree | ExtensionType::ExternalPub => {
+                context == ExtensionContext::GroupInfo
+            }
+            ExtensionType::Unknown(_) => true,
+        }
+    }
+}
+
+/// Check that `extension` may be added to `extensions` in `context`.
+pub(crate) fn check_extension(
+    extensions: &[Extension],
+    extension: &Extension,
+    context: ExtensionContext,
+) -> Result<(), Error> {
+    let extension_type = extension.get_type();
+    if !extension_type.allowed_in(context) {
+        return Err(Error::InvalidContext(extension_type, context));
+    }
+    if extensions.iter().any(|e| e.get_type() == extension_type) {
+        return Err(Error::DuplicateExtension(extension_type));
+    }
+    Ok(())
+}
+
+/// Validate a complete list of extensions for `context`.
+/// Every extension type must only appear once, be allowed in the context,
+/// and the encoded list must have the minimum length for the context.
+pub fn validate_extensions(
+    extensions: &[Extension],
+    context: ExtensionContext,
+) -> Result<(), Error> {
+    for (i, extension) in extensions.iter().enumerate() {
+        check_extension(&extensions[..i], extension, context)?;
+    }
+    let encoded_length = extensions.iter().map(|e| e.encode().len()).sum::<usize>();
+    if encoded_length < context.min_length() {
+        return Err(Error::ExtensionsTooShort);
+    }
+    Ok(())
+}
+
+/// The `required_capabilities` among the group context `extensions`.
+pub fn required_capabilities(extensions: &[Extension]) -> Option<&RequiredCapabilitiesExtension> {
+    extensions.iter().find_map(|e| match e {
+        Extension::RequiredCapabilities(e) => Some(e),
+        _ => None,
+    })
+}
+
+pub(crate) fn encode_extensions(extensions: &[Extension]) -> Vec<u8> {
+    let mut out = Vec::new();
+    encode_vec(LenType::L32, extensions, &mut out);
+    out
+}
+
+/// Decode a list of extensions.
+/// Every extension type may only appear once.
+pub(crate) fn decode_extensions(cursor: &mut Cursor) -> Result<Vec<Extension>, DecodeError> {
+    let extensions: Vec<Extension> = decode_vec(LenType::L32, cursor)?;
+    for (i, extension) in extensions.iter().enumerate() {
+        if extensions[..i]
+            .iter()
+            .any(|e| e.get_type() == extension.get_type())
+        {
+            return Err(DecodeError::InvalidValue);
+        }
+    }
+    Ok(extensions)
+}
+
+#[test]
+fn test_extension_codec() {
+    let extensions = vec![
+        Extension::Lifetime(LifetimeExtension::new(1, 2)),
+        Extension::KeyId(KeyIdExtension::new(b"key id")),
+        Extension::Unknown(0xff00, vec![1, 2, 3]),
+    ];
+    let encoded = encode_extensions(&extensions);
+    let decoded = decode_extensions(&mut Cursor::new(&encoded)).unwrap();
+    assert_eq!(extensions, decoded);
+    assert_eq!(decoded[2].get_type(), ExtensionType::Unknown(0xff00));
+
+    // Every extension type may only appear once.
+    let duplicates = encode_extensions(&[extensions[0].clone(), extensions[0].clone()]);
+    assert_eq!(
+        decode_extensions(&mut Cursor::new(&duplicates)),
+        Err(DecodeError::InvalidValue)
+    );
+}
+
+#[test]
+fn test_validate_extensions() {
+    let lifetime = Extension::Lifetime(LifetimeExtension::new(1, 2));
+    let key_id = Extension::KeyId(KeyIdExtension::new(b"key id"));
+    let required_capabilities = Extension::RequiredCapabilities(
+        RequiredCapabilitiesExtension::new(Vec::new(), Vec::new(), Vec::new()),
+    );
+
+    assert_eq!(
+        validate_extensions(&[lifetime.clone(), key_id.clone()], ExtensionContext::KeyPackage),
+        Ok(())
+    );
+    assert_eq!(
+        validate_extensions(&[lifetime.clone(), lifetime.clone()], ExtensionContext::KeyPackage),
+        Err(Error::DuplicateExtension(ExtensionType::Lifetime))
+    );
+    assert_eq!(
+        validate_extensions(
+            std::slice::from_ref(&required_capabilities),
+            ExtensionContext::KeyPackage
+        ),
+        Err(Error::InvalidContext(
+            ExtensionType::RequiredCapabilities,
+            ExtensionContext::KeyPackage
+        ))
+    );
+    assert_eq!(
+        validate_extensions(&[required_capabilities], ExtensionContext::GroupContext),
+        Ok(())
+    );
+    assert_eq!(
+        validate_extensions(&[], ExtensionContext::KeyPackage),
+        Err(Error::ExtensionsTooShort)
+    );
+    assert_eq!(validate_extensions(&[], ExtensionContext::GroupInfo), Ok(()));
+}
@@ -0,0 +1,37 @@
+use crate::encode_util::*;
+use crate::traits::{Decode, DecodeError, Encode};
+
+// Section 7.5 Parent Hash
+// opaque parent_hash<0..255>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParentHashExtension {
+    parent_hash: Vec<u8>,
+}
+
+impl ParentHashExtension {
+    pub fn new(parent_hash: &[u8]) -> Self {
+        Self {
+            parent_hash: parent_hash.to_vec(),
+        }
+    }
+    pub fn get_parent_hash(&self) -> &[u8] {
+        &self.parent_hash
+    }
+}
+
+impl Encode for ParentHashExtension {
+    fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        encode_slice(LenType::L8, &self.parent_hash, &mut out);
+        out
+    }
+}
+
+impl Decode for ParentHashExtension {
+    fn decode(cursor: &mut Cursor) -> Result<Self, DecodeError> {
+        Ok(Self {
+            parent_hash: decode_slice(LenType::L8, cursor)?,
+        })
+    }
+}
@@ -0,0 +1,43 @@
+use crate::ciphersuites::Name;
+use crate::clock::Clock;
+use crate::encode_util::*;
+use crate::traits::{Decode, DecodeError, Encode};
+use crate::tree::{Error as TreeError, NodeStore, Tree};
+
+// Section 7.6 Ratchet Tree Extension
+// optional<Node> tree<1..2^32-1>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RatchetTreeExtension {
+    // The encoded nodes without the length prefix.
+    nodes: Vec<u8>,
+}
+
+impl RatchetTreeExtension {
+    /// Build a `ratchet_tree` extension carrying the public part of `tree`.
+    pub fn new<S: NodeStore>(tree: &Tree<S>) -> Result<Self, TreeError> {
+        Self::decode_all(&tree.export_public()?).map_err(|_| TreeError::DecodingError)
+    }
+
+    /// Rebuild the public tree from this extension.
+    /// The key packages of the leaves are validated with `clock`.
+    pub fn to_tree(&self, ciphersuite: Name, clock: &dyn Clock) -> Result<Tree, TreeError> {
+        Tree::import_public(ciphersuite, &self.encode(), clock)
+    }
+}
+
+impl Encode for RatchetTreeExtension {
+    fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        encode_slice(LenType::L32, &self.nodes, &mut out);
+        out
+    }
+}
+
+impl Decode for RatchetTreeExtension {
+    fn decode(cursor: &mut Cursor) -> Result<Self, DecodeError> {
+        Ok(Self {
+            nodes: decode_slice(LenType::L32, cursor)?,
+        })
+    }
+}
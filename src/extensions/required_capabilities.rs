@@ -0,0 +1,66 @@
+use crate::credentials::CredentialType;
+use crate::encode_util::*;
+use crate::extensions::ExtensionType;
+use crate::proposal::ProposalType;
+use crate::traits::{Decode, DecodeError, Encode};
+
+// Section 11.1 Required Capabilities
+// struct {
+//     ExtensionType extensions<0..255>;
+//     ProposalType proposals<0..255>;
+//     CredentialType credentials<0..255>;
+// } RequiredCapabilities;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequiredCapabilitiesExtension {
+    extensions: Vec<ExtensionType>,
+    proposals: Vec<ProposalType>,
+    credentials: Vec<CredentialType>,
+}
+
+impl RequiredCapabilitiesExtension {
+    pub fn new(
+        extensions: Vec<ExtensionType>,
+        proposals: Vec<ProposalType>,
+        credentials: Vec<CredentialType>,
+    ) -> Self {
+        Self {
+            extensions,
+            proposals,
+            credentials,
+        }
+    }
+    pub fn get_extensions(&self) -> &[ExtensionType] {
+        &self.extensions
+    }
+    pub fn get_proposals(&self) -> &[ProposalType] {
+        &self.proposals
+    }
+    pub fn get_credentials(&self) -> &[CredentialType] {
+        &self.credentials
+    }
+    /// Whether nothing is required.
+    pub fn is_empty(&self) -> bool {
+        self.extensions.is_empty() && self.proposals.is_empty() && self.credentials.is_empty()
+    }
+}
+
+impl Encode for RequiredCapabilitiesExtension {
+    fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        encode_vec(LenType::L8, &self.extensions, &mut out);
+        encode_vec(LenType::L8, &self.proposals, &mut out);
+        encode_vec(LenType::L8, &self.credentials, &mut out);
+        out
+    }
+}
+
+impl Decode for RequiredCapabilitiesExtension {
+    fn decode(cursor: &mut Cursor) -> Result<Self, DecodeError> {
+        Ok(Self {
+            extensions: decode_vec(LenType::L8, cursor)?,
+            proposals: decode_vec(LenType::L8, cursor)?,
+            credentials: decode_vec(LenType::L8, cursor)?,
+        })
+    }
+}
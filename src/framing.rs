@@ -0,0 +1,422 @@
+//! Message framing
+
+use crate::ciphersuites::Ciphersuite;
+use crate::commit::Commit;
+use crate::credentials::{Credential, Error as CredentialError};
+use crate::encode_util::*;
+use crate::group_context::GroupContext;
+use crate::proposal::Proposal;
+use crate::traits::{Decode, DecodeError, Encode};
+
+// Section 9 Message Framing
+// enum {
+//     reserved(0),
+//     application(1),
+//     proposal(2),
+//     commit(3),
+//     (255)
+// } ContentType;
+//
+// enum {
+//     reserved(0),
+//     member(1),
+//     preconfigured(2),
+//     new_member(3),
+//     (255)
+// } SenderType;
+//
+// struct {
+//     SenderType sender_type;
+//     uint32 sender;
+// } Sender;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ContentType {
+    Application = 1,
+    Proposal = 2,
+    Commit = 3,
+}
+
+impl Decode for ContentType {
+    fn decode(cursor: &mut Cursor) -> Result<Self, DecodeError> {
+        match u8::decode(cursor)? {
+            1 => Ok(ContentType::Application),
+            2 => Ok(ContentType::Proposal),
+            3 => Ok(ContentType::Commit),
+            _ => Err(DecodeError::InvalidValue),
+        }
+    }
+}
+
+const MEMBER: u8 = 1;
+const EXTERNAL: u8 = 2;
+const NEW_MEMBER: u8 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sender {
+    /// A member of the group, identified by its leaf index.
+    Member(u32),
+    /// A preconfigured sender outside the group, identified by its index in
+    /// the `external_senders` extension of the group context.
+    External(u32),
+    NewMember,
+}
+
+impl Encode for Sender {
+    fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        match self {
+            Sender::Member(leaf_index) => {
+                out.push(MEMBER);
+                out.extend(leaf_index.encode());
+            }
+            Sender::External(sender_index) => {
+                out.push(EXTERNAL);
+                out.extend(sender_index.encode());
+            }
+            Sender::NewMember => {
+                out.push(NEW_MEMBER);
+                out.extend(0u32.encode());
+            }
+        }
+        out
+    }
+}
+
+impl Decode for Sender {
+    fn decode(cursor: &mut Cursor) -> Result<Self, DecodeError> {
+        let sender_type = u8::decode(cursor)?;
+        let sender = u32::decode(cursor)?;
+        match sender_type {
+            MEMBER => Ok(Sender::Member(sender)),
+            EXTERNAL => Ok(Sender::External(sender)),
+            NEW_MEMBER => Ok(Sender::NewMember),
+            _ => Err(DecodeError::InvalidValue),
+        }
+    }
+}
+
+//     ContentType content_type;
+//     select (MLSPlaintext.content_type) {
+//         case application:
+//           opaque application_data<0..2^32-1>;
+//
+//         case proposal:
+//           Proposal proposal;
+//
+//         case commit:
+//           Commit commit;
+//     }
+#[derive(Debug, Clone)]
+pub enum MLSPlaintextContent {
+    Application(Vec<u8>),
+    Proposal(Proposal),
+    Commit(Commit),
+}
+
+impl MLSPlaintextContent {
+    pub fn get_type(&self) -> ContentType {
+        match self {
+            MLSPlaintextContent::Application(_) => ContentType::Application,
+            MLSPlaintextContent::Proposal(_) => ContentType::Proposal,
+            MLSPlaintextContent::Commit(_) => ContentType::Commit,
+        }
+    }
+}
+
+impl MLSPlaintextContent {
+    /// Encode the content without the content type.
+    pub(crate) fn encode_body(&self, out: &mut Vec<u8>) {
+        match self {
+            MLSPlaintextContent::Application(application_data) => {
+                encode_slice(LenType::L32, application_data, out)
+            }
+            MLSPlaintextContent::Proposal(proposal) => out.extend(proposal.encode()),
+            MLSPlaintextContent::Commit(commit) => out.extend(commit.encode()),
+        }
+    }
+
+    /// Decode content of type `content_type`.
+    pub(crate) fn decode_body(
+        content_type: ContentType,
+        cursor: &mut Cursor,
+    ) -> Result<Self, DecodeError> {
+        match content_type {
+            ContentType::Application => Ok(MLSPlaintextContent::Application(decode_slice(
+                LenType::L32,
+                cursor,
+            )?)),
+            ContentType::Proposal => Ok(MLSPlaintextContent::Proposal(Proposal::decode(cursor)?)),
+            ContentType::Commit => Ok(MLSPlaintextContent::Commit(Commit::decode(cursor)?)),
+        }
+    }
+}
+
+impl Encode for MLSPlaintextContent {
+    fn encode(&self) -> Vec<u8> {
+        let mut out = vec![self.get_type() as u8];
+        self.encode_body(&mut out);
+        out
+    }
+}
+
+impl Decode for MLSPlaintextContent {
+    fn decode(cursor: &mut Cursor) -> Result<Self, DecodeError> {
+        let content_type = ContentType::decode(cursor)?;
+        Self::decode_body(content_type, cursor)
+    }
+}
+
+// struct {
+//     opaque group_id<0..255>;
+//     uint64 epoch;
+//     Sender sender;
+//     opaque authenticated_data<0..2^32-1>;
+//
+//     ContentType content_type;
+//     select (MLSPlaintext.content_type) { ... }
+//
+//     opaque signature<0..2^16-1>;
+//     optional<MAC> confirmation_tag;
+//     optional<MAC> membership_tag;
+// } MLSPlaintext;
+//
+// opaque MAC<0..255>;
+#[derive(Debug, Clone)]
+pub struct MLSPlaintext {
+    pub(crate) group_id: Vec<u8>,
+    pub(crate) epoch: u64,
+    pub(crate) sender: Sender,
+    pub(crate) authenticated_data: Vec<u8>,
+    pub(crate) content: MLSPlaintextContent,
+    pub(crate) signature: Vec<u8>,
+    pub(crate) confirmation_tag: Option<Vec<u8>>,
+    pub(crate) membership_tag: Option<Vec<u8>>,
+}
+
+impl MLSPlaintext {
+    /// Create a new, unsigned plaintext.
+    pub fn new(
+        group_id: &[u8],
+        epoch: u64,
+        sender: Sender,
+        authenticated_data: &[u8],
+        content: MLSPlaintextContent,
+    ) -> Self {
+        Self {
+            group_id: group_id.to_vec(),
+            epoch,
+            sender,
+            authenticated_data: authenticated_data.to_vec(),
+            content,
+            signature: Vec::new(),
+            confirmation_tag: None,
+            membership_tag: None,
+        }
+    }
+    pub fn get_group_id(&self) -> &[u8] {
+        &self.group_id
+    }
+    pub fn get_epoch(&self) -> u64 {
+        self.epoch
+    }
+    pub fn get_sender(&self) -> Sender {
+        self.sender
+    }
+    pub fn get_authenticated_data(&self) -> &[u8] {
+        &self.authenticated_data
+    }
+    pub fn get_content(&self) -> &MLSPlaintextContent {
+        &self.content
+    }
+    pub fn get_confirmation_tag(&self) -> Option<&[u8]> {
+        self.confirmation_tag.as_deref()
+    }
+
+    /// The part of the plaintext shared by the signature input and the
+    /// encoding.
+    fn encode_content(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        encode_slice(LenType::L8, &self.group_id, &mut out);
+        out.extend(self.epoch.encode());
+        out.extend(self.sender.encode());
+        encode_slice(LenType::L32, &self.authenticated_data, &mut out);
+        out.extend(self.content.encode());
+        out
+    }
+
+    // struct {
+    //     select (MLSPlaintextTBS.sender.sender_type) {
+    //         case member:
+    //             GroupContext context;
+    //
+    //         case preconfigured:
+    //         case new_member:
+    //             struct{};
+    //     }
+    //
+    //     opaque group_id<0..255>;
+    //     uint64 epoch;
+    //     Sender sender;
+    //     opaque authenticated_data<0..2^32-1>;
+    //
+    //     ContentType content_type;
+    //     select (MLSPlaintextTBS.content_type) { ... }
+    // } MLSPlaintextTBS;
+    pub(crate) fn encode_tbs(&self, context: &GroupContext) -> Vec<u8> {
+        let mut out = match self.sender {
+            Sender::Member(_) => context.encode(),
+            Sender::External(_) | Sender::NewMember => Vec::new(),
+        };
+        out.extend(self.encode_content());
+        out
+    }
+
+    /// Create a proposal from the external sender at `sender_index` of the
+    /// group's `external_senders` extension, signed with its `credential`.
+    /// External senders don't know the group context; only `group_id` and
+    /// `epoch` are needed.
+    pub fn external_proposal(
+        group_id: &[u8],
+        epoch: u64,
+        sender_index: u32,
+        proposal: Proposal,
+        credential: &Credential,
+    ) -> Result<Self, CredentialError> {
+        let mut plaintext = Self::new(
+            group_id,
+            epoch,
+            Sender::External(sender_index),
+            &[],
+            MLSPlaintextContent::Proposal(proposal),
+        );
+        // The group context isn't part of the signature input.
+        plaintext.signature = credential.credential.sign(&plaintext.encode_content())?;
+        Ok(plaintext)
+    }
+
+    /// Sign the plaintext with `credential`.
+    pub(crate) fn sign(
+        &mut self,
+        credential: &Credential,
+        context: &GroupContext,
+    ) -> Result<(), CredentialError> {
+        self.signature = credential.credential.sign(&self.encode_tbs(context))?;
+        Ok(())
+    }
+
+    /// Verify the signature with the sender's `credential`.
+    pub(crate) fn verify_signature(&self, credential: &Credential, context: &GroupContext) -> bool {
+        credential
+            .credential
+            .verify(&self.encode_tbs(context), &self.signature)
+    }
+
+    // struct {
+    //     MLSPlaintextTBS tbs;
+    //     opaque signature<0..2^16-1>;
+    //     optional<MAC> confirmation_tag;
+    // } MLSPlaintextTBM;
+    fn encode_tbm(&self, context: &GroupContext) -> Vec<u8> {
+        let mut out = self.encode_tbs(context);
+        encode_slice(LenType::L16, &self.signature, &mut out);
+        encode_mac(&self.confirmation_tag, &mut out);
+        out
+    }
+
+    // membership_tag = MAC(membership_key, MLSPlaintextTBM);
+    /// Set the membership tag. This has to happen after signing and setting
+    /// the confirmation tag.
+    pub(crate) fn set_membership_tag(
+        &mut self,
+        ciphersuite: &Ciphersuite,
+        membership_key: &[u8],
+        context: &GroupContext,
+    ) {
+        self.membership_tag = Some(ciphersuite.mac(membership_key, &self.encode_tbm(context)));
+    }
+
+    /// Check the membership tag in constant time. Plaintexts without one
+    /// are rejected.
+    pub(crate) fn verify_membership_tag(
+        &self,
+        ciphersuite: &Ciphersuite,
+        membership_key: &[u8],
+        context: &GroupContext,
+    ) -> bool {
+        match &self.membership_tag {
+            Some(tag) => ciphersuite.verify_mac(membership_key, &self.encode_tbm(context), tag),
+            None => false,
+        }
+    }
+
+    // struct {
+    //     opaque group_id<0..255>;
+    //     uint64 epoch;
+    //     Sender sender;
+    //     opaque authenticated_data<0..2^32-1>;
+    //
+    //     ContentType content_type = commit;
+    //     Commit commit;
+    //     opaque signature<0..2^16-1>;
+    // } MLSPlaintextCommitContent;
+    pub(crate) fn encode_commit_content(&self) -> Vec<u8> {
+        debug_assert_eq!(self.content.get_type(), ContentType::Commit);
+        let mut out = self.encode_content();
+        encode_slice(LenType::L16, &self.signature, &mut out);
+        out
+    }
+
+    // struct {
+    //     optional<MAC> confirmation_tag;
+    // } MLSPlaintextCommitAuthData;
+    pub(crate) fn encode_commit_auth_data(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        encode_mac(&self.confirmation_tag, &mut out);
+        out
+    }
+}
+
+/// Encode an `optional<MAC>`.
+pub(crate) fn encode_mac(mac: &Option<Vec<u8>>, out: &mut Vec<u8>) {
+    match mac {
+        Some(mac) => {
+            out.push(0x1);
+            encode_slice(LenType::L8, mac, out);
+        }
+        None => out.push(0x0),
+    }
+}
+
+pub(crate) fn decode_mac(cursor: &mut Cursor) -> Result<Option<Vec<u8>>, DecodeError> {
+    match u8::decode(cursor)? {
+        0x0 => Ok(None),
+        0x1 => Ok(Some(decode_slice(LenType::L8, cursor)?)),
+        _ => Err(DecodeError::InvalidValue),
+    }
+}
+
+impl Encode for MLSPlaintext {
+    fn encode(&self) -> Vec<u8> {
+        let mut out = self.encode_content();
+        encode_slice(LenType::L16, &self.signature, &mut out);
+        encode_mac(&self.confirmation_tag, &mut out);
+        encode_mac(&self.membership_tag, &mut out);
+        out
+    }
+}
+
+impl Decode for MLSPlaintext {
+    fn decode(cursor: &mut Cursor) -> Result<Self, DecodeError> {
+        Ok(Self {
+            group_id: decode_slice(LenType::L8, cursor)?,
+            epoch: u64::decode(cursor)?,
+            sender: Sender::decode(cursor)?,
+            authenticated_data: decode_slice(LenType::L32, cursor)?,
+            content: MLSPlaintextContent::decode(cursor)?,
+            signature: decode_slice(LenType::L16, cursor)?,
+            confirmation_tag: decode_mac(cursor)?,
+            membership_tag: decode_mac(cursor)?,
+        })
+    }
+}
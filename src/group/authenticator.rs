@@ -0,0 +1,33 @@
+//! Comparing group state out of band.
+//!
+//! All members of an epoch derive the same `authentication_secret`, the
+//! epoch authenticator. Users can compare it over another channel, e.g. in
+//! person, to make sure they are in the same group state. The safety number
+//! is a short rendering of it that is easy to read out.
+
+/// The number of 5 digit groups in a safety number.
+pub const SAFETY_NUMBER_GROUPS: usize = 6;
+
+/// Render `epoch_authenticator` as a safety number of 6 groups of 5 digits,
+/// e.g. `09060 60585 12110 63635 15160 66685`.
+/// Every group is a 5 byte chunk of the authenticator read as big endian
+/// integer modulo 100000. The authenticator has at least 32 bytes, of which
+/// the first 30 are used.
+pub fn safety_number(epoch_authenticator: &[u8]) -> String {
+    epoch_authenticator
+        .chunks_exact(5)
+        .take(SAFETY_NUMBER_GROUPS)
+        .map(|chunk| {
+            let value = chunk.iter().fold(0u64, |v, b| (v << 8) | u64::from(*b));
+            format!("{:05}", value % 100_000)
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+#[test]
+fn test_safety_number() {
+    let authenticator: Vec<u8> = (0..32).collect();
+    assert_eq!(safety_number(&authenticator), "09060 60585 12110 63635 15160 66685");
+    assert_eq!(safety_number(&[0xff; 32]), "27775 27775 27775 27775 27775 27775");
+}
@@ -0,0 +1,101 @@
+//! What changed in a group.
+
+use crate::framing::Sender;
+use crate::proposal::{Proposal, ReInitProposal};
+use crate::proposal_store::QueuedProposal;
+
+/// The result of processing a handshake message.
+#[derive(Debug, Clone)]
+pub enum GroupEvent {
+    /// A proposal was added to the proposal store.
+    ProposalReceived(QueuedProposal),
+    /// A commit was applied and the group is in a new epoch.
+    NewEpoch(EpochChange),
+    /// We were removed from the group by a commit. The group can't be used
+    /// anymore.
+    Removed,
+}
+
+/// The membership changes of a commit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EpochChange {
+    pub(crate) epoch: u64,
+    pub(crate) committer: u32,
+    pub(crate) added: Vec<u32>,
+    pub(crate) updated: Vec<u32>,
+    pub(crate) removed: Vec<u32>,
+    pub(crate) reinit: Option<ReInitProposal>,
+}
+
+impl EpochChange {
+    pub(crate) fn new(
+        epoch: u64,
+        committer: u32,
+        proposals: &[(Sender, Proposal)],
+        added: Vec<u32>,
+    ) -> Self {
+        let mut updated = Vec::new();
+        let mut removed = Vec::new();
+        let mut reinit = None;
+        for (sender, proposal) in proposals {
+            match (sender, proposal) {
+                (Sender::Member(leaf_index), Proposal::Update(_)) => updated.push(*leaf_index),
+                (_, Proposal::Remove(remove)) => removed.push(remove.get_removed()),
+                (_, Proposal::ReInit(p)) => reinit = Some(p.clone()),
+                _ => (),
+            }
+        }
+        Self {
+            epoch,
+            committer,
+            added,
+            updated,
+            removed,
+            reinit,
+        }
+    }
+
+    /// The new epoch.
+    pub fn get_epoch(&self) -> u64 {
+        self.epoch
+    }
+    pub fn get_committer(&self) -> u32 {
+        self.committer
+    }
+    /// Leaf indices of the new members.
+    pub fn get_added(&self) -> &[u32] {
+        &self.added
+    }
+    /// Leaf indices of members that updated their key package.
+    pub fn get_updated(&self) -> &[u32] {
+        &self.updated
+    }
+    /// Leaf indices of the removed members.
+    pub fn get_removed(&self) -> &[u32] {
+        &self.removed
+    }
+    /// The ReInit proposal if the group is to be reinitialized.
+    pub fn get_reinit(&self) -> Option<&ReInitProposal> {
+        self.reinit.as_ref()
+    }
+}
+
+/// A decrypted application message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApplicationMessage {
+    pub(crate) sender: u32,
+    pub(crate) authenticated_data: Vec<u8>,
+    pub(crate) data: Vec<u8>,
+}
+
+impl ApplicationMessage {
+    pub fn get_sender(&self) -> u32 {
+        self.sender
+    }
+    pub fn get_authenticated_data(&self) -> &[u8] {
+        &self.authenticated_data
+    }
+    pub fn get_data(&self) -> &[u8] {
+        &self.data
+    }
+}
@@ -0,0 +1,151 @@
+//! Secrets of past epochs.
+//!
+//! Resumption PSKs refer to the `resumption_secret` of a past epoch, and
+//! application messages can arrive after the epoch they were sent in has
+//! ended. The history keeps what's needed for that for a bounded number of
+//! past epochs; the oldest epoch is evicted first.
+
+use std::collections::VecDeque;
+
+use crate::credentials::Credential;
+use crate::group_context::GroupContext;
+use crate::psk::{Psk, PskStore};
+use crate::secret_tree::SecretTree;
+
+/// The number of past epochs a group keeps by default.
+pub const DEFAULT_MAX_PAST_EPOCHS: usize = 3;
+
+/// What's left of an epoch after the group moved on.
+#[derive(Debug, Clone)]
+pub struct PastEpoch {
+    pub(crate) group_context: GroupContext,
+    pub(crate) resumption_secret: Vec<u8>,
+    /// Only kept for late application messages.
+    pub(crate) late_messages: Option<LateMessageSecrets>,
+}
+
+/// The state needed to decrypt and verify application messages of a past
+/// epoch.
+#[derive(Debug, Clone)]
+pub(crate) struct LateMessageSecrets {
+    pub(crate) secret_tree: SecretTree,
+    pub(crate) sender_data_secret: Vec<u8>,
+    /// The credentials of the members by leaf index.
+    pub(crate) credentials: Vec<Option<Credential>>,
+}
+
+impl PastEpoch {
+    pub fn get_group_id(&self) -> &[u8] {
+        self.group_context.get_group_id()
+    }
+    pub fn get_epoch(&self) -> u64 {
+        self.group_context.get_epoch()
+    }
+    pub fn get_group_context(&self) -> &GroupContext {
+        &self.group_context
+    }
+    pub fn get_resumption_secret(&self) -> &[u8] {
+        &self.resumption_secret
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EpochHistory {
+    max_epochs: usize,
+    keep_secret_trees: bool,
+    /// Ordered from the oldest to the newest epoch.
+    epochs: VecDeque<PastEpoch>,
+}
+
+impl Default for EpochHistory {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_PAST_EPOCHS, false)
+    }
+}
+
+impl EpochHistory {
+    /// Keep up to `max_epochs` past epochs. The secret trees of past epochs
+    /// are only kept if `keep_secret_trees` is set.
+    pub fn new(max_epochs: usize, keep_secret_trees: bool) -> Self {
+        Self {
+            max_epochs,
+            keep_secret_trees,
+            epochs: VecDeque::new(),
+        }
+    }
+
+    pub fn get_max_epochs(&self) -> usize {
+        self.max_epochs
+    }
+    /// Whether secret trees should be kept for late application messages.
+    pub fn keeps_secret_trees(&self) -> bool {
+        self.keep_secret_trees
+    }
+    pub fn len(&self) -> usize {
+        self.epochs.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.epochs.is_empty()
+    }
+
+    /// Change the limits. Epochs over the new limit are evicted right away.
+    pub fn set_limits(&mut self, max_epochs: usize, keep_secret_trees: bool) {
+        self.max_epochs = max_epochs;
+        self.keep_secret_trees = keep_secret_trees;
+        if !keep_secret_trees {
+            for past_epoch in self.epochs.iter_mut() {
+                past_epoch.late_messages = None;
+            }
+        }
+        self.evict();
+    }
+
+    /// Add the epoch that just ended.
+    pub(crate) fn push(&mut self, mut past_epoch: PastEpoch) {
+        if !self.keep_secret_trees {
+            past_epoch.late_messages = None;
+        }
+        self.epochs.push_back(past_epoch);
+        self.evict();
+    }
+
+    fn evict(&mut self) {
+        while self.epochs.len() > self.max_epochs {
+            self.epochs.pop_front();
+        }
+    }
+
+    /// The past epoch `epoch` of the group `group_id`.
+    pub fn get(&self, group_id: &[u8], epoch: u64) -> Option<&PastEpoch> {
+        self.epochs
+            .iter()
+            .find(|e| e.get_group_id() == group_id && e.get_epoch() == epoch)
+    }
+
+    pub(crate) fn get_mut(&mut self, group_id: &[u8], epoch: u64) -> Option<&mut PastEpoch> {
+        self.epochs
+            .iter_mut()
+            .find(|e| e.get_group_id() == group_id && e.get_epoch() == epoch)
+    }
+
+    /// The resumption secret of the past epoch `epoch` of `group_id`.
+    pub fn get_resumption_secret(&self, group_id: &[u8], epoch: u64) -> Option<&[u8]> {
+        self.get(group_id, epoch).map(|e| e.get_resumption_secret())
+    }
+}
+
+/// Resumption PSKs of the retained epochs.
+impl PskStore for EpochHistory {
+    fn get_psk(&self, psk: &Psk) -> Option<Vec<u8>> {
+        match psk {
+            Psk::Resumption {
+                psk_group_id,
+                psk_epoch,
+                ..
+            } => self
+                .get_resumption_secret(psk_group_id, *psk_epoch)
+                .map(|s| s.to_vec()),
+            Psk::External { .. } => None,
+        }
+    }
+}
@@ -0,0 +1,1079 @@
+//! A group and its state in the current epoch.
+//!
+//! `Group` ties the building blocks of this crate together. Handshake
+//! messages are sent as `MLSPlaintext`, application messages are encrypted
+//! with the secret tree of the epoch.
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use evercrypt::rand_util::get_random_vec;
+
+use crate::ciphersuites::Name;
+use crate::ciphertext::{Error as CiphertextError, MLSCiphertext};
+use crate::clock::{Clock, SystemClock};
+use crate::commit::{
+    confirmation_tag, create_commit, create_external_commit, interim_transcript_hash_from_tag,
+    process_commit, CommitParams, CreateExternalCommitResult, Error as CommitError,
+    ExternalCommitParams, ProcessCommitParams, ProcessCommitResult,
+};
+use crate::credentials::{Credential, Error as CredentialError};
+use crate::extensions::{Extension, ExternalPubExtension, RatchetTreeExtension};
+use crate::framing::{ContentType, MLSPlaintext, MLSPlaintextContent, Sender};
+use crate::group_context::GroupContext;
+use crate::key_package::{KeyPackage, ProtocolVersion};
+use crate::key_schedule::{EpochSecrets, KeySchedule};
+use crate::proposal::{
+    allowed_from_external_sender, AddProposal, GroupContextExtensionsProposal,
+    PreSharedKeyProposal, Proposal, ReInitProposal, RemoveProposal, UpdateProposal,
+};
+use crate::proposal_store::{Error as ProposalStoreError, ProposalStore};
+use crate::psk::{PreSharedKeyId, Psk, PskStore, ResumptionPskUsage};
+use crate::secret_tree::SecretTree;
+use crate::tree::{Error as TreeError, StagedTreeDiff, Tree};
+use crate::welcome::{
+    process_welcome, Error as WelcomeError, GroupInfo, ProcessWelcomeResult, Welcome,
+};
+
+mod authenticator;
+pub use authenticator::*;
+
+mod events;
+pub use events::*;
+
+mod history;
+pub use history::*;
+
+mod pending_commit;
+pub use pending_commit::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// We were removed from the group.
+    Inactive,
+    WrongGroup,
+    WrongEpoch,
+    InvalidSender,
+    InvalidSignature,
+    InvalidMembershipTag,
+    /// Application messages must be encrypted.
+    UnencryptedApplicationMessage,
+    /// Only application messages are encrypted.
+    NotAnApplicationMessage,
+    /// A ReInit was committed. The group can only be used to create or join
+    /// its successor.
+    ReInitPending,
+    /// No ReInit was committed.
+    NoReInit,
+    /// The successor group doesn't match the ReInit proposal.
+    ReInitMismatch,
+    /// The `Welcome` of a successor group or subgroup doesn't inject the
+    /// resumption secret of this group.
+    MissingResumptionPsk,
+    /// A member of a subgroup isn't a member of this group.
+    NotAMember,
+    /// External senders can't send proposals of this type.
+    ExternalProposalNotAllowed,
+    /// An exported secret can be at most 255 times the hash length.
+    ExportTooLong,
+    CommitError(CommitError),
+    WelcomeError(WelcomeError),
+    ProposalStoreError(ProposalStoreError),
+    CiphertextError(CiphertextError),
+    TreeError(TreeError),
+    CredentialError(CredentialError),
+}
+
+impl From<CommitError> for Error {
+    fn from(e: CommitError) -> Self {
+        Error::CommitError(e)
+    }
+}
+
+impl From<WelcomeError> for Error {
+    fn from(e: WelcomeError) -> Self {
+        Error::WelcomeError(e)
+    }
+}
+
+impl From<ProposalStoreError> for Error {
+    fn from(e: ProposalStoreError) -> Self {
+        Error::ProposalStoreError(e)
+    }
+}
+
+impl From<CiphertextError> for Error {
+    fn from(e: CiphertextError) -> Self {
+        Error::CiphertextError(e)
+    }
+}
+
+impl From<TreeError> for Error {
+    fn from(e: TreeError) -> Self {
+        Error::TreeError(e)
+    }
+}
+
+impl From<CredentialError> for Error {
+    fn from(e: CredentialError) -> Self {
+        Error::CredentialError(e)
+    }
+}
+
+#[derive(Debug)]
+pub struct Group {
+    tree: Tree,
+    group_context: GroupContext,
+    epoch_secrets: EpochSecrets,
+    interim_transcript_hash: Vec<u8>,
+    /// The confirmation tag of the commit that started the epoch.
+    confirmation_tag: Vec<u8>,
+    own_leaf: u32,
+    proposal_store: ProposalStore,
+    secret_tree: SecretTree,
+    /// External PSKs by their `psk_id`.
+    external_psks: HashMap<Vec<u8>, Vec<u8>>,
+    history: EpochHistory,
+    /// Set once a ReInit was committed.
+    reinit: Option<ReInitProposal>,
+    active: bool,
+    /// Key packages are validated at the current time of the clock.
+    clock: Rc<dyn Clock>,
+}
+
+/// The PSKs a group can use: external PSKs, the resumption secret of the
+/// current epoch and those of the retained past epochs.
+struct GroupPsks<'a> {
+    group: &'a Group,
+}
+
+impl<'a> PskStore for GroupPsks<'a> {
+    fn get_psk(&self, psk: &Psk) -> Option<Vec<u8>> {
+        let group = self.group;
+        match psk {
+            Psk::External { .. } => group.external_psks.get_psk(psk),
+            Psk::Resumption {
+                psk_group_id,
+                psk_epoch,
+                ..
+            } if psk_group_id.as_slice() == group.get_group_id()
+                && *psk_epoch == group.get_epoch() =>
+            {
+                Some(group.epoch_secrets.resumption_secret.clone())
+            }
+            Psk::Resumption { .. } => group.history.get_psk(psk),
+        }
+    }
+}
+
+impl Group {
+    /// Install the state of a new epoch.
+    fn new(
+        tree: Tree,
+        group_context: GroupContext,
+        epoch_secrets: EpochSecrets,
+        interim_transcript_hash: Vec<u8>,
+        confirmation_tag: Vec<u8>,
+        own_leaf: u32,
+    ) -> Self {
+        let secret_tree = SecretTree::new(
+            tree.get_ciphersuite(),
+            &epoch_secrets.encryption_secret,
+            tree.num_leaves(),
+        );
+        let proposal_store =
+            ProposalStore::new(group_context.get_group_id(), group_context.get_epoch());
+        Self {
+            tree,
+            group_context,
+            epoch_secrets,
+            interim_transcript_hash,
+            confirmation_tag,
+            own_leaf,
+            proposal_store,
+            secret_tree,
+            external_psks: HashMap::new(),
+            history: EpochHistory::default(),
+            reinit: None,
+            active: true,
+            clock: Rc::new(SystemClock),
+        }
+    }
+
+    /// Move to the next epoch.
+    /// Fails without changing the group if the tree diff wasn't created from
+    /// the current tree.
+    fn merge_epoch(
+        &mut self,
+        tree_diff: StagedTreeDiff,
+        group_context: GroupContext,
+        epoch_secrets: EpochSecrets,
+        interim_transcript_hash: Vec<u8>,
+        confirmation_tag: Vec<u8>,
+    ) -> Result<(), Error> {
+        let past_epoch = self.past_epoch();
+        self.tree.merge_diff(tree_diff)?;
+        self.history.push(past_epoch);
+
+        self.secret_tree = SecretTree::new(
+            self.tree.get_ciphersuite(),
+            &epoch_secrets.encryption_secret,
+            self.tree.num_leaves(),
+        );
+        self.proposal_store.set_epoch(group_context.get_epoch());
+        self.group_context = group_context;
+        self.epoch_secrets = epoch_secrets;
+        self.interim_transcript_hash = interim_transcript_hash;
+        self.confirmation_tag = confirmation_tag;
+        Ok(())
+    }
+
+    /// What's kept of the current epoch once the group moves on.
+    fn past_epoch(&self) -> PastEpoch {
+        let late_messages = if self.history.keeps_secret_trees() {
+            let credentials = (0..self.tree.num_leaves())
+                .map(|i| self.tree.get_key_package(i).map(|kp| kp.get_credential().clone()))
+                .collect();
+            Some(LateMessageSecrets {
+                secret_tree: self.secret_tree.clone(),
+                sender_data_secret: self.epoch_secrets.sender_data_secret.clone(),
+                credentials,
+            })
+        } else {
+            None
+        };
+        PastEpoch {
+            group_context: self.group_context.clone(),
+            resumption_secret: self.epoch_secrets.resumption_secret.clone(),
+            late_messages,
+        }
+    }
+
+    // Section 12 Group Creation
+    /// Create a new group with `group_id` that has us as its only member.
+    /// `key_package` must hold the private init key.
+    pub fn create(group_id: &[u8], key_package: KeyPackage) -> Result<Self, Error> {
+        Self::create_with_extensions(group_id, key_package, &[])
+    }
+
+    /// Create a new group with the group context `extensions`, e.g. the
+    /// `external_senders` that may send proposals to the group.
+    pub fn create_with_extensions(
+        group_id: &[u8],
+        key_package: KeyPackage,
+        extensions: &[Extension],
+    ) -> Result<Self, Error> {
+        let ciphersuite = key_package.get_cipher_suite().clone();
+        let key_schedule = KeySchedule::new(&ciphersuite);
+        let mut tree = Tree::new(*ciphersuite.get_name());
+        let own_leaf = tree.add_member(key_package)?;
+        let group_context = GroupContext::new(group_id, 0, &tree.tree_hash()?, &[], extensions);
+
+        // The first epoch starts from a fresh random init secret.
+        let init_secret = get_random_vec(ciphersuite.hash_length());
+        let joiner_secret = key_schedule.joiner_secret(&init_secret, &key_schedule.zero_secret());
+        let epoch_secrets = key_schedule.epoch_secrets(&joiner_secret, None, &group_context);
+
+        // There is no commit for the first epoch; its confirmation tag is
+        // computed over the empty confirmed transcript hash.
+        let confirmation_tag = confirmation_tag(&ciphersuite, &epoch_secrets.confirmation_key, &[]);
+        let interim_transcript_hash =
+            interim_transcript_hash_from_tag(&ciphersuite, &[], &confirmation_tag);
+        Ok(Self::new(
+            tree,
+            group_context,
+            epoch_secrets,
+            interim_transcript_hash,
+            confirmation_tag,
+            own_leaf,
+        ))
+    }
+
+    /// Join a group with a `Welcome`, see `process_welcome`.
+    /// `external_psks` are the external PSKs we share with the group by
+    /// their `psk_id`. They are kept for later epochs.
+    /// The key packages in the tree are validated at the system time.
+    pub fn join(
+        welcome: &Welcome,
+        key_package: &KeyPackage,
+        ratchet_tree: Option<&Tree>,
+        external_psks: HashMap<Vec<u8>, Vec<u8>>,
+    ) -> Result<Self, Error> {
+        let joined = process_welcome(
+            welcome,
+            key_package,
+            ratchet_tree,
+            &external_psks,
+            &SystemClock,
+        )?;
+        let mut group = Self::from_welcome(joined);
+        group.external_psks = external_psks;
+        Ok(group)
+    }
+
+    fn from_welcome(joined: ProcessWelcomeResult) -> Self {
+        Self::new(
+            joined.tree,
+            joined.group_context,
+            joined.epoch_secrets,
+            joined.interim_transcript_hash,
+            joined.confirmation_tag,
+            joined.own_leaf,
+        )
+    }
+
+    // Section 11.2.1 External Initialization
+    /// Join a group with an external commit to the published `group_info`,
+    /// see `create_external_commit`. With `resync` our old leaf is removed.
+    /// The group is in the epoch of the commit, which has to be sent to the
+    /// members. The key packages are validated at the system time.
+    pub fn join_external(
+        group_info: &GroupInfo,
+        ratchet_tree: Option<&Tree>,
+        key_package: KeyPackage,
+        resync: bool,
+    ) -> Result<(Self, MLSPlaintext), Error> {
+        let CreateExternalCommitResult {
+            plaintext,
+            tree,
+            group_context,
+            epoch_secrets,
+            interim_transcript_hash,
+            own_leaf,
+        } = create_external_commit(ExternalCommitParams {
+            group_info,
+            ratchet_tree,
+            key_package,
+            resync,
+            authenticated_data: &[],
+            clock: &SystemClock,
+        })?;
+        let confirmation_tag = plaintext
+            .get_confirmation_tag()
+            .expect("own commits always have a confirmation tag")
+            .to_vec();
+        let group = Self::new(
+            tree,
+            group_context,
+            epoch_secrets,
+            interim_transcript_hash,
+            confirmation_tag,
+            own_leaf,
+        );
+        Ok((group, plaintext))
+    }
+
+    pub fn get_group_id(&self) -> &[u8] {
+        self.group_context.get_group_id()
+    }
+    pub fn get_epoch(&self) -> u64 {
+        self.group_context.get_epoch()
+    }
+    pub fn get_group_context(&self) -> &GroupContext {
+        &self.group_context
+    }
+    pub fn get_tree(&self) -> &Tree {
+        &self.tree
+    }
+    pub fn get_own_leaf(&self) -> u32 {
+        self.own_leaf
+    }
+    pub fn get_proposal_store(&self) -> &ProposalStore {
+        &self.proposal_store
+    }
+    /// The ReInit proposal once it was committed.
+    pub fn get_reinit(&self) -> Option<&ReInitProposal> {
+        self.reinit.as_ref()
+    }
+    /// The retained past epochs.
+    pub fn get_history(&self) -> &EpochHistory {
+        &self.history
+    }
+    /// The epoch authenticator of the current epoch. It's the same for all
+    /// members and can be compared out of band.
+    pub fn get_epoch_authenticator(&self) -> &[u8] {
+        &self.epoch_secrets.authentication_secret
+    }
+    /// The epoch authenticator as a safety number, see `safety_number`.
+    pub fn safety_number(&self) -> String {
+        safety_number(&self.epoch_secrets.authentication_secret)
+    }
+    /// False once we were removed from the group.
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Make the external PSK `psk_id` known to the group.
+    /// PSKs can only be used in commits if all members know them.
+    pub fn add_external_psk(&mut self, psk_id: &[u8], secret: &[u8]) {
+        self.external_psks.insert(psk_id.to_vec(), secret.to_vec());
+    }
+
+    /// Keep up to `max_epochs` past epochs for resumption PSKs, and their
+    /// secret trees for late application messages if `keep_secret_trees`
+    /// is set.
+    pub fn set_history_limits(&mut self, max_epochs: usize, keep_secret_trees: bool) {
+        self.history.set_limits(max_epochs, keep_secret_trees);
+    }
+
+    /// Validate key packages at the current time of `clock` instead of the
+    /// system time. Successor groups and subgroups use the same clock.
+    pub fn set_clock(&mut self, clock: Rc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    // Section 8.5 Exporters
+    /// Export a secret of `length` bytes from the current epoch for the
+    /// application, e.g. to derive media encryption keys. Different `label`s
+    /// and `context`s give independent secrets.
+    pub fn export_secret(
+        &self,
+        label: &str,
+        context: &[u8],
+        length: usize,
+    ) -> Result<Vec<u8>, Error> {
+        self.check_active()?;
+        let ciphersuite = self.tree.get_ciphersuite();
+        if length > 255 * ciphersuite.hash_length() {
+            return Err(Error::ExportTooLong);
+        }
+        Ok(KeySchedule::new(ciphersuite).export(
+            &self.epoch_secrets.exporter_secret,
+            label,
+            context,
+            length,
+        ))
+    }
+
+    /// The signed GroupInfo of the current epoch for external joiners.
+    /// It carries the `external_pub` key and, with `include_ratchet_tree`,
+    /// the public tree.
+    pub fn group_info(&self, include_ratchet_tree: bool) -> Result<GroupInfo, Error> {
+        self.check_sendable()?;
+        let key_schedule = KeySchedule::new(self.tree.get_ciphersuite());
+        let (_, external_pub) = key_schedule.external_key_pair(&self.epoch_secrets.external_secret);
+        let mut other_extensions = vec![Extension::ExternalPub(ExternalPubExtension::new(
+            &external_pub,
+        ))];
+        if include_ratchet_tree {
+            other_extensions.push(Extension::RatchetTree(RatchetTreeExtension::new(&self.tree)?));
+        }
+        Ok(GroupInfo::new(
+            &self.group_context,
+            other_extensions,
+            &self.confirmation_tag,
+            self.own_leaf,
+            &self.own_credential()?,
+        )?)
+    }
+
+    fn psks(&self) -> GroupPsks<'_> {
+        GroupPsks { group: self }
+    }
+
+    fn check_active(&self) -> Result<(), Error> {
+        if self.active {
+            Ok(())
+        } else {
+            Err(Error::Inactive)
+        }
+    }
+
+    /// Check that we can still send messages in the group.
+    fn check_sendable(&self) -> Result<(), Error> {
+        self.check_active()?;
+        match self.reinit {
+            Some(_) => Err(Error::ReInitPending),
+            None => Ok(()),
+        }
+    }
+
+    fn own_credential(&self) -> Result<Credential, Error> {
+        Ok(self
+            .tree
+            .get_key_package(self.own_leaf)
+            .ok_or(TreeError::BlankLeaf)?
+            .get_credential()
+            .clone())
+    }
+
+    /// Frame, sign and tag `content` from us in the current epoch.
+    fn frame(
+        &self,
+        content: MLSPlaintextContent,
+        authenticated_data: &[u8],
+    ) -> Result<MLSPlaintext, Error> {
+        let mut plaintext = MLSPlaintext::new(
+            self.get_group_id(),
+            self.get_epoch(),
+            Sender::Member(self.own_leaf),
+            authenticated_data,
+            content,
+        );
+        plaintext.sign(&self.own_credential()?, &self.group_context)?;
+        Ok(plaintext)
+    }
+
+    /// Create a proposal and add it to our own proposal store.
+    fn propose(&mut self, proposal: Proposal) -> Result<MLSPlaintext, Error> {
+        self.check_sendable()?;
+        let mut plaintext = self.frame(MLSPlaintextContent::Proposal(proposal), &[])?;
+        plaintext.set_membership_tag(
+            self.tree.get_ciphersuite(),
+            &self.epoch_secrets.membership_key,
+            &self.group_context,
+        );
+        self.proposal_store
+            .add(self.tree.get_ciphersuite(), &plaintext)?;
+        Ok(plaintext)
+    }
+
+    /// Propose to add the owner of `key_package`.
+    pub fn propose_add(&mut self, key_package: KeyPackage) -> Result<MLSPlaintext, Error> {
+        self.propose(Proposal::Add(AddProposal::new(key_package)))
+    }
+
+    /// Propose to replace our leaf with `key_package`.
+    /// `key_package` must hold the private init key.
+    pub fn propose_update(&mut self, key_package: KeyPackage) -> Result<MLSPlaintext, Error> {
+        self.propose(Proposal::Update(UpdateProposal::new(key_package)))
+    }
+
+    /// Propose to remove the member at `leaf_index`.
+    pub fn propose_remove(&mut self, leaf_index: u32) -> Result<MLSPlaintext, Error> {
+        self.propose(Proposal::Remove(RemoveProposal::new(leaf_index)))
+    }
+
+    /// Propose to inject the external PSK `psk_id` into the key schedule of
+    /// the next epoch.
+    pub fn propose_external_psk(&mut self, psk_id: &[u8]) -> Result<MLSPlaintext, Error> {
+        let psk = PreSharedKeyId::with_random_nonce(
+            Psk::External {
+                psk_id: psk_id.to_vec(),
+            },
+            self.tree.get_ciphersuite(),
+        );
+        self.propose(Proposal::PreSharedKey(PreSharedKeyProposal::new(psk)))
+    }
+
+    /// Propose to inject the resumption secret of our epoch `epoch` into the
+    /// key schedule of the next epoch. `epoch` must be the current or a
+    /// retained past epoch.
+    pub fn propose_resumption_psk(&mut self, epoch: u64) -> Result<MLSPlaintext, Error> {
+        let psk = PreSharedKeyId::with_random_nonce(
+            Psk::Resumption {
+                usage: ResumptionPskUsage::Application,
+                psk_group_id: self.get_group_id().to_vec(),
+                psk_epoch: epoch,
+            },
+            self.tree.get_ciphersuite(),
+        );
+        self.propose(Proposal::PreSharedKey(PreSharedKeyProposal::new(psk)))
+    }
+
+    /// Propose to reinitialize the group with new parameters.
+    /// A ReInit must be committed on its own.
+    pub fn propose_reinit(
+        &mut self,
+        group_id: &[u8],
+        version: ProtocolVersion,
+        cipher_suite: Name,
+        extensions: Vec<Extension>,
+    ) -> Result<MLSPlaintext, Error> {
+        self.propose(Proposal::ReInit(ReInitProposal::new(
+            group_id,
+            version,
+            cipher_suite,
+            extensions,
+        )))
+    }
+
+    /// Propose to replace the group context extensions with `extensions`.
+    /// All members must support the `required_capabilities` among them.
+    pub fn propose_group_context_extensions(
+        &mut self,
+        extensions: Vec<Extension>,
+    ) -> Result<MLSPlaintext, Error> {
+        self.propose(Proposal::GroupContextExtensions(
+            GroupContextExtensionsProposal::new(extensions),
+        ))
+    }
+
+    // Section 11.2 Commit
+    /// Commit all proposals in the store and `proposals`.
+    /// Our own updates in the store are replaced by the path.
+    /// The group stays in the current epoch until the returned commit is
+    /// merged with `merge_pending_commit`.
+    pub fn commit(&self, proposals: &[Proposal], force_path: bool) -> Result<PendingCommit, Error> {
+        self.check_sendable()?;
+        let own_sender = Sender::Member(self.own_leaf);
+        let mut force_path = force_path;
+        let mut proposal_refs = Vec::new();
+        for queued in self.proposal_store.proposals() {
+            match (queued.get_sender(), queued.get_proposal()) {
+                (sender, Proposal::Update(_)) if sender == own_sender => force_path = true,
+                _ => proposal_refs.push(queued.get_ref().clone()),
+            }
+        }
+
+        let psks = self.psks();
+        let result = create_commit(
+            CommitParams {
+                tree: &self.tree,
+                group_context: &self.group_context,
+                epoch_secrets: &self.epoch_secrets,
+                interim_transcript_hash: &self.interim_transcript_hash,
+                own_leaf: self.own_leaf,
+                proposal_store: &self.proposal_store,
+                proposal_refs: &proposal_refs,
+                proposals,
+                authenticated_data: &[],
+                psk_store: &psks,
+                clock: &*self.clock,
+            },
+            force_path,
+        )?;
+        let welcome = result.create_welcome(true)?;
+        let change = EpochChange::new(
+            result.group_context.get_epoch(),
+            self.own_leaf,
+            &result.proposals,
+            result.added.iter().map(|(i, _)| *i).collect(),
+        );
+        Ok(PendingCommit {
+            plaintext: result.plaintext,
+            welcome,
+            tree: result.tree.into_staged()?,
+            group_context: result.group_context,
+            epoch_secrets: result.epoch_secrets,
+            interim_transcript_hash: result.interim_transcript_hash,
+            change,
+        })
+    }
+
+    /// Move to the epoch of our own `pending_commit` once it's confirmed.
+    /// It must have been created in the current epoch.
+    pub fn merge_pending_commit(
+        &mut self,
+        pending_commit: PendingCommit,
+    ) -> Result<EpochChange, Error> {
+        self.check_active()?;
+        if pending_commit.plaintext.get_group_id() != self.get_group_id() {
+            return Err(Error::WrongGroup);
+        }
+        if pending_commit.plaintext.get_epoch() != self.get_epoch() {
+            return Err(Error::WrongEpoch);
+        }
+        let confirmation_tag = pending_commit
+            .plaintext
+            .get_confirmation_tag()
+            .expect("own commits always have a confirmation tag")
+            .to_vec();
+        self.merge_epoch(
+            pending_commit.tree,
+            pending_commit.group_context,
+            pending_commit.epoch_secrets,
+            pending_commit.interim_transcript_hash,
+            confirmation_tag,
+        )?;
+        self.reinit = pending_commit.change.reinit.clone();
+        Ok(pending_commit.change)
+    }
+
+    /// Process a handshake message from another member.
+    pub fn process_message(&mut self, plaintext: &MLSPlaintext) -> Result<GroupEvent, Error> {
+        self.check_sendable()?;
+        if plaintext.get_group_id() != self.get_group_id() {
+            return Err(Error::WrongGroup);
+        }
+        if plaintext.get_epoch() != self.get_epoch() {
+            return Err(Error::WrongEpoch);
+        }
+        match plaintext.get_content() {
+            MLSPlaintextContent::Application(_) => Err(Error::UnencryptedApplicationMessage),
+            MLSPlaintextContent::Proposal(_) => self.process_proposal(plaintext),
+            MLSPlaintextContent::Commit(_) => self.process_commit(plaintext),
+        }
+    }
+
+    /// The credential of the external sender at `sender_index`.
+    fn external_sender(&self, sender_index: u32) -> Option<&Credential> {
+        self.group_context
+            .get_extensions()
+            .iter()
+            .find_map(|e| match e {
+                Extension::ExternalSenders(e) => e.get_sender(sender_index),
+                _ => None,
+            })
+    }
+
+    fn process_proposal(&mut self, plaintext: &MLSPlaintext) -> Result<GroupEvent, Error> {
+        let ciphersuite = self.tree.get_ciphersuite();
+        let credential = match plaintext.get_sender() {
+            Sender::Member(leaf_index) if leaf_index != self.own_leaf => {
+                if !plaintext.verify_membership_tag(
+                    ciphersuite,
+                    &self.epoch_secrets.membership_key,
+                    &self.group_context,
+                ) {
+                    return Err(Error::InvalidMembershipTag);
+                }
+                self.tree
+                    .get_key_package(leaf_index)
+                    .ok_or(Error::InvalidSender)?
+                    .get_credential()
+            }
+            // External senders aren't members and can't have a membership tag.
+            Sender::External(sender_index) => {
+                match plaintext.get_content() {
+                    MLSPlaintextContent::Proposal(p) if allowed_from_external_sender(p) => (),
+                    _ => return Err(Error::ExternalProposalNotAllowed),
+                }
+                self.external_sender(sender_index).ok_or(Error::InvalidSender)?
+            }
+            _ => return Err(Error::InvalidSender),
+        };
+        if !plaintext.verify_signature(credential, &self.group_context) {
+            return Err(Error::InvalidSignature);
+        }
+        let proposal_ref = self.proposal_store.add(ciphersuite, plaintext)?;
+        let queued = self
+            .proposal_store
+            .get(&proposal_ref)
+            .expect("The proposal was just added.");
+        Ok(GroupEvent::ProposalReceived(queued.clone()))
+    }
+
+    fn process_commit(&mut self, plaintext: &MLSPlaintext) -> Result<GroupEvent, Error> {
+        let psks = self.psks();
+        let result = process_commit(
+            ProcessCommitParams {
+                tree: &self.tree,
+                group_context: &self.group_context,
+                epoch_secrets: &self.epoch_secrets,
+                interim_transcript_hash: &self.interim_transcript_hash,
+                own_leaf: self.own_leaf,
+                proposal_store: &self.proposal_store,
+                psk_store: &psks,
+                clock: &*self.clock,
+            },
+            plaintext,
+        );
+        let result = match result {
+            Ok(result) => result,
+            Err(CommitError::OwnLeafRemoved) => {
+                self.active = false;
+                return Ok(GroupEvent::Removed);
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let ProcessCommitResult {
+            tree,
+            group_context,
+            epoch_secrets,
+            interim_transcript_hash,
+            committer,
+            proposals,
+            added,
+            ..
+        } = result;
+        let change = EpochChange::new(
+            group_context.get_epoch(),
+            committer,
+            &proposals,
+            added.iter().map(|(i, _)| *i).collect(),
+        );
+        let confirmation_tag = plaintext
+            .get_confirmation_tag()
+            .expect("process_commit checked the confirmation tag")
+            .to_vec();
+        self.merge_epoch(
+            tree.into_staged()?,
+            group_context,
+            epoch_secrets,
+            interim_transcript_hash,
+            confirmation_tag,
+        )?;
+        self.reinit = change.reinit.clone();
+        Ok(GroupEvent::NewEpoch(change))
+    }
+
+    /// Encrypt an application message to the group.
+    pub fn encrypt_application(
+        &mut self,
+        data: &[u8],
+        authenticated_data: &[u8],
+    ) -> Result<MLSCiphertext, Error> {
+        self.check_sendable()?;
+        let plaintext = self.frame(
+            MLSPlaintextContent::Application(data.to_vec()),
+            authenticated_data,
+        )?;
+        Ok(MLSCiphertext::encrypt(
+            &plaintext,
+            self.tree.get_ciphersuite(),
+            &mut self.secret_tree,
+            &self.epoch_secrets.sender_data_secret,
+            0,
+        )?)
+    }
+
+    /// Decrypt an application message from another member and check its
+    /// signature.
+    /// Messages from a past epoch can only be decrypted if the history keeps
+    /// secret trees and the epoch is still retained.
+    pub fn decrypt_application(
+        &mut self,
+        ciphertext: &MLSCiphertext,
+    ) -> Result<ApplicationMessage, Error> {
+        self.check_active()?;
+        if ciphertext.get_group_id() != self.get_group_id() {
+            return Err(Error::WrongGroup);
+        }
+        if ciphertext.get_content_type() != ContentType::Application {
+            return Err(Error::NotAnApplicationMessage);
+        }
+        let ciphersuite = self.tree.get_ciphersuite();
+        let (plaintext, credential, group_context) = if ciphertext.get_epoch() == self.get_epoch()
+        {
+            let plaintext = ciphertext.decrypt(
+                ciphersuite,
+                &mut self.secret_tree,
+                &self.epoch_secrets.sender_data_secret,
+            )?;
+            let credential = match plaintext.get_sender() {
+                Sender::Member(leaf_index) => self
+                    .tree
+                    .get_key_package(leaf_index)
+                    .map(|kp| kp.get_credential()),
+                _ => None,
+            };
+            (plaintext, credential, &self.group_context)
+        } else {
+            let past_epoch = self
+                .history
+                .get_mut(ciphertext.get_group_id(), ciphertext.get_epoch())
+                .ok_or(Error::WrongEpoch)?;
+            let late_messages = past_epoch
+                .late_messages
+                .as_mut()
+                .ok_or(Error::WrongEpoch)?;
+            let plaintext = ciphertext.decrypt(
+                ciphersuite,
+                &mut late_messages.secret_tree,
+                &late_messages.sender_data_secret,
+            )?;
+            let credential = match plaintext.get_sender() {
+                Sender::Member(leaf_index) => late_messages
+                    .credentials
+                    .get(leaf_index as usize)
+                    .and_then(|c| c.as_ref()),
+                _ => None,
+            };
+            (plaintext, credential, &past_epoch.group_context)
+        };
+        let sender = match plaintext.get_sender() {
+            Sender::Member(leaf_index) => leaf_index,
+            _ => return Err(Error::InvalidSender),
+        };
+        let credential = credential.ok_or(Error::InvalidSender)?;
+        if !plaintext.verify_signature(credential, group_context) {
+            return Err(Error::InvalidSignature);
+        }
+        let data = match plaintext.get_content() {
+            MLSPlaintextContent::Application(data) => data.clone(),
+            _ => return Err(Error::NotAnApplicationMessage),
+        };
+        Ok(ApplicationMessage {
+            sender,
+            authenticated_data: plaintext.get_authenticated_data().to_vec(),
+            data,
+        })
+    }
+
+    // Section 8.2 Pre-Shared Keys
+    /// The resumption PSK that ties a successor group to the current epoch
+    /// of this group.
+    fn resumption_psk(&self, usage: ResumptionPskUsage) -> Psk {
+        Psk::Resumption {
+            usage,
+            psk_group_id: self.get_group_id().to_vec(),
+            psk_epoch: self.get_epoch(),
+        }
+    }
+
+    /// Create a new group that resumes this group and add the owners of
+    /// `key_packages`. The first commit of the new group injects the
+    /// resumption secret of this group. It's already merged; the `Welcome`
+    /// has to be sent to the new members.
+    fn create_successor(
+        &self,
+        group_id: &[u8],
+        key_package: KeyPackage,
+        extensions: &[Extension],
+        key_packages: Vec<KeyPackage>,
+        usage: ResumptionPskUsage,
+    ) -> Result<(Group, Option<Welcome>), Error> {
+        let mut group = Self::create_with_extensions(group_id, key_package, extensions)?;
+        group.external_psks = self.external_psks.clone();
+        group.clock = self.clock.clone();
+        group.history.push(self.past_epoch());
+
+        let mut proposals: Vec<Proposal> = key_packages
+            .into_iter()
+            .map(|kp| Proposal::Add(AddProposal::new(kp)))
+            .collect();
+        let psk = PreSharedKeyId::with_random_nonce(
+            self.resumption_psk(usage),
+            group.tree.get_ciphersuite(),
+        );
+        proposals.push(Proposal::PreSharedKey(PreSharedKeyProposal::new(psk)));
+        let pending_commit = group.commit(&proposals, false)?;
+        let welcome = pending_commit.welcome.clone();
+        group.merge_pending_commit(pending_commit)?;
+        Ok((group, welcome))
+    }
+
+    /// Join a group that resumes this group. The `Welcome` must inject the
+    /// resumption secret of the current epoch with `usage`.
+    fn join_successor(
+        &self,
+        welcome: &Welcome,
+        key_package: &KeyPackage,
+        ratchet_tree: Option<&Tree>,
+        usage: ResumptionPskUsage,
+    ) -> Result<Group, Error> {
+        let joined = process_welcome(
+            welcome,
+            key_package,
+            ratchet_tree,
+            &self.psks(),
+            &*self.clock,
+        )?;
+        let resumption_psk = self.resumption_psk(usage);
+        if !joined.psks.iter().any(|p| *p.get_psk() == resumption_psk) {
+            return Err(Error::MissingResumptionPsk);
+        }
+        let mut group = Self::from_welcome(joined);
+        group.external_psks = self.external_psks.clone();
+        group.clock = self.clock.clone();
+        group.history.push(self.past_epoch());
+        Ok(group)
+    }
+
+    // Section 11.1.5 ReInit
+    /// Check that the successor group is the one described by the ReInit.
+    fn check_reinit_group(&self, group: &Group, version: ProtocolVersion) -> Result<(), Error> {
+        let reinit = self.reinit.as_ref().ok_or(Error::NoReInit)?;
+        if group.get_group_id() != reinit.get_group_id()
+            || version != reinit.get_version()
+            || *group.tree.get_ciphersuite().get_name() != reinit.get_cipher_suite()
+            || group.group_context.get_extensions() != reinit.get_extensions()
+        {
+            return Err(Error::ReInitMismatch);
+        }
+        Ok(())
+    }
+
+    /// Create the successor group once a ReInit was committed and add the
+    /// owners of `key_packages`. `key_package` is ours and must use the
+    /// version and ciphersuite of the ReInit proposal.
+    pub fn create_reinit_group(
+        &self,
+        key_package: KeyPackage,
+        key_packages: Vec<KeyPackage>,
+    ) -> Result<(Group, Option<Welcome>), Error> {
+        self.check_active()?;
+        let reinit = self.reinit.as_ref().ok_or(Error::NoReInit)?;
+        if key_package.get_version() != reinit.get_version()
+            || *key_package.get_cipher_suite().get_name() != reinit.get_cipher_suite()
+        {
+            return Err(Error::ReInitMismatch);
+        }
+        self.create_successor(
+            reinit.get_group_id(),
+            key_package,
+            reinit.get_extensions(),
+            key_packages,
+            ResumptionPskUsage::ReInit,
+        )
+    }
+
+    /// Join the successor group once a ReInit was committed.
+    /// The `Welcome` must be for the group described by the ReInit proposal
+    /// and inject the resumption secret of this group.
+    pub fn join_reinit_group(
+        &self,
+        welcome: &Welcome,
+        key_package: &KeyPackage,
+        ratchet_tree: Option<&Tree>,
+    ) -> Result<Group, Error> {
+        self.check_active()?;
+        if self.reinit.is_none() {
+            return Err(Error::NoReInit);
+        }
+        let group =
+            self.join_successor(welcome, key_package, ratchet_tree, ResumptionPskUsage::ReInit)?;
+        self.check_reinit_group(&group, key_package.get_version())?;
+        Ok(group)
+    }
+
+    /// Whether `identity` is a member of the group.
+    fn is_member(&self, identity: &[u8]) -> bool {
+        self.tree
+            .members()
+            .any(|(_, kp)| kp.get_credential().get_identity() == identity)
+    }
+
+    /// Branch a subgroup with `group_id` that has us and the owners of
+    /// `key_packages`. `key_package` is ours; all other key packages must
+    /// be from members of this group.
+    /// The subgroup injects the resumption secret of the current epoch so
+    /// the members can confirm that they share the history of this group.
+    pub fn branch(
+        &self,
+        group_id: &[u8],
+        key_package: KeyPackage,
+        key_packages: Vec<KeyPackage>,
+    ) -> Result<(Group, Option<Welcome>), Error> {
+        self.check_active()?;
+        let own_identity = self.own_credential()?.get_identity().to_vec();
+        if key_package.get_credential().get_identity() != own_identity.as_slice()
+            || key_packages
+                .iter()
+                .any(|kp| !self.is_member(kp.get_credential().get_identity()))
+        {
+            return Err(Error::NotAMember);
+        }
+        self.create_successor(
+            group_id,
+            key_package,
+            self.group_context.get_extensions(),
+            key_packages,
+            ResumptionPskUsage::Branch,
+        )
+    }
+
+    /// Join a subgroup branched from this group.
+    /// The `Welcome` must inject the resumption secret of the current epoch
+    /// and all members of the subgroup must be members of this group.
+    pub fn join_branch(
+        &self,
+        welcome: &Welcome,
+        key_package: &KeyPackage,
+        ratchet_tree: Option<&Tree>,
+    ) -> Result<Group, Error> {
+        self.check_active()?;
+        let group =
+            self.join_successor(welcome, key_package, ratchet_tree, ResumptionPskUsage::Branch)?;
+        if group
+            .tree
+            .members()
+            .any(|(_, kp)| !self.is_member(kp.get_credential().get_identity()))
+        {
+            return Err(Error::NotAMember);
+        }
+        Ok(group)
+    }
+}
@@ -0,0 +1,41 @@
+//! Commits that were sent but not yet confirmed.
+
+use crate::framing::MLSPlaintext;
+use crate::group::EpochChange;
+use crate::group_context::GroupContext;
+use crate::key_schedule::EpochSecrets;
+use crate::tree::StagedTreeDiff;
+use crate::welcome::Welcome;
+
+/// A commit we created together with the state of the epoch it leads to.
+/// The group stays in its current epoch until the commit is merged with
+/// `Group::merge_pending_commit`. If another member's commit for the same
+/// epoch wins, the pending commit is simply dropped.
+#[derive(Debug)]
+pub struct PendingCommit {
+    pub(crate) plaintext: MLSPlaintext,
+    pub(crate) welcome: Option<Welcome>,
+    pub(crate) tree: StagedTreeDiff,
+    pub(crate) group_context: GroupContext,
+    pub(crate) epoch_secrets: EpochSecrets,
+    pub(crate) interim_transcript_hash: Vec<u8>,
+    pub(crate) change: EpochChange,
+}
+
+impl PendingCommit {
+    /// The commit to send to the group.
+    pub fn get_plaintext(&self) -> &MLSPlaintext {
+        &self.plaintext
+    }
+    /// The `Welcome` to send to new members, if there are any.
+    pub fn get_welcome(&self) -> Option<&Welcome> {
+        self.welcome.as_ref()
+    }
+    /// The group context of the next epoch.
+    pub fn get_group_context(&self) -> &GroupContext {
+        &self.group_context
+    }
+    pub fn get_change(&self) -> &EpochChange {
+        &self.change
+    }
+}
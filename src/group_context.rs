@@ -0,0 +1,79 @@
+//! The group context
+
+use crate::encode_util::*;
+use crate::extensions::{decode_extensions, encode_extensions, Extension};
+use crate::traits::{Decode, DecodeError, Encode};
+
+// Section 8.1 Group Context
+// struct {
+//     opaque group_id<0..255>;
+//     uint64 epoch;
+//     opaque tree_hash<0..255>;
+//     opaque confirmed_transcript_hash<0..255>;
+//     Extension extensions<0..2^32-1>;
+// } GroupContext;
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupContext {
+    pub(crate) group_id: Vec<u8>,
+    pub(crate) epoch: u64,
+    pub(crate) tree_hash: Vec<u8>,
+    pub(crate) confirmed_transcript_hash: Vec<u8>,
+    pub(crate) extensions: Vec<Extension>,
+}
+
+impl GroupContext {
+    pub fn new(
+        group_id: &[u8],
+        epoch: u64,
+        tree_hash: &[u8],
+        confirmed_transcript_hash: &[u8],
+        extensions: &[Extension],
+    ) -> Self {
+        Self {
+            group_id: group_id.to_vec(),
+            epoch,
+            tree_hash: tree_hash.to_vec(),
+            confirmed_transcript_hash: confirmed_transcript_hash.to_vec(),
+            extensions: extensions.to_vec(),
+        }
+    }
+    pub fn get_group_id(&self) -> &[u8] {
+        &self.group_id
+    }
+    pub fn get_epoch(&self) -> u64 {
+        self.epoch
+    }
+    pub fn get_tree_hash(&self) -> &[u8] {
+        &self.tree_hash
+    }
+    pub fn get_confirmed_transcript_hash(&self) -> &[u8] {
+        &self.confirmed_transcript_hash
+    }
+    pub fn get_extensions(&self) -> &[Extension] {
+        &self.extensions
+    }
+}
+
+impl Encode for GroupContext {
+    fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        encode_slice(LenType::L8, &self.group_id, &mut out);
+        out.extend(self.epoch.encode());
+        encode_slice(LenType::L8, &self.tree_hash, &mut out);
+        encode_slice(LenType::L8, &self.confirmed_transcript_hash, &mut out);
+        out.extend(encode_extensions(&self.extensions));
+        out
+    }
+}
+
+impl Decode for GroupContext {
+    fn decode(cursor: &mut Cursor) -> Result<Self, DecodeError> {
+        Ok(Self {
+            group_id: decode_slice(LenType::L8, cursor)?,
+            epoch: u64::decode(cursor)?,
+            tree_hash: decode_slice(LenType::L8, cursor)?,
+            confirmed_transcript_hash: decode_slice(LenType::L8, cursor)?,
+            extensions: decode_extensions(cursor)?,
+        })
+    }
+}
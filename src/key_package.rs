@@ -1,6 +1,12 @@
 use crate::{
-    ciphersuites::Ciphersuite, credentials::Credential, extensions::*, traits::Encode, encode_util::{encode_slice, LenType}
+    ciphersuites::Ciphersuite,
+    clock::Clock,
+    credentials::{Credential, Error as CredentialError},
+    encode_util::{decode_slice, encode_slice, Cursor, LenType},
+    extensions::{Error as ExtensionError, *},
+    traits::{Decode, DecodeError, Encode},
 };
+use evercrypt::digest;
 use hpke::Hpke;
 
 // Sec. 7 Key Packages
@@ -19,10 +25,54 @@ use hpke::Hpke;
 //     opaque signature<0..2^16-1>;
 // } KeyPackage;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum ProtocolVersion {
-    Reserved = 0,
-    Mls10 = 1,
+    Reserved,
+    Mls10,
+    Unknown(u8),
+}
+
+impl ProtocolVersion {
+    /// Decode a version this implementation speaks. Other versions are only
+    /// accepted where they are advertised, e.g. in a capabilities extension.
+    pub(crate) fn decode_supported(cursor: &mut Cursor) -> Result<Self, DecodeError> {
+        match Self::decode(cursor)? {
+            ProtocolVersion::Mls10 => Ok(ProtocolVersion::Mls10),
+            _ => Err(DecodeError::InvalidValue),
+        }
+    }
+}
+
+impl From<u8> for ProtocolVersion {
+    fn from(v: u8) -> Self {
+        match v {
+            0 => ProtocolVersion::Reserved,
+            1 => ProtocolVersion::Mls10,
+            _ => ProtocolVersion::Unknown(v),
+        }
+    }
+}
+
+impl From<ProtocolVersion> for u8 {
+    fn from(v: ProtocolVersion) -> u8 {
+        match v {
+            ProtocolVersion::Reserved => 0,
+            ProtocolVersion::Mls10 => 1,
+            ProtocolVersion::Unknown(v) => v,
+        }
+    }
+}
+
+impl Encode for ProtocolVersion {
+    fn encode(&self) -> Vec<u8> {
+        vec![u8::from(*self)]
+    }
+}
+
+impl Decode for ProtocolVersion {
+    fn decode(cursor: &mut Cursor) -> Result<Self, DecodeError> {
+        Ok(ProtocolVersion::from(u8::decode(cursor)?))
+    }
 }
 
 impl Default for ProtocolVersion {
@@ -31,13 +81,28 @@ impl Default for ProtocolVersion {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    InvalidExtensions(ExtensionError),
+    InvalidSignature,
+    MissingLifetime,
+    Expired,
+    NotYetValid,
+}
+
+impl From<ExtensionError> for Error {
+    fn from(e: ExtensionError) -> Self {
+        Error::InvalidExtensions(e)
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct KeyPackage {
     version: ProtocolVersion,
     cipher_suite: Ciphersuite,
     public_hpke_init_key: Vec<u8>,
     private_hpke_init_key: Vec<u8>,
-    credential: Box<Credential>,
+    credential: Credential,
     extensions: Vec<Extension>,
     signature: Vec<u8>,
 }
@@ -46,7 +111,7 @@ impl KeyPackage {
     pub fn new(
         version: ProtocolVersion,
         cipher_suite: Ciphersuite,
-        credential: Box<Credential>,
+        credential: Credential,
     ) -> Self {
         let hpke_init_key = hpke::Hpke::new(
             hpke::Mode::Base,
@@ -66,39 +131,288 @@ impl KeyPackage {
         }
     }
 
-    pub(crate) fn add_extension(&mut self, extension: Extension) {
+    /// Create a new signed key package that is valid for `validity` seconds
+    /// from the current time of `clock` on.
+    pub fn new_with_lifetime(
+        version: ProtocolVersion,
+        cipher_suite: Ciphersuite,
+        credential: Credential,
+        validity: u64,
+        clock: &dyn Clock,
+    ) -> Result<Self, CredentialError> {
+        let mut key_package = Self::new(version, cipher_suite, credential);
+        key_package.set_lifetime(LifetimeExtension::from_now(clock, validity));
+        key_package.sign()?;
+        Ok(key_package)
+    }
+
+    /// Add an extension.
+    /// Fails if the extension isn't allowed in key packages or if there's
+    /// already an extension of the same type.
+    pub fn add_extension(&mut self, extension: Extension) -> Result<(), ExtensionError> {
+        check_extension(&self.extensions, &extension, ExtensionContext::KeyPackage)?;
         self.extensions.push(extension);
+        Ok(())
     }
 
-    pub(crate) fn add_extensions(&mut self, extensions: &[Extension]) {
+    /// Add all `extensions`.
+    /// Nothing is added if any of the extensions can't be added.
+    pub fn add_extensions(&mut self, extensions: &[Extension]) -> Result<(), ExtensionError> {
         // XXX: consume?
-        self.extensions.extend_from_slice(extensions);
+        let mut new_extensions = self.extensions.clone();
+        for extension in extensions.iter() {
+            check_extension(&new_extensions, extension, ExtensionContext::KeyPackage)?;
+            new_extensions.push(extension.clone());
+        }
+        self.extensions = new_extensions;
+        Ok(())
+    }
+
+    /// Set `extension`, replacing an extension of the same type if there is one.
+    /// Fails if the extension isn't allowed in key packages.
+    /// Note that the key package has to be signed again afterwards.
+    pub fn set_extension(&mut self, extension: Extension) -> Result<(), ExtensionError> {
+        let extension_type = extension.get_type();
+        if !extension_type.allowed_in(ExtensionContext::KeyPackage) {
+            return Err(ExtensionError::InvalidContext(
+                extension_type,
+                ExtensionContext::KeyPackage,
+            ));
+        }
+        self.replace_extension(extension);
+        Ok(())
+    }
+
+    fn replace_extension(&mut self, extension: Extension) {
+        let extension_type = extension.get_type();
+        match self
+            .extensions
+            .iter_mut()
+            .find(|e| e.get_type() == extension_type)
+        {
+            Some(e) => *e = extension,
+            None => self.extensions.push(extension),
+        }
     }
 
+    pub fn set_capabilities(&mut self, capabilities: CapabilitiesExtension) {
+        self.replace_extension(Extension::Capabilities(capabilities));
+    }
+
+    pub fn set_lifetime(&mut self, lifetime: LifetimeExtension) {
+        self.replace_extension(Extension::Lifetime(lifetime));
+    }
+
+    pub fn set_key_id(&mut self, key_id: KeyIdExtension) {
+        self.replace_extension(Extension::KeyId(key_id));
+    }
+
+    pub fn set_parent_hash(&mut self, parent_hash: ParentHashExtension) {
+        self.replace_extension(Extension::ParentHash(parent_hash));
+    }
+
+    pub fn get_extension(&self, extension_type: ExtensionType) -> Option<&Extension> {
+        self.extensions
+            .iter()
+            .find(|e| e.get_type() == extension_type)
+    }
+
+    pub fn get_extensions(&self) -> &[Extension] {
+        &self.extensions
+    }
+
+    /// Whether the capabilities of the key package cover `required`.
+    /// Without a capabilities extension nothing is supported.
+    pub fn supports(&self, required: &RequiredCapabilitiesExtension) -> bool {
+        match self.get_extension(ExtensionType::Capabilities) {
+            Some(Extension::Capabilities(capabilities)) => capabilities.supports(required),
+            _ => required.is_empty(),
+        }
+    }
+
+    pub(crate) fn get_public_hpke_init_key(&self) -> &[u8] {
+        &self.public_hpke_init_key
+    }
+
+    pub(crate) fn get_private_hpke_init_key(&self) -> &[u8] {
+        &self.private_hpke_init_key
+    }
+
+    /// Replace the HPKE init key pair.
+    /// Note that the key package has to be signed again afterwards.
+    pub(crate) fn set_hpke_init_key(&mut self, private_key: &[u8], public_key: &[u8]) {
+        self.private_hpke_init_key = private_key.to_vec();
+        self.public_hpke_init_key = public_key.to_vec();
+    }
+
+    /// The hash of the encoded key package, used to reference it.
+    pub fn hash(&self) -> Vec<u8> {
+        digest::hash(self.cipher_suite.hash, &self.encode())
+    }
+
+    pub fn get_credential(&self) -> &Credential {
+        &self.credential
+    }
+
+    pub fn get_version(&self) -> ProtocolVersion {
+        self.version
+    }
+
+    pub fn get_cipher_suite(&self) -> &Ciphersuite {
+        &self.cipher_suite
+    }
+
+    /// The encoded key package without signature, i.e. the signature input.
     fn to_bytes(&self) -> Vec<u8> {
         let mut out = Vec::new();
-        out.push(self.version as u8);
+        out.push(u8::from(self.version));
         out.extend(&self.cipher_suite.encode());
-        out.extend(&self.public_hpke_init_key); // This is already encoded.
+        encode_slice(LenType::L16, &self.public_hpke_init_key, &mut out);
         out.extend(self.credential.encode());
         out.extend(encode_extensions(&self.extensions));
         out
     }
 
-    pub(crate) fn sign(&mut self, credential: &Credential) {
-        let sig = credential.credential.sign(&self.to_bytes());
-        self.signature = sig;
+    /// Sign the key package with its credential.
+    /// This has to be called after changing extensions.
+    /// Fails if the credential has no private key.
+    pub fn sign(&mut self) -> Result<(), CredentialError> {
+        self.signature = self.credential.credential.sign(&self.to_bytes())?;
+        Ok(())
     }
 
     pub(crate) fn verify(&self) -> bool {
         self.credential.credential.verify(&self.to_bytes(), &self.signature)
     }
+
+    /// Validate the key package.
+    /// This checks the extensions, the lifetime against the current time of
+    /// `clock`, and the signature.
+    pub fn validate(&self, clock: &dyn Clock) -> Result<(), Error> {
+        validate_extensions(&self.extensions, ExtensionContext::KeyPackage)?;
+        let lifetime = match self.get_extension(ExtensionType::Lifetime) {
+            Some(Extension::Lifetime(lifetime)) => lifetime,
+            _ => return Err(Error::MissingLifetime),
+        };
+        let now = clock.now();
+        if now < lifetime.get_not_before() {
+            return Err(Error::NotYetValid);
+        }
+        if now > lifetime.get_not_after() {
+            return Err(Error::Expired);
+        }
+        if !self.verify() {
+            return Err(Error::InvalidSignature);
+        }
+        Ok(())
+    }
+}
+
+impl Encode for KeyPackage {
+    fn encode(&self) -> Vec<u8> {
+        let mut out = self.to_bytes();
+        encode_slice(LenType::L16, &self.signature, &mut out);
+        out
+    }
 }
 
 impl Encode for &KeyPackage {
     fn encode(&self) -> Vec<u8> {
-        self.to_bytes()
+        (*self).encode()
+    }
+}
+
+/// A decoded key package has no private HPKE init key.
+impl Decode for KeyPackage {
+    fn decode(cursor: &mut Cursor) -> Result<Self, DecodeError> {
+        let version = ProtocolVersion::decode_supported(cursor)?;
+        let cipher_suite = Ciphersuite::decode(cursor)?;
+        let public_hpke_init_key = decode_slice(LenType::L16, cursor)?;
+        let credential = Credential::decode(cursor)?;
+        let extensions = decode_extensions(cursor)?;
+        let signature = decode_slice(LenType::L16, cursor)?;
+        Ok(Self {
+            version,
+            cipher_suite,
+            public_hpke_init_key,
+            private_hpke_init_key: Vec::new(),
+            credential,
+            extensions,
+            signature,
+        })
     }
 }
 
-// TODO: Add extensions
+#[test]
+fn test_key_package_lifetime() {
+    use crate::clock::FixedClock;
+    use crate::credentials::BasicCredential;
+
+    let credential = Credential::basic(BasicCredential::ed25519());
+    let key_package = KeyPackage::new_with_lifetime(
+        ProtocolVersion::Mls10,
+        Ciphersuite::default(),
+        credential,
+        100,
+        &FixedClock(1000),
+    )
+    .unwrap();
+    assert_eq!(key_package.validate(&FixedClock(1000)), Ok(()));
+    assert_eq!(key_package.validate(&FixedClock(1100)), Ok(()));
+    assert_eq!(key_package.validate(&FixedClock(999)), Err(Error::NotYetValid));
+    assert_eq!(key_package.validate(&FixedClock(1101)), Err(Error::Expired));
+
+    // Changing the extensions without signing again invalidates the key package.
+    let mut key_package = key_package;
+    key_package.set_lifetime(LifetimeExtension::new(0, u64::MAX));
+    assert_eq!(
+        key_package.validate(&FixedClock(1)),
+        Err(Error::InvalidSignature)
+    );
+    key_package.sign().unwrap();
+    assert_eq!(key_package.validate(&FixedClock(1)), Ok(()));
+}
+
+#[test]
+fn test_sign_without_private_key() {
+    use crate::credentials::BasicCredential;
+
+    let credential = Credential::basic(BasicCredential::ed25519());
+    let mut key_package =
+        KeyPackage::new(ProtocolVersion::Mls10, Ciphersuite::default(), credential);
+    assert_eq!(key_package.sign(), Ok(()));
+
+    // A decoded credential only has the public key.
+    let mut decoded = KeyPackage::decode_all(&key_package.encode()).unwrap();
+    assert_eq!(decoded.sign(), Err(CredentialError::InvalidPrivateKey));
+}
+
+#[test]
+fn test_unknown_capabilities() {
+    use crate::ciphersuites::Name;
+    use crate::credentials::{BasicCredential, BASIC};
+    use crate::proposal::ProposalType;
+
+    // Capabilities may advertise versions, ciphersuites and types we don't know.
+    let credential = Credential::basic(BasicCredential::ed25519());
+    let mut key_package =
+        KeyPackage::new(ProtocolVersion::Mls10, Ciphersuite::default(), credential);
+    let capabilities = Extension::Capabilities(CapabilitiesExtension::new(
+        vec![ProtocolVersion::Mls10, ProtocolVersion::Unknown(0xff)],
+        vec![*Ciphersuite::default().get_name(), Name::Unknown(0x0a0a)],
+        vec![ExtensionType::Unknown(0x0a0a)],
+        vec![ProposalType::Add, ProposalType::Unknown(0x0a0a)],
+        vec![BASIC, 0x0a0a],
+    ));
+    key_package.set_extension(capabilities.clone()).unwrap();
+    key_package.sign().unwrap();
+    let decoded = KeyPackage::decode_all(&key_package.encode()).unwrap();
+    assert_eq!(decoded.get_extensions(), &[capabilities]);
+
+    // The key package itself has to use a version we know.
+    key_package.version = ProtocolVersion::Unknown(0xff);
+    assert_eq!(
+        KeyPackage::decode_all(&key_package.encode()).err(),
+        Some(DecodeError::InvalidValue)
+    );
+}
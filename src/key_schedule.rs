@@ -30,41 +30,187 @@
 //!                         |
 //!                         V
 //!                   init_secret_[n]
-use crate::ciphersuites::Ciphersuite;
+use crate::ciphersuites::{Ciphersuite, HpkeError};
+use crate::encode_util::*;
+use crate::group_context::GroupContext;
+use crate::traits::Encode;
+use evercrypt::digest::hash;
 use evercrypt::hkdf::{hkdf_expand, hkdf_extract};
-use evercrypt::hmac;
-use hpke;
+
+const EXTERNAL_INIT_LABEL: &[u8] = b"MLS 1.0 external init secret";
 
 #[derive(Default)]
-struct KeySchedule {
+pub(crate) struct KeySchedule {
     ciphersuite: Ciphersuite,
 }
 
-struct Label {
-    group_context_hash: Vec<u8>,
+// struct {
+//     uint16 length = Length;
+//     opaque label<7..255> = "mls10 " + Label;
+//     opaque context<0..2^32-1> = Context;
+// } KDFLabel;
+struct Label<'a> {
     length: u16,
-    label: String, // "mls10 " + Label
-    context: Vec<u8>,
+    label: &'a str,
+    context: &'a [u8],
 }
 
-impl Label {
+impl<'a> Encode for Label<'a> {
     fn encode(&self) -> Vec<u8> {
-        let mut encoded = group_context_hash.clone();
-        encoded.extend(self.length.to_be_bytes());
-        encoded.extend(("mls10"+self.label).into_bytes());
-        encoded.extend()
+        let mut out = self.length.encode();
+        let label = format!("mls10 {}", self.label);
+        encode_slice(LenType::L8, label.as_bytes(), &mut out);
+        encode_slice(LenType::L32, self.context, &mut out);
+        out
     }
 }
 
+/// The secrets derived from the epoch secret.
+#[derive(Debug, Clone, Default)]
+pub struct EpochSecrets {
+    pub(crate) sender_data_secret: Vec<u8>,
+    pub(crate) encryption_secret: Vec<u8>,
+    pub(crate) exporter_secret: Vec<u8>,
+    pub(crate) authentication_secret: Vec<u8>,
+    pub(crate) external_secret: Vec<u8>,
+    pub(crate) confirmation_key: Vec<u8>,
+    pub(crate) membership_key: Vec<u8>,
+    pub(crate) resumption_secret: Vec<u8>,
+    pub(crate) init_secret: Vec<u8>,
+}
+
 impl KeySchedule {
-    fn hkdf_expand_label(&self, secret: &[u8], label: &Label, length: usize) -> Vec<u8> {
-        hkdf_expand(self.kdf, secret, label.encode(), length)
+    pub(crate) fn new(ciphersuite: &Ciphersuite) -> Self {
+        Self {
+            ciphersuite: ciphersuite.clone(),
+        }
     }
-    fn derive_secret(&self, secret: &[u8], label: &str) -> Vec<u8> {
-        self.hkdf_expand_label(secret, label, hmac::get_tag_size(self.kdf))
+
+    // Section 8 Key Schedule
+    // ExpandWithLabel(Secret, Label, Context, Length) =
+    //     KDF.Expand(Secret, KDFLabel, Length)
+    pub(crate) fn expand_with_label(
+        &self,
+        secret: &[u8],
+        label: &str,
+        context: &[u8],
+        length: usize,
+    ) -> Vec<u8> {
+        let info = Label {
+            length: length as u16,
+            label,
+            context,
+        };
+        hkdf_expand(self.ciphersuite.kdf, secret, &info.encode(), length)
     }
+
+    // DeriveSecret(Secret, Label) =
+    //     ExpandWithLabel(Secret, Label, "", KDF.Nh)
+    pub(crate) fn derive_secret(&self, secret: &[u8], label: &str) -> Vec<u8> {
+        self.expand_with_label(secret, label, &[], self.ciphersuite.hash_length())
+    }
+
     fn early_secret(&self, init_secret: &[u8], psk: &[u8]) -> Vec<u8> {
-        hkdf_extract(self.kdf, init_secret, psk)
+        hkdf_extract(self.ciphersuite.kdf, init_secret, psk)
+    }
+
+    /// The all-zero secret of length `KDF.Nh`.
+    pub(crate) fn zero_secret(&self) -> Vec<u8> {
+        vec![0u8; self.ciphersuite.hash_length()]
+    }
+
+    pub(crate) fn joiner_secret(&self, init_secret: &[u8], commit_secret: &[u8]) -> Vec<u8> {
+        hkdf_extract(self.ciphersuite.kdf, init_secret, commit_secret)
+    }
+
+    pub(crate) fn welcome_secret(&self, joiner_secret: &[u8]) -> Vec<u8> {
+        self.derive_secret(joiner_secret, "welcome")
+    }
+
+    // Section 8.5 Exporters
+    // MLS-Exporter(Label, Context, key_length) =
+    //     ExpandWithLabel(DeriveSecret(exporter_secret, Label),
+    //                     "exporter", Hash(Context), key_length)
+    /// Export a secret of `length` bytes for `label` and `context` from the
+    /// `exporter_secret` of an epoch.
+    pub(crate) fn export(
+        &self,
+        exporter_secret: &[u8],
+        label: &str,
+        context: &[u8],
+        length: usize,
+    ) -> Vec<u8> {
+        let secret = self.derive_secret(exporter_secret, label);
+        let context_hash = hash(self.ciphersuite.hash, context);
+        self.expand_with_label(&secret, "exporter", &context_hash, length)
+    }
+
+    // Section 11.2.1 External Initialization
+    // external_priv, external_pub = KEM.DeriveKeyPair(external_secret)
+    /// The key pair `(external_priv, external_pub)` non-members use to
+    /// initialize an external commit.
+    pub(crate) fn external_key_pair(&self, external_secret: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        self.ciphersuite.derive_hpke_key_pair(external_secret)
+    }
+
+    // kem_output, context = SetupBaseS(external_pub, "")
+    // init_secret = context.export("MLS 1.0 external init secret", KDF.Nh)
+    /// The `kem_output` for the ExternalInit proposal and the `init_secret`
+    /// of an external commit.
+    pub(crate) fn external_init(&self, external_pub: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        self.ciphersuite.hpke_export_sender(
+            external_pub,
+            &[],
+            EXTERNAL_INIT_LABEL,
+            self.ciphersuite.hash_length(),
+        )
+    }
+
+    /// The `init_secret` of an external commit from the `kem_output` of its
+    /// ExternalInit proposal.
+    pub(crate) fn external_init_secret(
+        &self,
+        external_secret: &[u8],
+        kem_output: &[u8],
+    ) -> Result<Vec<u8>, HpkeError> {
+        let (external_priv, _) = self.external_key_pair(external_secret);
+        self.ciphersuite.hpke_export_receiver(
+            kem_output,
+            &external_priv,
+            &[],
+            EXTERNAL_INIT_LABEL,
+            self.ciphersuite.hash_length(),
+        )
+    }
+
+    /// Derive the secrets of the epoch described by `group_context` from the
+    /// joiner secret. Without PSK the all-zero secret is used instead.
+    pub(crate) fn epoch_secrets(
+        &self,
+        joiner_secret: &[u8],
+        psk_secret: Option<&[u8]>,
+        group_context: &GroupContext,
+    ) -> EpochSecrets {
+        let zero = self.zero_secret();
+        let psk_secret = psk_secret.unwrap_or(&zero);
+        let member_secret =
+            self.early_secret(&self.derive_secret(joiner_secret, "member"), psk_secret);
+        let epoch_secret = hkdf_extract(
+            self.ciphersuite.kdf,
+            &self.derive_secret(&member_secret, "epoch"),
+            &group_context.encode(),
+        );
+        EpochSecrets {
+            sender_data_secret: self.derive_secret(&epoch_secret, "sender data"),
+            encryption_secret: self.derive_secret(&epoch_secret, "encryption"),
+            exporter_secret: self.derive_secret(&epoch_secret, "exporter"),
+            authentication_secret: self.derive_secret(&epoch_secret, "authentication"),
+            external_secret: self.derive_secret(&epoch_secret, "external"),
+            confirmation_key: self.derive_secret(&epoch_secret, "confirm"),
+            membership_key: self.derive_secret(&epoch_secret, "membership"),
+            resumption_secret: self.derive_secret(&epoch_secret, "resumption"),
+            init_secret: self.derive_secret(&epoch_secret, "init"),
+        }
     }
 }
 
@@ -73,3 +219,28 @@ fn test_early_secret() {
     let schedule = KeySchedule::default();
     let _es = schedule.early_secret(&[], &[]);
 }
+
+#[test]
+fn test_export() {
+    let hex = |s: &str| -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    };
+    let schedule = KeySchedule::default();
+    let exporter_secret: Vec<u8> = (0..32).collect();
+
+    assert_eq!(
+        schedule.export(&exporter_secret, "test", b"context", 32),
+        hex("5744e70943c034c75e50042de382c24d4164b8ff50ef17ef27c55ccee31c548a")
+    );
+    assert_eq!(
+        schedule.export(&exporter_secret, "test", b"context", 16),
+        hex("f4a6bbe9ee3a4dc24e121d7adcf15a15")
+    );
+    assert_eq!(
+        schedule.export(&exporter_secret, "other", b"", 32),
+        hex("bb1ce8962e69df4e4dc1ab1229a6004d3d84dfd08749538a0b2b3a62a2fcb585")
+    );
+}
@@ -1,12 +1,23 @@
 pub mod ciphersuites;
+pub mod ciphertext;
+pub mod clock;
+pub mod commit;
 pub mod credentials;
 mod encode_util;
-mod extensions;
+pub mod extensions;
+pub mod framing;
+pub mod group;
+pub mod group_context;
 pub mod key_package;
+pub mod key_schedule;
 pub mod message;
 pub mod proposal;
+pub mod proposal_store;
+pub mod psk;
+pub mod secret_tree;
 pub mod traits;
 pub mod tree;
 mod util;
+pub mod welcome;
 
 pub mod prelude;
@@ -2,4 +2,26 @@
 //! Include this to get access to all the interesting public functions of this crate.
 
 pub use crate::ciphersuites::Name as CiphersuiteName;
-pub use crate::tree::{Error as TreeError, Tree};
+pub use crate::ciphertext::MLSCiphertext;
+pub use crate::clock::{Clock, FixedClock, SystemClock};
+pub use crate::commit::{
+    create_commit, create_external_commit, process_commit, Commit, CommitParams,
+    CreateCommitResult, CreateExternalCommitResult, ExternalCommitParams, ProcessCommitParams,
+    ProcessCommitResult, ProposalOrRef,
+};
+pub use crate::credentials::{BasicCredential, Credential, CredentialType};
+pub use crate::extensions::*;
+pub use crate::framing::{ContentType, MLSPlaintext, MLSPlaintextContent, Sender};
+pub use crate::group::{
+    safety_number, ApplicationMessage, EpochChange, EpochHistory, Group, GroupEvent, PastEpoch,
+    PendingCommit,
+};
+pub use crate::group_context::GroupContext;
+pub use crate::key_package::{KeyPackage, ProtocolVersion};
+pub use crate::key_schedule::EpochSecrets;
+pub use crate::proposal::*;
+pub use crate::proposal_store::{ProposalRef, ProposalStore, QueuedProposal};
+pub use crate::psk::{PreSharedKeyId, Psk, PskStore, ResumptionPskUsage};
+pub use crate::traits::{Decode, Encode};
+pub use crate::tree::{Error as TreeError, StagedTreeDiff, Tree, TreeDiff};
+pub use crate::welcome::{process_welcome, GroupInfo, ProcessWelcomeResult, Welcome};
@@ -0,0 +1,440 @@
+use crate::{
+    ciphersuites::{Ciphersuite, Name},
+    encode_util::*,
+    extensions::{decode_extensions, encode_extensions, Extension},
+    key_package::{KeyPackage, ProtocolVersion},
+    psk::PreSharedKeyId,
+    traits::{Decode, DecodeError, Encode},
+};
+
+mod validation;
+pub use validation::*;
+
+// Section 11.1 Proposals
+// enum {
+//     reserved(0),
+//     add(1),
+//     update(2),
+//     remove(3),
+//     psk(4),
+//     reinit(5),
+//     external_init(6),
+//     app_ack(7),
+//     group_context_extensions(8),
+//     (65535)
+// } ProposalType;
+//
+// struct {
+//     ProposalType msg_type;
+//     select (Proposal.msg_type) {
+//         case add:                      Add;
+//         case update:                   Update;
+//         case remove:                   Remove;
+//         case psk:                      PreSharedKey;
+//         case reinit:                   ReInit;
+//         case external_init:            ExternalInit;
+//         case group_context_extensions: GroupContextExtensions;
+//     };
+// } Proposal;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProposalType {
+    Invalid,
+    Add,
+    Update,
+    Remove,
+    PreSharedKey,
+    ReInit,
+    ExternalInit,
+    GroupContextExtensions,
+    Unknown(u16),
+}
+
+impl From<u16> for ProposalType {
+    fn from(v: u16) -> Self {
+        match v {
+            0 => ProposalType::Invalid,
+            1 => ProposalType::Add,
+            2 => ProposalType::Update,
+            3 => ProposalType::Remove,
+            4 => ProposalType::PreSharedKey,
+            5 => ProposalType::ReInit,
+            6 => ProposalType::ExternalInit,
+            8 => ProposalType::GroupContextExtensions,
+            _ => ProposalType::Unknown(v),
+        }
+    }
+}
+
+impl From<ProposalType> for u16 {
+    fn from(t: ProposalType) -> u16 {
+        match t {
+            ProposalType::Invalid => 0,
+            ProposalType::Add => 1,
+            ProposalType::Update => 2,
+            ProposalType::Remove => 3,
+            ProposalType::PreSharedKey => 4,
+            ProposalType::ReInit => 5,
+            ProposalType::ExternalInit => 6,
+            ProposalType::GroupContextExtensions => 8,
+            ProposalType::Unknown(v) => v,
+        }
+    }
+}
+
+impl Encode for ProposalType {
+    fn encode(&self) -> Vec<u8> {
+        u16::from(*self).encode()
+    }
+}
+
+impl Decode for ProposalType {
+    fn decode(cursor: &mut Cursor) -> Result<Self, DecodeError> {
+        Ok(ProposalType::from(u16::decode(cursor)?))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Proposal {
+    Add(AddProposal),
+    Update(UpdateProposal),
+    Remove(RemoveProposal),
+    PreSharedKey(PreSharedKeyProposal),
+    ReInit(ReInitProposal),
+    ExternalInit(ExternalInitProposal),
+    GroupContextExtensions(GroupContextExtensionsProposal),
+}
+
+impl Proposal {
+    pub fn get_type(&self) -> ProposalType {
+        match self {
+            Proposal::Add(_) => ProposalType::Add,
+            Proposal::Update(_) => ProposalType::Update,
+            Proposal::Remove(_) => ProposalType::Remove,
+            Proposal::PreSharedKey(_) => ProposalType::PreSharedKey,
+            Proposal::ReInit(_) => ProposalType::ReInit,
+            Proposal::ExternalInit(_) => ProposalType::ExternalInit,
+            Proposal::GroupContextExtensions(_) => ProposalType::GroupContextExtensions,
+        }
+    }
+}
+
+impl Encode for Proposal {
+    fn encode(&self) -> Vec<u8> {
+        let mut out = self.get_type().encode();
+        out.extend(match self {
+            Proposal::Add(p) => p.encode(),
+            Proposal::Update(p) => p.encode(),
+            Proposal::Remove(p) => p.encode(),
+            Proposal::PreSharedKey(p) => p.encode(),
+            Proposal::ReInit(p) => p.encode(),
+            Proposal::ExternalInit(p) => p.encode(),
+            Proposal::GroupContextExtensions(p) => p.encode(),
+        });
+        out
+    }
+}
+
+impl Decode for Proposal {
+    fn decode(cursor: &mut Cursor) -> Result<Self, DecodeError> {
+        Ok(match ProposalType::decode(cursor)? {
+            ProposalType::Add => Proposal::Add(AddProposal::decode(cursor)?),
+            ProposalType::Update => Proposal::Update(UpdateProposal::decode(cursor)?),
+            ProposalType::Remove => Proposal::Remove(RemoveProposal::decode(cursor)?),
+            ProposalType::PreSharedKey => {
+                Proposal::PreSharedKey(PreSharedKeyProposal::decode(cursor)?)
+            }
+            ProposalType::ReInit => Proposal::ReInit(ReInitProposal::decode(cursor)?),
+            ProposalType::ExternalInit => {
+                Proposal::ExternalInit(ExternalInitProposal::decode(cursor)?)
+            }
+            ProposalType::GroupContextExtensions => Proposal::GroupContextExtensions(
+                GroupContextExtensionsProposal::decode(cursor)?,
+            ),
+            ProposalType::Invalid | ProposalType::Unknown(_) => {
+                return Err(DecodeError::InvalidValue)
+            }
+        })
+    }
+}
+
+// struct {
+//     KeyPackage key_package;
+// } Add;
+#[derive(Debug, Clone)]
+pub struct AddProposal {
+    key_package: KeyPackage,
+}
+
+impl AddProposal {
+    pub fn new(key_package: KeyPackage) -> Self {
+        Self { key_package }
+    }
+    pub fn get_key_package(&self) -> &KeyPackage {
+        &self.key_package
+    }
+}
+
+impl Encode for AddProposal {
+    fn encode(&self) -> Vec<u8> {
+        self.key_package.encode()
+    }
+}
+
+impl Decode for AddProposal {
+    fn decode(cursor: &mut Cursor) -> Result<Self, DecodeError> {
+        Ok(Self::new(KeyPackage::decode(cursor)?))
+    }
+}
+
+// struct {
+//     KeyPackage key_package;
+// } Update;
+#[derive(Debug, Clone)]
+pub struct UpdateProposal {
+    key_package: KeyPackage,
+}
+
+impl UpdateProposal {
+    pub fn new(key_package: KeyPackage) -> Self {
+        Self { key_package }
+    }
+    pub fn get_key_package(&self) -> &KeyPackage {
+        &self.key_package
+    }
+}
+
+impl Encode for UpdateProposal {
+    fn encode(&self) -> Vec<u8> {
+        self.key_package.encode()
+    }
+}
+
+impl Decode for UpdateProposal {
+    fn decode(cursor: &mut Cursor) -> Result<Self, DecodeError> {
+        Ok(Self::new(KeyPackage::decode(cursor)?))
+    }
+}
+
+// struct {
+//     uint32 removed;
+// } Remove;
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RemoveProposal {
+    removed: u32,
+}
+
+impl RemoveProposal {
+    /// Remove the member at leaf index `removed`.
+    pub fn new(removed: u32) -> Self {
+        Self { removed }
+    }
+    pub fn get_removed(&self) -> u32 {
+        self.removed
+    }
+}
+
+impl Encode for RemoveProposal {
+    fn encode(&self) -> Vec<u8> {
+        self.removed.encode()
+    }
+}
+
+impl Decode for RemoveProposal {
+    fn decode(cursor: &mut Cursor) -> Result<Self, DecodeError> {
+        Ok(Self::new(u32::decode(cursor)?))
+    }
+}
+
+// struct {
+//     PreSharedKeyID psk;
+// } PreSharedKey;
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreSharedKeyProposal {
+    psk: PreSharedKeyId,
+}
+
+impl PreSharedKeyProposal {
+    pub fn new(psk: PreSharedKeyId) -> Self {
+        Self { psk }
+    }
+    pub fn get_psk(&self) -> &PreSharedKeyId {
+        &self.psk
+    }
+}
+
+impl Encode for PreSharedKeyProposal {
+    fn encode(&self) -> Vec<u8> {
+        self.psk.encode()
+    }
+}
+
+impl Decode for PreSharedKeyProposal {
+    fn decode(cursor: &mut Cursor) -> Result<Self, DecodeError> {
+        Ok(Self::new(PreSharedKeyId::decode(cursor)?))
+    }
+}
+
+// struct {
+//     opaque group_id<0..255>;
+//     ProtocolVersion version;
+//     CipherSuite cipher_suite;
+//     Extension extensions<0..2^32-1>;
+// } ReInit;
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReInitProposal {
+    group_id: Vec<u8>,
+    version: ProtocolVersion,
+    cipher_suite: Name,
+    extensions: Vec<Extension>,
+}
+
+impl ReInitProposal {
+    pub fn new(
+        group_id: &[u8],
+        version: ProtocolVersion,
+        cipher_suite: Name,
+        extensions: Vec<Extension>,
+    ) -> Self {
+        Self {
+            group_id: group_id.to_vec(),
+            version,
+            cipher_suite,
+            extensions,
+        }
+    }
+    pub fn get_group_id(&self) -> &[u8] {
+        &self.group_id
+    }
+    pub fn get_version(&self) -> ProtocolVersion {
+        self.version
+    }
+    pub fn get_cipher_suite(&self) -> Name {
+        self.cipher_suite
+    }
+    pub fn get_extensions(&self) -> &[Extension] {
+        &self.extensions
+    }
+}
+
+impl Encode for ReInitProposal {
+    fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        encode_slice(LenType::L8, &self.group_id, &mut out);
+        out.extend(self.version.encode());
+        out.extend(self.cipher_suite.encode());
+        out.extend(encode_extensions(&self.extensions));
+        out
+    }
+}
+
+impl Decode for ReInitProposal {
+    fn decode(cursor: &mut Cursor) -> Result<Self, DecodeError> {
+        Ok(Self {
+            group_id: decode_slice(LenType::L8, cursor)?,
+            version: ProtocolVersion::decode_supported(cursor)?,
+            cipher_suite: *Ciphersuite::decode(cursor)?.get_name(),
+            extensions: decode_extensions(cursor)?,
+        })
+    }
+}
+
+// struct {
+//     opaque kem_output<0..2^16-1>;
+// } ExternalInit;
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExternalInitProposal {
+    kem_output: Vec<u8>,
+}
+
+impl ExternalInitProposal {
+    pub fn new(kem_output: &[u8]) -> Self {
+        Self {
+            kem_output: kem_output.to_vec(),
+        }
+    }
+    pub fn get_kem_output(&self) -> &[u8] {
+        &self.kem_output
+    }
+}
+
+impl Encode for ExternalInitProposal {
+    fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        encode_slice(LenType::L16, &self.kem_output, &mut out);
+        out
+    }
+}
+
+impl Decode for ExternalInitProposal {
+    fn decode(cursor: &mut Cursor) -> Result<Self, DecodeError> {
+        Ok(Self {
+            kem_output: decode_slice(LenType::L16, cursor)?,
+        })
+    }
+}
+
+// struct {
+//     Extension extensions<0..2^32-1>;
+// } GroupContextExtensions;
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupContextExtensionsProposal {
+    extensions: Vec<Extension>,
+}
+
+impl GroupContextExtensionsProposal {
+    pub fn new(extensions: Vec<Extension>) -> Self {
+        Self { extensions }
+    }
+    pub fn get_extensions(&self) -> &[Extension] {
+        &self.extensions
+    }
+}
+
+impl Encode for GroupContextExtensionsProposal {
+    fn encode(&self) -> Vec<u8> {
+        encode_extensions(&self.extensions)
+    }
+}
+
+impl Decode for GroupContextExtensionsProposal {
+    fn decode(cursor: &mut Cursor) -> Result<Self, DecodeError> {
+        Ok(Self {
+            extensions: decode_extensions(cursor)?,
+        })
+    }
+}
+
+#[test]
+fn test_proposal_codec() {
+    use crate::psk::Psk;
+
+    let proposals = [
+        Proposal::Remove(RemoveProposal::new(3)),
+        Proposal::PreSharedKey(PreSharedKeyProposal::new(PreSharedKeyId::new(
+            Psk::External {
+                psk_id: b"psk id".to_vec(),
+            },
+            b"nonce",
+        ))),
+        Proposal::ReInit(ReInitProposal::new(
+            b"new group",
+            ProtocolVersion::Mls10,
+            Name::MLS10_128_DHKEMX25519_CHACHA20POLY1305_SHA256_Ed25519,
+            Vec::new(),
+        )),
+        Proposal::ExternalInit(ExternalInitProposal::new(&[1, 2, 3])),
+        Proposal::GroupContextExtensions(GroupContextExtensionsProposal::new(Vec::new())),
+    ];
+    for proposal in proposals.iter() {
+        let encoded = proposal.encode();
+        let decoded = Proposal::decode_all(&encoded).unwrap();
+        assert_eq!(decoded.get_type(), proposal.get_type());
+        assert_eq!(decoded.encode(), encoded);
+    }
+    // Unknown proposal types, e.g. AppAck, can't be decoded.
+    assert_eq!(
+        Proposal::decode_all(&[0x00, 0x07]).err(),
+        Some(DecodeError::InvalidValue)
+    );
+}
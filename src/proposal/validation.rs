@@ -0,0 +1,615 @@
+//! Validation of the proposals covered by a commit.
+
+use crate::ciphersuites::Ciphersuite;
+use crate::clock::Clock;
+use crate::extensions::{
+    required_capabilities, validate_extensions, Error as ExtensionError, Extension,
+    ExtensionContext,
+};
+use crate::framing::Sender;
+use crate::key_package::{Error as KeyPackageError, KeyPackage, ProtocolVersion};
+use crate::proposal::{Proposal, ProposalType};
+use crate::tree::Tree;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProposalError {
+    /// There's more than one Update or Remove for the leaf.
+    DuplicateUpdateOrRemove(u32),
+    UpdateFromCommitter,
+    /// The key package of an Update has another identity than the leaf.
+    UpdateIdentityChanged,
+    /// Updates must come from members.
+    InvalidSender,
+    RemoveBlankLeaf(u32),
+    /// The committer can't remove itself.
+    RemoveCommitter,
+    CiphersuiteMismatch,
+    VersionMismatch,
+    /// There's more than one Add for the same identity.
+    DuplicateAdd,
+    /// The identity of an Add is already a member at the leaf.
+    AddExistingMember(u32),
+    ReInitWithOtherProposals,
+    /// The same PSK is used more than once.
+    DuplicatePsk,
+    /// ExternalInit is only allowed in external commits.
+    ExternalInitFromMember,
+    /// The key package of an Add doesn't support the group's required
+    /// capabilities.
+    UnsupportedRequiredCapabilities,
+    /// The member at the leaf doesn't support the proposed required
+    /// capabilities.
+    MemberLacksRequiredCapabilities(u32),
+    InvalidGroupContextExtensions(ExtensionError),
+    /// There's more than one GroupContextExtensions proposal.
+    DuplicateGroupContextExtensions,
+    /// External senders can't send proposals of this type.
+    NotAllowedFromExternalSender,
+    /// The key package of an Add or Update is invalid, e.g. expired.
+    InvalidKeyPackage(KeyPackageError),
+}
+
+/// A proposal that can't be committed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InvalidProposal {
+    /// The position of the proposal in the list that was validated.
+    pub index: usize,
+    pub error: ProposalError,
+}
+
+/// The group parameters the proposals are validated against.
+pub struct ValidationContext<'a> {
+    pub tree: &'a Tree,
+    pub ciphersuite: &'a Ciphersuite,
+    pub version: ProtocolVersion,
+    /// The leaf index of the member committing the proposals.
+    pub committer: u32,
+    /// The group context extensions of the current epoch.
+    pub extensions: &'a [Extension],
+    /// Key packages are validated at the current time of the clock.
+    pub clock: &'a dyn Clock,
+}
+
+// Section 11.1 External Proposals
+/// Whether an external sender may send `proposal`. External senders have no
+/// leaf, so they can't update, and they can't initialize an external commit.
+pub fn allowed_from_external_sender(proposal: &Proposal) -> bool {
+    matches!(
+        proposal.get_type(),
+        ProposalType::Add
+            | ProposalType::Remove
+            | ProposalType::PreSharedKey
+            | ProposalType::ReInit
+            | ProposalType::GroupContextExtensions
+    )
+}
+
+/// Check the key package of an Add or Update against the group.
+fn check_key_package(
+    context: &ValidationContext,
+    key_package: &KeyPackage,
+) -> Result<(), ProposalError> {
+    if key_package.get_cipher_suite().get_name() != context.ciphersuite.get_name() {
+        return Err(ProposalError::CiphersuiteMismatch);
+    }
+    if key_package.get_version() != context.version {
+        return Err(ProposalError::VersionMismatch);
+    }
+    key_package
+        .validate(context.clock)
+        .map_err(ProposalError::InvalidKeyPackage)
+}
+
+// Section 11.2 Commit
+/// Check a list of proposals with their senders.
+/// Returns all invalid proposals. For duplicates the first proposal is
+/// considered valid and all later ones invalid.
+pub fn validate_proposals(
+    context: &ValidationContext,
+    proposals: &[(Sender, Proposal)],
+) -> Result<(), Vec<InvalidProposal>> {
+    let mut invalid = Vec::new();
+    let mut changed_leaves = Vec::new();
+    let mut added_identities: Vec<&[u8]> = Vec::new();
+    let mut psks = Vec::new();
+    let mut updates = Vec::new();
+    let mut extensions_changed = false;
+    let mut proposed_requirements = None;
+    // Adds must support the requirements in effect after the commit, i.e.
+    // the proposed ones if there's a GroupContextExtensions proposal.
+    let new_extensions = proposals.iter().find_map(|(_, proposal)| match proposal {
+        Proposal::GroupContextExtensions(p) => Some(p.get_extensions()),
+        _ => None,
+    });
+    let required = required_capabilities(new_extensions.unwrap_or(context.extensions));
+    // A member may be removed and added again in the same commit, so Adds
+    // are checked against the members that remain after all Removes.
+    let removed_leaves: Vec<u32> = proposals
+        .iter()
+        .filter_map(|(_, proposal)| match proposal {
+            Proposal::Remove(p) => Some(p.get_removed()),
+            _ => None,
+        })
+        .filter(|&removed| {
+            removed != context.committer && context.tree.get_key_package(removed).is_some()
+        })
+        .collect();
+    let mut reject = |index, error| invalid.push(InvalidProposal { index, error });
+
+    for (index, (sender, proposal)) in proposals.iter().enumerate() {
+        if let Sender::External(_) = sender {
+            if !allowed_from_external_sender(proposal) {
+                reject(index, ProposalError::NotAllowedFromExternalSender);
+                continue;
+            }
+        }
+        match proposal {
+            Proposal::Update(p) => {
+                let leaf_index = match sender {
+                    Sender::Member(leaf_index) => *leaf_index,
+                    _ => {
+                        reject(index, ProposalError::InvalidSender);
+                        continue;
+                    }
+                };
+                let identity = p.get_key_package().get_credential().get_identity();
+                let leaf_identity = context
+                    .tree
+                    .get_key_package(leaf_index)
+                    .map(|kp| kp.get_credential().get_identity());
+                if let Err(e) = check_key_package(context, p.get_key_package()) {
+                    reject(index, e);
+                } else if leaf_index == context.committer {
+                    reject(index, ProposalError::UpdateFromCommitter);
+                } else if leaf_identity != Some(identity) {
+                    reject(index, ProposalError::UpdateIdentityChanged);
+                } else if changed_leaves.contains(&leaf_index) {
+                    reject(index, ProposalError::DuplicateUpdateOrRemove(leaf_index));
+                } else {
+                    changed_leaves.push(leaf_index);
+                    updates.push((leaf_index, p.get_key_package()));
+                }
+            }
+            Proposal::Remove(p) => {
+                let removed = p.get_removed();
+                if context.tree.get_key_package(removed).is_none() {
+                    reject(index, ProposalError::RemoveBlankLeaf(removed));
+                } else if removed == context.committer {
+                    reject(index, ProposalError::RemoveCommitter);
+                } else if changed_leaves.contains(&removed) {
+                    reject(index, ProposalError::DuplicateUpdateOrRemove(removed));
+                } else {
+                    changed_leaves.push(removed);
+                }
+            }
+            Proposal::Add(p) => {
+                let key_package = p.get_key_package();
+                let identity = key_package.get_credential().get_identity();
+                let member = context.tree.members().find(|(leaf_index, kp)| {
+                    !removed_leaves.contains(leaf_index)
+                        && kp.get_credential().get_identity() == identity
+                });
+                if let Err(e) = check_key_package(context, key_package) {
+                    reject(index, e);
+                } else if let Some((leaf_index, _)) = member {
+                    reject(index, ProposalError::AddExistingMember(leaf_index));
+                } else if added_identities.contains(&identity) {
+                    reject(index, ProposalError::DuplicateAdd);
+                } else if matches!(required, Some(r) if !key_package.supports(r)) {
+                    reject(index, ProposalError::UnsupportedRequiredCapabilities);
+                } else {
+                    added_identities.push(identity);
+                }
+            }
+            Proposal::PreSharedKey(p) => {
+                let psk = p.get_psk().get_psk();
+                if psks.contains(&psk) {
+                    reject(index, ProposalError::DuplicatePsk);
+                } else {
+                    psks.push(psk);
+                }
+            }
+            Proposal::ExternalInit(_) => reject(index, ProposalError::ExternalInitFromMember),
+            // Section 11.1.7 GroupContextExtensions
+            Proposal::GroupContextExtensions(p) => {
+                let extensions = p.get_extensions();
+                if extensions_changed {
+                    reject(index, ProposalError::DuplicateGroupContextExtensions);
+                    continue;
+                }
+                extensions_changed = true;
+                if let Err(e) = validate_extensions(extensions, ExtensionContext::GroupContext) {
+                    reject(index, ProposalError::InvalidGroupContextExtensions(e));
+                } else if let Some(required) = required_capabilities(extensions) {
+                    proposed_requirements = Some((index, required));
+                }
+            }
+            Proposal::ReInit(_) => {
+                if proposals.len() > 1 {
+                    reject(index, ProposalError::ReInitWithOtherProposals);
+                }
+            }
+        }
+    }
+
+    // All members that remain after the commit must support the new
+    // requirements, with the key packages of their Updates.
+    if let Some((index, required)) = proposed_requirements {
+        let key_package = |leaf_index, kp| match updates.iter().find(|(l, _)| *l == leaf_index) {
+            Some((_, update)) => *update,
+            None => kp,
+        };
+        let lacking = context
+            .tree
+            .members()
+            .filter(|(leaf_index, _)| !removed_leaves.contains(leaf_index))
+            .find(|&(leaf_index, kp)| !key_package(leaf_index, kp).supports(required));
+        if let Some((leaf_index, _)) = lacking {
+            let error = ProposalError::MemberLacksRequiredCapabilities(leaf_index);
+            reject(index, error);
+        }
+    }
+
+    invalid.sort_by_key(|p| p.index);
+    if invalid.is_empty() {
+        Ok(())
+    } else {
+        Err(invalid)
+    }
+}
+
+#[test]
+fn test_validate_proposals() {
+    use crate::ciphersuites::Name;
+    use crate::clock::FixedClock;
+    use crate::credentials::{BasicCredential, Credential};
+    use crate::key_package::KeyPackage;
+    use crate::proposal::{
+        AddProposal, ExternalInitProposal, PreSharedKeyProposal, ReInitProposal, RemoveProposal,
+        UpdateProposal,
+    };
+    use crate::psk::{PreSharedKeyId, Psk};
+
+    let key_package = |name: Name| {
+        KeyPackage::new_with_lifetime(
+            ProtocolVersion::Mls10,
+            Ciphersuite::new(name),
+            Credential::basic(BasicCredential::ed25519()),
+            60,
+            &FixedClock(1000),
+        )
+        .unwrap()
+    };
+    let default = Name::MLS10_128_DHKEMX25519_AES128GCM_SHA256_Ed25519;
+    let mut tree = Tree::new(default);
+    tree.add_member(key_package(default)).unwrap();
+    tree.add_member(key_package(default)).unwrap();
+    let ciphersuite = Ciphersuite::new(default);
+    let context = ValidationContext {
+        tree: &tree,
+        ciphersuite: &ciphersuite,
+        version: ProtocolVersion::Mls10,
+        committer: 0,
+        extensions: &[],
+        clock: &FixedClock(1000),
+    };
+
+    let new_member = key_package(default);
+    let psk = |nonce: &[u8]| {
+        Proposal::PreSharedKey(PreSharedKeyProposal::new(PreSharedKeyId::new(
+            Psk::External {
+                psk_id: b"psk".to_vec(),
+            },
+            nonce,
+        )))
+    };
+    let proposals = vec![
+        (Sender::Member(0), Proposal::Remove(RemoveProposal::new(1))),
+        (Sender::Member(0), Proposal::Remove(RemoveProposal::new(1))),
+        (Sender::Member(0), Proposal::Remove(RemoveProposal::new(5))),
+        (Sender::Member(0), Proposal::Update(UpdateProposal::new(key_package(default)))),
+        (Sender::Member(0), Proposal::Add(AddProposal::new(new_member.clone()))),
+        (Sender::Member(0), Proposal::Add(AddProposal::new(new_member))),
+        (
+            Sender::Member(0),
+            Proposal::Add(AddProposal::new(key_package(
+                Name::MLS10_128_DHKEMP256_AES128GCM_SHA256_P256,
+            ))),
+        ),
+        (Sender::Member(1), psk(b"nonce 1")),
+        (Sender::Member(1), psk(b"nonce 2")),
+        (Sender::Member(1), Proposal::ExternalInit(ExternalInitProposal::new(b"kem output"))),
+        (Sender::External(0), Proposal::Add(AddProposal::new(key_package(default)))),
+        (Sender::External(0), Proposal::Update(UpdateProposal::new(key_package(default)))),
+        (Sender::Member(1), Proposal::Remove(RemoveProposal::new(0))),
+        (
+            Sender::Member(0),
+            Proposal::Add(AddProposal::new(tree.get_key_package(0).unwrap().clone())),
+        ),
+        (Sender::Member(1), Proposal::Update(UpdateProposal::new(key_package(default)))),
+    ];
+    let errors: Vec<(usize, ProposalError)> = validate_proposals(&context, &proposals)
+        .unwrap_err()
+        .iter()
+        .map(|e| (e.index, e.error))
+        .collect();
+    assert_eq!(
+        errors,
+        vec![
+            (1, ProposalError::DuplicateUpdateOrRemove(1)),
+            (2, ProposalError::RemoveBlankLeaf(5)),
+            (3, ProposalError::UpdateFromCommitter),
+            (5, ProposalError::DuplicateAdd),
+            (6, ProposalError::CiphersuiteMismatch),
+            (8, ProposalError::DuplicatePsk),
+            (9, ProposalError::ExternalInitFromMember),
+            (11, ProposalError::NotAllowedFromExternalSender),
+            (12, ProposalError::RemoveCommitter),
+            (13, ProposalError::AddExistingMember(0)),
+            (14, ProposalError::UpdateIdentityChanged),
+        ]
+    );
+
+    // A member can be removed and added again, whatever the order.
+    let member = tree.get_key_package(1).unwrap().clone();
+    let re_add = (Sender::Member(0), Proposal::Add(AddProposal::new(member)));
+    let remove = (Sender::Member(0), Proposal::Remove(RemoveProposal::new(1)));
+    assert!(validate_proposals(&context, &[re_add.clone(), remove.clone()]).is_ok());
+    assert!(validate_proposals(&context, &[remove, re_add]).is_ok());
+
+    let reinit = Proposal::ReInit(ReInitProposal::new(
+        b"new group",
+        ProtocolVersion::Mls10,
+        default,
+        Vec::new(),
+    ));
+    assert!(validate_proposals(&context, &[(Sender::Member(0), reinit.clone())]).is_ok());
+    assert_eq!(
+        validate_proposals(
+            &context,
+            &[
+                (Sender::Member(0), reinit),
+                (Sender::Member(0), Proposal::Remove(RemoveProposal::new(1))),
+            ]
+        )
+        .unwrap_err(),
+        vec![InvalidProposal {
+            index: 0,
+            error: ProposalError::ReInitWithOtherProposals
+        }]
+    );
+}
+
+#[test]
+fn test_validate_key_packages() {
+    use crate::ciphersuites::Name;
+    use crate::clock::FixedClock;
+    use crate::credentials::{BasicCredential, Credential};
+    use crate::extensions::LifetimeExtension;
+    use crate::proposal::{AddProposal, UpdateProposal};
+
+    let default = Name::MLS10_128_DHKEMX25519_AES128GCM_SHA256_Ed25519;
+    let key_package = |name, version, now| {
+        KeyPackage::new_with_lifetime(
+            version,
+            Ciphersuite::new(name),
+            Credential::basic(BasicCredential::ed25519()),
+            60,
+            &FixedClock(now),
+        )
+        .unwrap()
+    };
+    let member = || key_package(default, ProtocolVersion::Mls10, 1000);
+    let mut tree = Tree::new(default);
+    tree.add_member(member()).unwrap();
+    tree.add_member(member()).unwrap();
+    let ciphersuite = Ciphersuite::new(default);
+    let context = ValidationContext {
+        tree: &tree,
+        ciphersuite: &ciphersuite,
+        version: ProtocolVersion::Mls10,
+        committer: 0,
+        extensions: &[],
+        clock: &FixedClock(1000),
+    };
+
+    let mut unsigned = KeyPackage::new(
+        ProtocolVersion::Mls10,
+        Ciphersuite::new(default),
+        Credential::basic(BasicCredential::ed25519()),
+    );
+    unsigned.set_lifetime(LifetimeExtension::from_now(&FixedClock(1000), 60));
+    let cases = vec![
+        (
+            unsigned,
+            ProposalError::InvalidKeyPackage(KeyPackageError::InvalidSignature),
+        ),
+        (
+            key_package(default, ProtocolVersion::Mls10, 0),
+            ProposalError::InvalidKeyPackage(KeyPackageError::Expired),
+        ),
+        (
+            key_package(default, ProtocolVersion::Mls10, 2000),
+            ProposalError::InvalidKeyPackage(KeyPackageError::NotYetValid),
+        ),
+        (
+            key_package(
+                Name::MLS10_128_DHKEMP256_AES128GCM_SHA256_P256,
+                ProtocolVersion::Mls10,
+                1000,
+            ),
+            ProposalError::CiphersuiteMismatch,
+        ),
+        (
+            key_package(default, ProtocolVersion::Reserved, 1000),
+            ProposalError::VersionMismatch,
+        ),
+    ];
+    for (key_package, error) in cases {
+        let update = Proposal::Update(UpdateProposal::new(key_package.clone()));
+        let add = Proposal::Add(AddProposal::new(key_package));
+        for proposal in [(Sender::Member(1), update), (Sender::Member(0), add)] {
+            assert_eq!(
+                validate_proposals(&context, &[proposal]).unwrap_err(),
+                vec![InvalidProposal { index: 0, error }]
+            );
+        }
+    }
+}
+
+#[test]
+fn test_required_capabilities() {
+    use crate::ciphersuites::Name;
+    use crate::clock::SystemClock;
+    use crate::credentials::{BasicCredential, Credential, BASIC};
+    use crate::extensions::{CapabilitiesExtension, ExtensionType, RequiredCapabilitiesExtension};
+    use crate::key_package::KeyPackage;
+    use crate::proposal::{
+        AddProposal, GroupContextExtensionsProposal, RemoveProposal, UpdateProposal,
+    };
+
+    let default = Name::MLS10_128_DHKEMX25519_AES128GCM_SHA256_Ed25519;
+    let key_package_for = |credential: Credential, capable: bool| {
+        let mut key_package = KeyPackage::new_with_lifetime(
+            ProtocolVersion::Mls10,
+            Ciphersuite::new(default),
+            credential,
+            60,
+            &SystemClock,
+        )
+        .unwrap();
+        if capable {
+            key_package.set_capabilities(CapabilitiesExtension::new(
+                vec![ProtocolVersion::Mls10],
+                vec![default],
+                vec![ExtensionType::ExternalSenders],
+                vec![ProposalType::GroupContextExtensions],
+                vec![BASIC],
+            ));
+            key_package.sign().unwrap();
+        }
+        key_package
+    };
+    let key_package =
+        |capable: bool| key_package_for(Credential::basic(BasicCredential::ed25519()), capable);
+    let required = Extension::RequiredCapabilities(RequiredCapabilitiesExtension::new(
+        vec![ExtensionType::ExternalSenders],
+        vec![ProposalType::GroupContextExtensions],
+        vec![BASIC],
+    ));
+    let mut tree = Tree::new(default);
+    tree.add_member(key_package(true)).unwrap();
+    tree.add_member(key_package(false)).unwrap();
+    let ciphersuite = Ciphersuite::new(default);
+
+    // Requiring capabilities that a member lacks.
+    let context = ValidationContext {
+        tree: &tree,
+        ciphersuite: &ciphersuite,
+        version: ProtocolVersion::Mls10,
+        committer: 0,
+        extensions: &[],
+        clock: &SystemClock,
+    };
+    let group_context_extensions = |extensions: Vec<Extension>| {
+        (
+            Sender::Member(0),
+            Proposal::GroupContextExtensions(GroupContextExtensionsProposal::new(extensions)),
+        )
+    };
+    assert_eq!(
+        validate_proposals(
+            &context,
+            &[group_context_extensions(vec![required.clone()])]
+        )
+        .unwrap_err(),
+        vec![InvalidProposal {
+            index: 0,
+            error: ProposalError::MemberLacksRequiredCapabilities(1),
+        }]
+    );
+
+    // Invalid extensions.
+    assert_eq!(
+        validate_proposals(
+            &context,
+            &[group_context_extensions(vec![
+                required.clone(),
+                required.clone()
+            ])]
+        )
+        .unwrap_err(),
+        vec![InvalidProposal {
+            index: 0,
+            error: ProposalError::InvalidGroupContextExtensions(
+                ExtensionError::DuplicateExtension(ExtensionType::RequiredCapabilities)
+            ),
+        }]
+    );
+
+    // Only one GroupContextExtensions proposal per commit.
+    assert_eq!(
+        validate_proposals(
+            &context,
+            &[
+                group_context_extensions(Vec::new()),
+                group_context_extensions(Vec::new())
+            ]
+        )
+        .unwrap_err(),
+        vec![InvalidProposal {
+            index: 1,
+            error: ProposalError::DuplicateGroupContextExtensions,
+        }]
+    );
+
+    // The requirements apply to the members after Updates and Removes.
+    let credential = tree.get_key_package(1).unwrap().get_credential().clone();
+    let update = UpdateProposal::new(key_package_for(credential, true));
+    let update = (Sender::Member(1), Proposal::Update(update));
+    let remove = (Sender::Member(0), Proposal::Remove(RemoveProposal::new(1)));
+    for proposal in [update, remove] {
+        let proposals = [group_context_extensions(vec![required.clone()]), proposal];
+        assert!(validate_proposals(&context, &proposals).is_ok());
+    }
+
+    // New members must support the proposed requirements.
+    let proposals = vec![
+        group_context_extensions(vec![required.clone()]),
+        (Sender::Member(0), Proposal::Add(AddProposal::new(key_package(true)))),
+        (Sender::Member(0), Proposal::Add(AddProposal::new(key_package(false)))),
+        (Sender::Member(0), Proposal::Remove(RemoveProposal::new(1))),
+    ];
+    assert_eq!(
+        validate_proposals(&context, &proposals).unwrap_err(),
+        vec![InvalidProposal {
+            index: 2,
+            error: ProposalError::UnsupportedRequiredCapabilities,
+        }]
+    );
+
+    // New members must support the current requirements.
+    let extensions = [required];
+    let context = ValidationContext {
+        extensions: &extensions,
+        ..context
+    };
+    let proposals = vec![
+        (Sender::Member(0), Proposal::Add(AddProposal::new(key_package(true)))),
+        (Sender::Member(0), Proposal::Add(AddProposal::new(key_package(false)))),
+    ];
+    assert_eq!(
+        validate_proposals(&context, &proposals).unwrap_err(),
+        vec![InvalidProposal {
+            index: 1,
+            error: ProposalError::UnsupportedRequiredCapabilities,
+        }]
+    );
+
+    // Unless they are dropped in the same commit.
+    let proposals = vec![
+        group_context_extensions(Vec::new()),
+        (Sender::Member(0), Proposal::Add(AddProposal::new(key_package(false)))),
+    ];
+    assert!(validate_proposals(&context, &proposals).is_ok());
+}
@@ -0,0 +1,193 @@
+//! Proposals received in the current epoch.
+//! Commits may include proposals by reference. The store keeps all proposals
+//! of the current epoch such that references can be resolved and a committer
+//! can pick the proposals it wants to commit.
+
+use crate::ciphersuites::Ciphersuite;
+use crate::encode_util::*;
+use crate::framing::{MLSPlaintext, MLSPlaintextContent, Sender};
+use crate::proposal::Proposal;
+use crate::traits::{Decode, DecodeError, Encode};
+use evercrypt::prelude::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    WrongEpoch,
+    WrongGroup,
+    NotAProposal,
+    DuplicateProposal,
+}
+
+// Section 11.2 Commit
+// opaque ProposalRef<0..255>;
+// ProposalRef = Hash(MLSPlaintext)
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ProposalRef {
+    value: Vec<u8>,
+}
+
+impl ProposalRef {
+    /// Compute the reference of the proposal carried in `plaintext`.
+    pub fn new(ciphersuite: &Ciphersuite, plaintext: &MLSPlaintext) -> Self {
+        Self {
+            value: hash(ciphersuite.hash, &plaintext.encode()),
+        }
+    }
+    pub fn as_slice(&self) -> &[u8] {
+        &self.value
+    }
+}
+
+impl Encode for ProposalRef {
+    fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        encode_slice(LenType::L8, &self.value, &mut out);
+        out
+    }
+}
+
+impl Decode for ProposalRef {
+    fn decode(cursor: &mut Cursor) -> Result<Self, DecodeError> {
+        Ok(Self {
+            value: decode_slice(LenType::L8, cursor)?,
+        })
+    }
+}
+
+/// A proposal in the store together with its reference and sender.
+#[derive(Debug, Clone)]
+pub struct QueuedProposal {
+    proposal_ref: ProposalRef,
+    proposal: Proposal,
+    sender: Sender,
+}
+
+impl QueuedProposal {
+    pub fn get_ref(&self) -> &ProposalRef {
+        &self.proposal_ref
+    }
+    pub fn get_proposal(&self) -> &Proposal {
+        &self.proposal
+    }
+    pub fn get_sender(&self) -> Sender {
+        self.sender
+    }
+}
+
+#[derive(Debug)]
+pub struct ProposalStore {
+    group_id: Vec<u8>,
+    epoch: u64,
+    proposals: Vec<QueuedProposal>, // in the order they were received
+}
+
+impl ProposalStore {
+    pub fn new(group_id: &[u8], epoch: u64) -> Self {
+        Self {
+            group_id: group_id.to_vec(),
+            epoch,
+            proposals: Vec::new(),
+        }
+    }
+
+    pub fn get_epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// Add the proposal carried in `plaintext` to the store.
+    /// The plaintext must be from the store's group and epoch.
+    pub fn add(
+        &mut self,
+        ciphersuite: &Ciphersuite,
+        plaintext: &MLSPlaintext,
+    ) -> Result<ProposalRef, Error> {
+        if plaintext.get_group_id() != self.group_id.as_slice() {
+            return Err(Error::WrongGroup);
+        }
+        if plaintext.get_epoch() != self.epoch {
+            return Err(Error::WrongEpoch);
+        }
+        let proposal = match plaintext.get_content() {
+            MLSPlaintextContent::Proposal(p) => p.clone(),
+            _ => return Err(Error::NotAProposal),
+        };
+        let proposal_ref = ProposalRef::new(ciphersuite, plaintext);
+        if self.get(&proposal_ref).is_some() {
+            return Err(Error::DuplicateProposal);
+        }
+        self.proposals.push(QueuedProposal {
+            proposal_ref: proposal_ref.clone(),
+            proposal,
+            sender: plaintext.get_sender(),
+        });
+        Ok(proposal_ref)
+    }
+
+    pub fn get(&self, proposal_ref: &ProposalRef) -> Option<&QueuedProposal> {
+        self.proposals
+            .iter()
+            .find(|p| &p.proposal_ref == proposal_ref)
+    }
+
+    /// All proposals of this epoch in the order they were received.
+    pub fn proposals(&self) -> &[QueuedProposal] {
+        &self.proposals
+    }
+
+    pub fn remove(&mut self, proposal_ref: &ProposalRef) -> Option<QueuedProposal> {
+        let i = self
+            .proposals
+            .iter()
+            .position(|p| &p.proposal_ref == proposal_ref)?;
+        Some(self.proposals.remove(i))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.proposals.is_empty()
+    }
+
+    /// Move the store to `epoch`.
+    /// Proposals are only valid in the epoch they were sent in, so the store
+    /// is cleared if the epoch changes.
+    pub fn set_epoch(&mut self, epoch: u64) {
+        if epoch != self.epoch {
+            self.proposals.clear();
+            self.epoch = epoch;
+        }
+    }
+}
+
+#[test]
+fn test_proposal_store() {
+    use crate::proposal::RemoveProposal;
+
+    let ciphersuite = Ciphersuite::default();
+    let mut store = ProposalStore::new(b"group", 1);
+    let plaintext = MLSPlaintext::new(
+        b"group",
+        1,
+        Sender::Member(0),
+        &[],
+        MLSPlaintextContent::Proposal(Proposal::Remove(RemoveProposal::new(2))),
+    );
+    let proposal_ref = store.add(&ciphersuite, &plaintext).unwrap();
+    assert_eq!(proposal_ref, ProposalRef::new(&ciphersuite, &plaintext));
+    assert_eq!(
+        store.add(&ciphersuite, &plaintext).unwrap_err(),
+        Error::DuplicateProposal
+    );
+    assert!(store.get(&proposal_ref).is_some());
+
+    let mut old_plaintext = plaintext.clone();
+    old_plaintext.epoch = 0;
+    assert_eq!(
+        store.add(&ciphersuite, &old_plaintext).unwrap_err(),
+        Error::WrongEpoch
+    );
+
+    // Nothing happens if the epoch doesn't change.
+    store.set_epoch(1);
+    assert_eq!(store.proposals().len(), 1);
+    store.set_epoch(2);
+    assert!(store.is_empty());
+}
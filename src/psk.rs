@@ -0,0 +1,267 @@
+//! Pre-shared keys
+
+use std::collections::HashMap;
+
+use evercrypt::hkdf::hkdf_extract;
+use evercrypt::rand_util::get_random_vec;
+
+use crate::ciphersuites::Ciphersuite;
+use crate::encode_util::*;
+use crate::key_schedule::KeySchedule;
+use crate::traits::{Decode, DecodeError, Encode};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// The PSK isn't in the PSK store.
+    UnknownPsk(Psk),
+}
+
+// Section 8.2 Pre-Shared Keys
+// enum {
+//   reserved(0),
+//   external(1),
+//   resumption(2),
+//   (255)
+// } PSKType;
+//
+// enum {
+//   reserved(0),
+//   application(1),
+//   reinit(2),
+//   branch(3),
+//   (255)
+// } ResumptionPSKUsage;
+//
+// struct {
+//   PSKType psktype;
+//   select (PreSharedKeyID.psktype) {
+//     case external:
+//       opaque psk_id<0..255>;
+//
+//     case resumption:
+//       ResumptionPSKUsage usage;
+//       opaque psk_group_id<0..255>;
+//       uint64 psk_epoch;
+//   };
+//   opaque psk_nonce<0..255>;
+// } PreSharedKeyID;
+
+const EXTERNAL: u8 = 1;
+const RESUMPTION: u8 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResumptionPskUsage {
+    Application = 1,
+    ReInit = 2,
+    Branch = 3,
+}
+
+impl Decode for ResumptionPskUsage {
+    fn decode(cursor: &mut Cursor) -> Result<Self, DecodeError> {
+        match u8::decode(cursor)? {
+            1 => Ok(ResumptionPskUsage::Application),
+            2 => Ok(ResumptionPskUsage::ReInit),
+            3 => Ok(ResumptionPskUsage::Branch),
+            _ => Err(DecodeError::InvalidValue),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Psk {
+    External {
+        psk_id: Vec<u8>,
+    },
+    Resumption {
+        usage: ResumptionPskUsage,
+        psk_group_id: Vec<u8>,
+        psk_epoch: u64,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreSharedKeyId {
+    psk: Psk,
+    psk_nonce: Vec<u8>,
+}
+
+impl PreSharedKeyId {
+    pub fn new(psk: Psk, psk_nonce: &[u8]) -> Self {
+        Self {
+            psk,
+            psk_nonce: psk_nonce.to_vec(),
+        }
+    }
+    /// Reference `psk` with a fresh random nonce of length `KDF.Nh`.
+    pub fn with_random_nonce(psk: Psk, ciphersuite: &Ciphersuite) -> Self {
+        Self::new(psk, &get_random_vec(ciphersuite.hash_length()))
+    }
+    pub fn get_psk(&self) -> &Psk {
+        &self.psk
+    }
+    pub fn get_psk_nonce(&self) -> &[u8] {
+        &self.psk_nonce
+    }
+}
+
+impl Encode for PreSharedKeyId {
+    fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        match &self.psk {
+            Psk::External { psk_id } => {
+                out.push(EXTERNAL);
+                encode_slice(LenType::L8, psk_id, &mut out);
+            }
+            Psk::Resumption {
+                usage,
+                psk_group_id,
+                psk_epoch,
+            } => {
+                out.push(RESUMPTION);
+                out.push(*usage as u8);
+                encode_slice(LenType::L8, psk_group_id, &mut out);
+                out.extend(psk_epoch.encode());
+            }
+        }
+        encode_slice(LenType::L8, &self.psk_nonce, &mut out);
+        out
+    }
+}
+
+impl Decode for PreSharedKeyId {
+    fn decode(cursor: &mut Cursor) -> Result<Self, DecodeError> {
+        let psk = match u8::decode(cursor)? {
+            EXTERNAL => Psk::External {
+                psk_id: decode_slice(LenType::L8, cursor)?,
+            },
+            RESUMPTION => Psk::Resumption {
+                usage: ResumptionPskUsage::decode(cursor)?,
+                psk_group_id: decode_slice(LenType::L8, cursor)?,
+                psk_epoch: u64::decode(cursor)?,
+            },
+            _ => return Err(DecodeError::InvalidValue),
+        };
+        let psk_nonce = decode_slice(LenType::L8, cursor)?;
+        Ok(Self { psk, psk_nonce })
+    }
+}
+
+/// Where the secrets of pre-shared keys are looked up.
+/// Applications implement this to inject external PSKs.
+pub trait PskStore {
+    /// The secret of `psk` if it's known.
+    fn get_psk(&self, psk: &Psk) -> Option<Vec<u8>>;
+}
+
+/// External PSKs by their `psk_id`.
+impl PskStore for HashMap<Vec<u8>, Vec<u8>> {
+    fn get_psk(&self, psk: &Psk) -> Option<Vec<u8>> {
+        match psk {
+            Psk::External { psk_id } => self.get(psk_id).cloned(),
+            Psk::Resumption { .. } => None,
+        }
+    }
+}
+
+// struct {
+//     PreSharedKeyID id;
+//     uint16 index;
+//     uint16 count;
+// } PSKLabel;
+struct PskLabel<'a> {
+    id: &'a PreSharedKeyId,
+    index: u16,
+    count: u16,
+}
+
+impl<'a> Encode for PskLabel<'a> {
+    fn encode(&self) -> Vec<u8> {
+        let mut out = self.id.encode();
+        out.extend(self.index.encode());
+        out.extend(self.count.encode());
+        out
+    }
+}
+
+// Section 8.2 Pre-Shared Keys
+// psk_extracted_[i] = KDF.Extract(0, psk_[i])
+// psk_input_[i] = ExpandWithLabel(psk_extracted_[i], "derived psk", PSKLabel, KDF.Nh)
+//
+// psk_secret_[0] = 0
+// psk_secret_[i] = KDF.Extract(psk_input_[i-1], psk_secret_[i-1])
+// psk_secret     = psk_secret_[n]
+/// Derive the `psk_secret` for the key schedule from the PSKs `psks`.
+/// Returns `None` if there are no PSKs; the key schedule uses the all-zero
+/// secret then.
+pub(crate) fn psk_secret(
+    ciphersuite: &Ciphersuite,
+    psks: &[PreSharedKeyId],
+    psk_store: &dyn PskStore,
+) -> Result<Option<Vec<u8>>, Error> {
+    if psks.is_empty() {
+        return Ok(None);
+    }
+    let key_schedule = KeySchedule::new(ciphersuite);
+    let zero = key_schedule.zero_secret();
+    let mut psk_secret = zero.clone();
+    for (index, id) in psks.iter().enumerate() {
+        let psk = psk_store
+            .get_psk(&id.psk)
+            .ok_or_else(|| Error::UnknownPsk(id.psk.clone()))?;
+        let psk_extracted = hkdf_extract(ciphersuite.kdf, &zero, &psk);
+        let label = PskLabel {
+            id,
+            index: index as u16,
+            count: psks.len() as u16,
+        };
+        let psk_input = key_schedule.expand_with_label(
+            &psk_extracted,
+            "derived psk",
+            &label.encode(),
+            ciphersuite.hash_length(),
+        );
+        psk_secret = hkdf_extract(ciphersuite.kdf, &psk_input, &psk_secret);
+    }
+    Ok(Some(psk_secret))
+}
+
+#[test]
+fn test_psk_secret() {
+    use crate::ciphersuites::Name;
+
+    let ciphersuite = Ciphersuite::new(Name::MLS10_128_DHKEMX25519_AES128GCM_SHA256_Ed25519);
+    let mut store = HashMap::new();
+    store.insert(b"psk 1".to_vec(), vec![1u8; 32]);
+    store.insert(b"psk 2".to_vec(), vec![2u8; 32]);
+    let external = |psk_id: &[u8]| {
+        PreSharedKeyId::new(
+            Psk::External {
+                psk_id: psk_id.to_vec(),
+            },
+            &[0u8; 32],
+        )
+    };
+
+    assert_eq!(psk_secret(&ciphersuite, &[], &store), Ok(None));
+    let one = psk_secret(&ciphersuite, &[external(b"psk 1")], &store).unwrap();
+    let both = psk_secret(
+        &ciphersuite,
+        &[external(b"psk 1"), external(b"psk 2")],
+        &store,
+    )
+    .unwrap();
+    let reversed = psk_secret(
+        &ciphersuite,
+        &[external(b"psk 2"), external(b"psk 1")],
+        &store,
+    )
+    .unwrap();
+    assert_ne!(one, both);
+    assert_ne!(both, reversed);
+    assert_eq!(
+        psk_secret(&ciphersuite, &[external(b"psk 3")], &store),
+        Err(Error::UnknownPsk(Psk::External {
+            psk_id: b"psk 3".to_vec()
+        }))
+    );
+}
@@ -0,0 +1,321 @@
+//! The secret tree of an epoch.
+//! It derives a symmetric ratchet for each member from the epoch's
+//! `encryption_secret`. Secrets are derived on demand and intermediate
+//! secrets are deleted once they are consumed.
+
+use std::collections::BTreeMap;
+
+use crate::ciphersuites::Ciphersuite;
+use crate::key_schedule::KeySchedule;
+use crate::util;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    InvalidLeaf,
+    /// The generation was used already and its key was deleted.
+    GenerationTooOld,
+    /// The generation is too far ahead of the ratchet.
+    GenerationTooFarInTheFuture,
+}
+
+/// How far a ratchet may be moved forward for a single message. This is also
+/// the number of keys of skipped generations that are kept.
+const MAX_FORWARD_DISTANCE: u32 = 1000;
+
+/// The ratchets of a member.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RatchetType {
+    Handshake,
+    Application,
+}
+
+impl RatchetType {
+    fn label(&self) -> &'static str {
+        match self {
+            RatchetType::Handshake => "handshake",
+            RatchetType::Application => "application",
+        }
+    }
+}
+
+// Section 9.1 Encryption Keys
+// ratchet_key_[i]_[j] = ExpandWithLabel(ratchet_secret_[i]_[j], "key", j, AEAD.Nk)
+// ratchet_nonce_[i]_[j] = ExpandWithLabel(ratchet_secret_[i]_[j], "nonce", j, AEAD.Nn)
+// ratchet_secret_[i]_[j+1] = ExpandWithLabel(ratchet_secret_[i]_[j], "secret", j, KDF.Nh)
+#[derive(Debug, Clone)]
+struct SenderRatchet {
+    secret: Vec<u8>,
+    generation: u32,
+    /// Keys and nonces of skipped generations that haven't been received yet.
+    skipped: BTreeMap<u32, (Vec<u8>, Vec<u8>)>,
+}
+
+impl SenderRatchet {
+    /// Key and nonce for the current generation. The ratchet moves on to
+    /// the next generation.
+    fn next(&mut self, key_schedule: &KeySchedule, ciphersuite: &Ciphersuite) -> (Vec<u8>, Vec<u8>) {
+        let generation = self.generation.to_be_bytes();
+        let key = key_schedule.expand_with_label(
+            &self.secret,
+            "key",
+            &generation,
+            ciphersuite.aead_key_length(),
+        );
+        let nonce = key_schedule.expand_with_label(
+            &self.secret,
+            "nonce",
+            &generation,
+            ciphersuite.aead_nonce_length(),
+        );
+        self.secret = key_schedule.expand_with_label(
+            &self.secret,
+            "secret",
+            &generation,
+            ciphersuite.hash_length(),
+        );
+        self.generation += 1;
+        (key, nonce)
+    }
+
+    /// Key and nonce for a received message with `generation`. The keys of
+    /// skipped generations are kept for messages that arrive out of order.
+    fn receive(
+        &mut self,
+        generation: u32,
+        key_schedule: &KeySchedule,
+        ciphersuite: &Ciphersuite,
+    ) -> Result<(Vec<u8>, Vec<u8>), Error> {
+        if generation < self.generation {
+            return self
+                .skipped
+                .remove(&generation)
+                .ok_or(Error::GenerationTooOld);
+        }
+        if generation - self.generation > MAX_FORWARD_DISTANCE {
+            return Err(Error::GenerationTooFarInTheFuture);
+        }
+        while self.generation < generation {
+            let skipped = self.generation;
+            let key_nonce = self.next(key_schedule, ciphersuite);
+            self.skipped.insert(skipped, key_nonce);
+        }
+        while self.skipped.len() > MAX_FORWARD_DISTANCE as usize {
+            self.skipped.pop_first();
+        }
+        Ok(self.next(key_schedule, ciphersuite))
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct LeafRatchets {
+    handshake: Option<SenderRatchet>,
+    application: Option<SenderRatchet>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct SecretTree {
+    ciphersuite: Ciphersuite,
+    n_leaves: u32,
+    /// The secrets of the nodes that haven't been consumed yet.
+    nodes: Vec<Option<Vec<u8>>>,
+    ratchets: Vec<LeafRatchets>,
+}
+
+impl SecretTree {
+    /// Create the secret tree of an epoch with `n_leaves` leaves.
+    pub(crate) fn new(ciphersuite: &Ciphersuite, encryption_secret: &[u8], n_leaves: u32) -> Self {
+        let mut nodes = vec![None; util::num_nodes(n_leaves) as usize];
+        nodes[util::root(n_leaves) as usize] = Some(encryption_secret.to_vec());
+        Self {
+            ciphersuite: ciphersuite.clone(),
+            n_leaves,
+            nodes,
+            ratchets: vec![LeafRatchets::default(); n_leaves as usize],
+        }
+    }
+
+    // tree_node_[root]_secret = encryption_secret
+    // tree_node_[left(x)]_secret = ExpandWithLabel(tree_node_[x]_secret, "tree", "left", KDF.Nh)
+    // tree_node_[right(x)]_secret = ExpandWithLabel(tree_node_[x]_secret, "tree", "right", KDF.Nh)
+    /// Derive the secret of leaf `leaf_index` and delete all secrets on the
+    /// way down.
+    fn derive_leaf_secret(&mut self, leaf_index: u32) -> Result<Vec<u8>, Error> {
+        let key_schedule = KeySchedule::new(&self.ciphersuite);
+        let leaf = 2 * leaf_index;
+        let mut path = util::direct_path_in(leaf, self.n_leaves);
+        path.reverse();
+        path.push(leaf);
+
+        // Start at the lowest node we still have the secret of.
+        let start = path
+            .iter()
+            .rposition(|&id| self.nodes[id as usize].is_some())
+            .ok_or(Error::InvalidLeaf)?;
+        for &id in path[start..].iter() {
+            if id == leaf {
+                break;
+            }
+            let secret = self.nodes[id as usize].take().ok_or(Error::InvalidLeaf)?;
+            let left = util::left(id);
+            let right = util::right(id, self.n_leaves);
+            let hash_length = self.ciphersuite.hash_length();
+            self.nodes[left as usize] =
+                Some(key_schedule.expand_with_label(&secret, "tree", b"left", hash_length));
+            self.nodes[right as usize] =
+                Some(key_schedule.expand_with_label(&secret, "tree", b"right", hash_length));
+        }
+        self.nodes[leaf as usize].take().ok_or(Error::InvalidLeaf)
+    }
+
+    // handshake_ratchet_secret_[N]_[0] = ExpandWithLabel(tree_node_[N]_secret, "handshake", "", KDF.Nh)
+    // application_ratchet_secret_[N]_[0] = ExpandWithLabel(tree_node_[N]_secret, "application", "", KDF.Nh)
+    fn ratchet(
+        &mut self,
+        leaf_index: u32,
+        ratchet_type: RatchetType,
+    ) -> Result<&mut SenderRatchet, Error> {
+        if leaf_index >= self.n_leaves {
+            return Err(Error::InvalidLeaf);
+        }
+        let initialized = self.ratchets[leaf_index as usize].handshake.is_some();
+        if !initialized {
+            let key_schedule = KeySchedule::new(&self.ciphersuite);
+            let leaf_secret = self.derive_leaf_secret(leaf_index)?;
+            let new_ratchet = |label| {
+                Some(SenderRatchet {
+                    secret: key_schedule.derive_secret(&leaf_secret, label),
+                    generation: 0,
+                    skipped: BTreeMap::new(),
+                })
+            };
+            self.ratchets[leaf_index as usize] = LeafRatchets {
+                handshake: new_ratchet(RatchetType::Handshake.label()),
+                application: new_ratchet(RatchetType::Application.label()),
+            };
+        }
+        let ratchets = &mut self.ratchets[leaf_index as usize];
+        let ratchet = match ratchet_type {
+            RatchetType::Handshake => ratchets.handshake.as_mut(),
+            RatchetType::Application => ratchets.application.as_mut(),
+        };
+        ratchet.ok_or(Error::InvalidLeaf)
+    }
+
+    /// Key, nonce and generation for the next message we send from
+    /// `leaf_index`.
+    pub(crate) fn next_sender_key(
+        &mut self,
+        leaf_index: u32,
+        ratchet_type: RatchetType,
+    ) -> Result<(Vec<u8>, Vec<u8>, u32), Error> {
+        let ciphersuite = self.ciphersuite.clone();
+        let key_schedule = KeySchedule::new(&ciphersuite);
+        let ratchet = self.ratchet(leaf_index, ratchet_type)?;
+        let generation = ratchet.generation;
+        let (key, nonce) = ratchet.next(&key_schedule, &ciphersuite);
+        Ok((key, nonce, generation))
+    }
+
+    /// Key and nonce for a message from `leaf_index` with `generation`.
+    /// The ratchet doesn't change until the key is consumed with
+    /// `consume_receiver_key`, i.e. once the message was decrypted.
+    pub(crate) fn receiver_key(
+        &mut self,
+        leaf_index: u32,
+        ratchet_type: RatchetType,
+        generation: u32,
+    ) -> Result<(Vec<u8>, Vec<u8>), Error> {
+        let ciphersuite = self.ciphersuite.clone();
+        let key_schedule = KeySchedule::new(&ciphersuite);
+        let mut ratchet = self.ratchet(leaf_index, ratchet_type)?.clone();
+        ratchet.receive(generation, &key_schedule, &ciphersuite)
+    }
+
+    /// Delete the key of `generation` and move the ratchet past it.
+    pub(crate) fn consume_receiver_key(
+        &mut self,
+        leaf_index: u32,
+        ratchet_type: RatchetType,
+        generation: u32,
+    ) -> Result<(), Error> {
+        let ciphersuite = self.ciphersuite.clone();
+        let key_schedule = KeySchedule::new(&ciphersuite);
+        let ratchet = self.ratchet(leaf_index, ratchet_type)?;
+        ratchet.receive(generation, &key_schedule, &ciphersuite)?;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_secret_tree() {
+    use crate::ciphersuites::Name;
+
+    let ciphersuite = Ciphersuite::new(Name::MLS10_128_DHKEMX25519_AES128GCM_SHA256_Ed25519);
+    let encryption_secret = vec![1u8; ciphersuite.hash_length()];
+    let mut sender = SecretTree::new(&ciphersuite, &encryption_secret, 5);
+    let mut receiver = SecretTree::new(&ciphersuite, &encryption_secret, 5);
+
+    let (key_0, nonce_0, generation) = sender
+        .next_sender_key(4, RatchetType::Application)
+        .unwrap();
+    assert_eq!(generation, 0);
+    let (key_1, _, generation) = sender
+        .next_sender_key(4, RatchetType::Application)
+        .unwrap();
+    assert_eq!(generation, 1);
+    assert_ne!(key_0, key_1);
+
+    // A key can be used again until it is consumed.
+    for _ in 0..2 {
+        assert_eq!(
+            receiver.receiver_key(4, RatchetType::Application, 1).unwrap().0,
+            key_1
+        );
+    }
+    receiver
+        .consume_receiver_key(4, RatchetType::Application, 1)
+        .unwrap();
+
+    // Out of order messages can be received once.
+    assert_eq!(
+        receiver.receiver_key(4, RatchetType::Application, 0),
+        Ok((key_0.clone(), nonce_0.clone()))
+    );
+    receiver
+        .consume_receiver_key(4, RatchetType::Application, 0)
+        .unwrap();
+    for generation in 0..2 {
+        assert_eq!(
+            receiver.receiver_key(4, RatchetType::Application, generation),
+            Err(Error::GenerationTooOld)
+        );
+    }
+
+    // Only the keys of the latest skipped generations are kept.
+    let far = 2 + MAX_FORWARD_DISTANCE;
+    receiver
+        .consume_receiver_key(4, RatchetType::Application, far)
+        .unwrap();
+    assert!(receiver.receiver_key(4, RatchetType::Application, 2).is_ok());
+    receiver
+        .consume_receiver_key(4, RatchetType::Application, far + 2)
+        .unwrap();
+    assert_eq!(
+        receiver.receiver_key(4, RatchetType::Application, 2),
+        Err(Error::GenerationTooOld)
+    );
+    assert!(receiver.receiver_key(4, RatchetType::Application, 3).is_ok());
+
+    // The handshake ratchet is independent.
+    let (key, nonce) = receiver
+        .receiver_key(4, RatchetType::Handshake, 0)
+        .unwrap();
+    assert_ne!((key, nonce), (key_0, nonce_0));
+
+    // Other leaves can still be derived.
+    assert!(receiver.receiver_key(0, RatchetType::Application, 0).is_ok());
+    assert_eq!(
+        receiver.receiver_key(5, RatchetType::Application, 0),
+        Err(Error::InvalidLeaf)
+    );
+}
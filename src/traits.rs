@@ -1,6 +1,29 @@
+use crate::encode_util::Cursor;
 
 pub trait Encode {
     fn encode(&self) -> Vec<u8> {
         unimplemented!();
     }
 }
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DecodeError {
+    EndOfStream,
+    InvalidValue,
+    TrailingBytes,
+}
+
+pub trait Decode: Sized {
+    fn decode(cursor: &mut Cursor) -> Result<Self, DecodeError>;
+
+    /// Decode `bytes` completely.
+    /// Returns an error if there are bytes left after decoding.
+    fn decode_all(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut cursor = Cursor::new(bytes);
+        let out = Self::decode(&mut cursor)?;
+        if !cursor.is_empty() {
+            return Err(DecodeError::TrailingBytes);
+        }
+        Ok(out)
+    }
+}
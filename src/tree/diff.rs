@@ -0,0 +1,113 @@
+//! Copy-on-write changes to a tree.
+//!
+//! Creating or processing a commit changes the tree tentatively. Instead of
+//! cloning the whole tree, a `TreeDiff` reads through to the tree it was
+//! created from and only copies the nodes that are changed.
+//! If the changes are to be kept, the diff is turned into a
+//! `StagedTreeDiff` that can be merged into the original tree in
+//! O(changes). Merging into any other tree fails.
+
+use std::collections::BTreeMap;
+
+use crate::tree::*;
+
+/// The nodes of a base tree with some of them changed.
+#[derive(Debug, Clone)]
+pub struct NodeDiff<'a> {
+    base: &'a Tree,
+    /// Changed nodes and nodes that were added after the base's nodes.
+    changes: BTreeMap<u32, Node>,
+    len: usize,
+}
+
+impl<'a> NodeStore for NodeDiff<'a> {
+    fn node(&self, id: u32) -> Option<&Node> {
+        match self.changes.get(&id) {
+            Some(node) => Some(node),
+            None => self.base.nodes.get(id as usize),
+        }
+    }
+    fn node_mut(&mut self, id: u32) -> Option<&mut Node> {
+        if !self.changes.contains_key(&id) {
+            let node = self.base.nodes.get(id as usize)?.clone();
+            self.changes.insert(id, node);
+        }
+        self.changes.get_mut(&id)
+    }
+    fn push(&mut self, node: Node) {
+        self.changes.insert(self.len as u32, node);
+        self.len += 1;
+    }
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+/// A tree with changes on top of another tree.
+pub type TreeDiff<'a> = Tree<NodeDiff<'a>>;
+
+/// The changes of a `TreeDiff` without the reference to the base tree.
+#[derive(Debug, Clone)]
+pub struct StagedTreeDiff {
+    changes: BTreeMap<u32, Node>,
+    base_len: usize,
+    /// The tree hash of the base tree.
+    base_hash: Vec<u8>,
+    id_ctr: u32,
+    leaf_id_ctr: u32,
+}
+
+impl Tree {
+    /// Start a new diff on top of this tree.
+    pub fn diff(&self) -> TreeDiff<'_> {
+        Tree {
+            nodes: NodeDiff {
+                base: self,
+                changes: BTreeMap::new(),
+                len: self.nodes.len(),
+            },
+            id_ctr: self.id_ctr,
+            leaf_id_ctr: self.leaf_id_ctr,
+            ciphersuite: self.ciphersuite.clone(),
+        }
+    }
+
+    /// Apply the changes of a diff that was created from this tree.
+    /// Fails without changing the tree if the tree isn't the diff's base.
+    pub fn merge_diff(&mut self, staged_diff: StagedTreeDiff) -> Result<(), Error> {
+        if self.nodes.len() != staged_diff.base_len
+            || self.cached_tree_hash()? != staged_diff.base_hash
+        {
+            return Err(Error::DiffBaseMismatch);
+        }
+        // The changes are ordered by id, so added nodes are pushed in order.
+        for (id, node) in staged_diff.changes {
+            match self.nodes.get_mut(id as usize) {
+                Some(n) => *n = node,
+                None => self.nodes.push(node),
+            }
+        }
+        self.id_ctr = staged_diff.id_ctr;
+        self.leaf_id_ctr = staged_diff.leaf_id_ctr;
+        Ok(())
+    }
+}
+
+impl<'a> TreeDiff<'a> {
+    /// Keep the changes of this diff such that they can be merged into the
+    /// base tree.
+    pub fn into_staged(self) -> Result<StagedTreeDiff, Error> {
+        Ok(StagedTreeDiff {
+            base_len: self.nodes.base.nodes.len(),
+            base_hash: self.nodes.base.cached_tree_hash()?,
+            changes: self.nodes.changes,
+            id_ctr: self.id_ctr,
+            leaf_id_ctr: self.leaf_id_ctr,
+        })
+    }
+
+    /// The number of nodes that were changed or added.
+    pub fn num_changes(&self) -> usize {
+        self.nodes.changes.len()
+    }
+}
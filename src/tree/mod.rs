@@ -2,6 +2,8 @@
 //! A binary tree structure
 
 use crate::ciphersuites::*;
+use crate::credentials::Error as CredentialError;
+use crate::key_package::Error as KeyPackageError;
 use crate::{traits::Encode, util};
 use evercrypt::prelude::*;
 
@@ -13,6 +15,16 @@ use node::*;
 
 mod pretty_print;
 
+mod ratchet_tree;
+
+mod operations;
+
+mod update_path;
+pub use update_path::{UpdatePath, UpdatePathNode};
+
+mod diff;
+pub use diff::{NodeDiff, StagedTreeDiff, TreeDiff};
+
 use std::fmt::Debug;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -20,11 +32,57 @@ pub enum Error {
     InvalidNodeId,
     NodeIdTooFarTooTheRight,
     InconsistentTreeState,
+    DecodingError,
+    BlankLeaf,
+    InvalidUpdatePath,
+    InvalidParentHash,
+    /// The new key package of a leaf has another identity than the old one.
+    IdentityChanged,
+    /// A diff is merged into another tree than it was created from.
+    DiffBaseMismatch,
+    DecryptionFailed,
+    CredentialError(CredentialError),
+    /// A leaf of an imported tree has an invalid key package.
+    InvalidKeyPackage(KeyPackageError),
+}
+
+impl From<CredentialError> for Error {
+    fn from(e: CredentialError) -> Self {
+        Error::CredentialError(e)
+    }
+}
+
+/// Storage for the nodes of a tree.
+/// A `Tree` owns its nodes in a `Vec`. A `TreeDiff` reads through to the
+/// nodes of another tree and only stores the nodes it changed.
+pub trait NodeStore {
+    fn node(&self, id: u32) -> Option<&Node>;
+    fn node_mut(&mut self, id: u32) -> Option<&mut Node>;
+    fn push(&mut self, node: Node);
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
 }
 
-#[derive(Debug)]
-pub struct Tree {
-    nodes: Vec<Node>,
+impl NodeStore for Vec<Node> {
+    fn node(&self, id: u32) -> Option<&Node> {
+        self.get(id as usize)
+    }
+    fn node_mut(&mut self, id: u32) -> Option<&mut Node> {
+        self.get_mut(id as usize)
+    }
+    fn push(&mut self, node: Node) {
+        Vec::push(self, node)
+    }
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Tree<S = Vec<Node>> {
+    nodes: S,
     id_ctr: u32,
     leaf_id_ctr: u32,
     ciphersuite: Ciphersuite,
@@ -39,17 +97,28 @@ impl Tree {
             ciphersuite: Ciphersuite::from(csuite),
         }
     }
+}
+
+impl<S: NodeStore> Tree<S> {
+    /// All nodes from left to right.
+    pub(crate) fn iter_nodes(&self) -> impl Iterator<Item = &Node> {
+        (0..self.nodes.len() as u32).filter_map(move |id| self.nodes.node(id))
+    }
     pub fn get_height(&self) -> u32 {
         util::log2(self.id_ctr)
     }
     pub fn num_nodes(&self) -> u32 {
         util::num_nodes(self.leaf_id_ctr)
     }
+    /// Get a mutable reference to a node.
+    /// Because the node may be changed, the node and its direct path are
+    /// marked dirty and their hashes get recomputed on the next `hash`.
     pub fn get_node_mut(&mut self, id: u32) -> Result<&mut Node, Error> {
         if id > self.id_ctr {
             return Err(Error::InvalidNodeId);
         }
-        match self.nodes.get_mut(id as usize) {
+        self.mark_dirty(id);
+        match self.nodes.node_mut(id) {
             Some(n) => Ok(n),
             None => Err(Error::InvalidNodeId),
         }
@@ -58,7 +127,7 @@ impl Tree {
         if id > self.id_ctr {
             return Err(Error::InvalidNodeId);
         }
-        match self.nodes.get(id as usize) {
+        match self.nodes.node(id) {
             Some(n) => Ok(n),
             None => Err(Error::InvalidNodeId),
         }
@@ -67,53 +136,53 @@ impl Tree {
         self.get_node(2 * leaf_id)
     }
     pub fn get_parent(&self, node_id: u32) -> Result<&Node, Error> {
-        let node = match self.nodes.get(node_id as usize) {
+        let node = match self.nodes.node(node_id) {
             Some(n) => n,
             None => return Err(Error::InvalidNodeId),
         };
         self.get_parent_from_node(node)
     }
     pub fn get_parent_from_node(&self, node: &Node) -> Result<&Node, Error> {
-        match self.nodes.get(node.get_parent_id() as usize) {
+        match self.nodes.node(node.get_parent_id()) {
             Some(n) => Ok(n),
             None => Err(Error::InvalidNodeId),
         }
     }
     pub fn get_sibling(&self, node_id: u32) -> Result<&Node, Error> {
-        let node = match self.nodes.get(node_id as usize) {
+        let node = match self.nodes.node(node_id) {
             Some(n) => n,
             None => return Err(Error::InvalidNodeId),
         };
         self.get_sibling_from_node(node)
     }
     pub fn get_sibling_from_node(&self, node: &Node) -> Result<&Node, Error> {
-        match self.nodes.get(node.get_sibling_id(self.leaf_id_ctr) as usize) {
+        match self.nodes.node(node.get_sibling_id(self.leaf_id_ctr)) {
             Some(n) => Ok(n),
             None => Err(Error::InvalidNodeId),
         }
     }
     pub fn get_left_child(&self, node_id: u32) -> Result<&Node, Error> {
-        let node = match self.nodes.get(node_id as usize) {
+        let node = match self.nodes.node(node_id) {
             Some(n) => n,
             None => return Err(Error::InvalidNodeId),
         };
         self.get_left_child_from_node(node)
     }
     pub fn get_left_child_from_node(&self, node: &Node) -> Result<&Node, Error> {
-        match self.nodes.get(node.get_left_id() as usize) {
+        match self.nodes.node(node.get_left_id()) {
             Some(n) => Ok(n),
             None => Err(Error::InvalidNodeId),
         }
     }
     pub fn get_right_child(&self, node_id: u32) -> Result<&Node, Error> {
-        let node = match self.nodes.get(node_id as usize) {
+        let node = match self.nodes.node(node_id) {
             Some(n) => n,
             None => return Err(Error::InvalidNodeId),
         };
         self.get_right_child_from_node(node)
     }
     pub fn get_right_child_from_node(&self, node: &Node) -> Result<&Node, Error> {
-        match self.nodes.get(node.get_right_id(self.leaf_id_ctr) as usize) {
+        match self.nodes.node(node.get_right_id(self.leaf_id_ctr)) {
             Some(n) => Ok(n),
             None => Err(Error::InvalidNodeId),
         }
@@ -135,12 +204,18 @@ impl Tree {
         let id = (1 << util::log2(self.num_nodes())) - 1;
         let out = self
             .nodes
-            .get(id)
+            .node(id)
             .expect("Couldn't get the node with ID I was looking for.");
-        debug_assert_eq!(id as u32, out.id);
+        debug_assert_eq!(id, out.id);
         out
     }
     pub fn add_leaf(&mut self) {
+        let old_root = if self.nodes.is_empty() {
+            None
+        } else {
+            Some(self.get_root().id)
+        };
+
         if self.id_ctr % 2 != 0 {
             // We add an intermediate node before we add the leaf.
             self.nodes
@@ -150,15 +225,42 @@ impl Tree {
 
         // Now add the leaf.
         let new_leaf = Node::new(NodeType::Leaf, self.id_ctr, Some(self.leaf_id_ctr));
+        let new_leaf_id = new_leaf.id;
         self.nodes.push(new_leaf);
         self.leaf_id_ctr += 1;
         self.id_ctr += 1;
+
+        // The new leaf changes the right children along its direct path.
+        // If the tree grew, the old root is hashed as inner node now.
+        self.mark_dirty(new_leaf_id);
+        if let Some(old_root) = old_root {
+            if old_root != self.get_root().id {
+                self.mark_dirty(old_root);
+            }
+        }
+    }
+
+    /// Mark the node `node_id` and its direct path as dirty.
+    /// Nodes on the direct path that are not in the tree (because the tree
+    /// isn't full) are skipped.
+    fn mark_dirty(&mut self, node_id: u32) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let root_id = self.get_root().id;
+        let mut path = vec![node_id];
+        path.extend(util::direct_path(node_id, root_id));
+        for id in path {
+            if let Some(node) = self.nodes.node_mut(id) {
+                node.dirty = true;
+            }
+        }
     }
 
     pub fn get_level(&self, level: u32) -> Vec<&Node> {
         let mut out = Vec::new();
 
-        for node in &self.nodes {
+        for node in self.iter_nodes() {
             if node.get_level() == level {
                 out.push(node);
             }
@@ -170,7 +272,7 @@ impl Tree {
     // Section 5.2
     // The resolution of a node is an ordered list of non-blank nodes that
     // collectively cover all non-blank descendants of the node.
-    fn resolution(&self, node_id: u32) -> Result<Vec<&Node>, Error> {
+    pub(crate) fn resolution(&self, node_id: u32) -> Result<Vec<&Node>, Error> {
         let node = self.get_node(node_id)?;
 
         if node.blank {
@@ -191,25 +293,18 @@ impl Tree {
 
         // Add all unmerged leaves
         for &unmerged in node.unmerged_leaves.iter() {
-            resolution.push(self.get_node(unmerged)?);
+            resolution.push(self.get_leaf_node(unmerged)?);
         }
 
         Ok(resolution)
     }
 
-    fn hash_parent(&self, node: &Node) -> Result<Vec<u8>, Error> {
-        let left_node = self.get_node(node.get_left_id())?;
-        let left_hash = self.hash_node(left_node)?;
-        let right_node = self.get_node(node.get_right_id(self.leaf_id_ctr))?;
-        let right_hash = self.hash_node(right_node)?;
-
-        if !left_node.subtree_hash.is_empty() && left_node.subtree_hash != left_hash {
-            return Err(Error::InconsistentTreeState);
-        }
-        if !right_node.subtree_hash.is_empty() && right_node.subtree_hash != right_hash {
-            return Err(Error::InconsistentTreeState);
-        }
-
+    fn hash_parent_from_children(
+        &self,
+        node: &Node,
+        left_hash: &[u8],
+        right_hash: &[u8],
+    ) -> Vec<u8> {
         let parent_node = if self.get_root().id == node.id {
             None
         } else {
@@ -219,8 +314,15 @@ impl Tree {
                 &node.parent_hash,
             ))
         };
-        let input = ParentNodeHashInput::new(node.id, parent_node, &left_hash, &right_hash);
-        Ok(hash(self.ciphersuite.hash, &input.encode()))
+        let input = ParentNodeHashInput::new(node.id, parent_node, left_hash, right_hash);
+        hash(self.ciphersuite.hash, &input.encode())
+    }
+    fn hash_parent(&self, node: &Node) -> Result<Vec<u8>, Error> {
+        let left_node = self.get_node(node.get_left_id())?;
+        let left_hash = self.hash_node(left_node)?;
+        let right_node = self.get_node(node.get_right_id(self.leaf_id_ctr))?;
+        let right_hash = self.hash_node(right_node)?;
+        Ok(self.hash_parent_from_children(node, &left_hash, &right_hash))
     }
     fn hash_leaf(&self, node: &Node) -> Result<Vec<u8>, Error> {
         let input = LeafNodeHashInput::new(node.id, node.key_package.as_ref());
@@ -228,11 +330,80 @@ impl Tree {
     }
 
     // Section 7.5 Tree Hash
+    /// Compute the hash of the subtree rooted at `node_id` from scratch.
+    /// The cached subtree hashes are neither used nor updated.
     pub fn hash(&self, node_id: u32) -> Result<Vec<u8>, Error> {
         let node = self.get_node(node_id)?;
-        self.hash_node(node)
+        match node.node_type {
+            NodeType::Parent => {
+                let left_hash = self.hash(node.get_left_id())?;
+                let right_hash = self.hash(node.get_right_id(self.leaf_id_ctr))?;
+                Ok(self.hash_parent_from_children(node, &left_hash, &right_hash))
+            }
+            NodeType::Leaf => self.hash_leaf(node),
+            _ => Err(Error::InconsistentTreeState),
+        }
     }
+
+    /// Compute the tree hash and cache the subtree hashes.
+    /// Only nodes that are marked dirty are re-hashed. All other nodes use
+    /// their cached `subtree_hash`.
+    pub fn tree_hash(&mut self) -> Result<Vec<u8>, Error> {
+        if self.nodes.is_empty() {
+            return Err(Error::InvalidNodeId);
+        }
+        let root_id = self.get_root().id;
+        self.update_hash(root_id)?;
+        Ok(self.get_root().subtree_hash.clone())
+    }
+
+    /// Recompute the `subtree_hash` of `node_id` if it is dirty.
+    /// Because a dirty node always has a dirty direct path, clean nodes
+    /// have clean subtrees and the recursion stops there.
+    fn update_hash(&mut self, node_id: u32) -> Result<(), Error> {
+        let node = self.get_node(node_id)?;
+        if !node.dirty {
+            return Ok(());
+        }
+        let subtree_hash = match node.node_type {
+            NodeType::Parent => {
+                let left_id = node.get_left_id();
+                let right_id = node.get_right_id(self.leaf_id_ctr);
+                self.update_hash(left_id)?;
+                self.update_hash(right_id)?;
+                let node = self.get_node(node_id)?;
+                let left_hash = &self.get_node(left_id)?.subtree_hash;
+                let right_hash = &self.get_node(right_id)?.subtree_hash;
+                self.hash_parent_from_children(node, left_hash, right_hash)
+            }
+            NodeType::Leaf => self.hash_leaf(node)?,
+            _ => return Err(Error::InconsistentTreeState),
+        };
+        // Don't use get_node_mut here. It would mark the path dirty again.
+        let node = match self.nodes.node_mut(node_id) {
+            Some(n) => n,
+            None => return Err(Error::InvalidNodeId),
+        };
+        node.subtree_hash = subtree_hash;
+        node.dirty = false;
+        Ok(())
+    }
+
+    /// The tree hash from the cached subtree hashes. Unlike `tree_hash`
+    /// the cache isn't updated.
+    pub(crate) fn cached_tree_hash(&self) -> Result<Vec<u8>, Error> {
+        if self.nodes.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.hash_node(self.get_root())
+    }
+
+    /// Hash the subtree rooted at `node`.
+    /// The cached `subtree_hash` is used if the node is clean.
     pub fn hash_node(&self, node: &Node) -> Result<Vec<u8>, Error> {
+        if !node.dirty {
+            return Ok(node.subtree_hash.clone());
+        }
         match node.node_type {
             NodeType::Parent => self.hash_parent(node),
             NodeType::Leaf => self.hash_leaf(node),
@@ -240,3 +411,39 @@ impl Tree {
         }
     }
 }
+
+#[test]
+fn test_tree_hash_cache() {
+    use crate::credentials::{BasicCredential, Credential};
+    use crate::key_package::{KeyPackage, ProtocolVersion};
+
+    let key_package = || {
+        KeyPackage::new(
+            ProtocolVersion::Mls10,
+            Ciphersuite::default(),
+            Credential::basic(BasicCredential::ed25519()),
+        )
+    };
+    let mut tree = Tree::new(Name::MLS10_128_DHKEMX25519_AES128GCM_SHA256_Ed25519);
+    let assert_cache_valid = |tree: &mut Tree| {
+        let cached = tree.tree_hash().unwrap();
+        let root_id = tree.get_root().id;
+        assert_eq!(cached, tree.hash(root_id).unwrap());
+    };
+
+    for _ in 0..5 {
+        tree.add_member(key_package()).unwrap();
+        assert_cache_valid(&mut tree);
+    }
+    tree.get_node_mut(3).unwrap().public_key = vec![1; 32];
+    tree.get_node_mut(3).unwrap().blank = false;
+    assert_cache_valid(&mut tree);
+    tree.get_node_mut(3).unwrap().unmerged_leaves.push(1);
+    assert_cache_valid(&mut tree);
+    tree.remove_member(1).unwrap();
+    assert_cache_valid(&mut tree);
+    tree.add_member(key_package()).unwrap();
+    assert_cache_valid(&mut tree);
+    tree.remove_member(4).unwrap();
+    assert_cache_valid(&mut tree);
+}
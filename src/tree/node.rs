@@ -1,7 +1,7 @@
 use crate::{key_package::KeyPackage, util};
 use std::fmt::Debug;
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone)]
 pub struct Node {
     pub(crate) id: u32,
     pub(crate) leaf_id: Option<u32>, // Only used if node_type is Leaf
@@ -14,6 +14,7 @@ pub struct Node {
     pub(crate) parent_hash: Vec<u8>,
     pub(crate) blank: bool, // if true, nothing else is set
     pub(crate) subtree_hash: Vec<u8>,
+    pub(crate) dirty: bool, // if true, subtree_hash has to be recomputed
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -43,6 +44,7 @@ impl Node {
             parent_hash: Vec::new(),
             blank: true,
             subtree_hash: Vec::new(),
+            dirty: true,
         }
     }
 
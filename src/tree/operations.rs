@@ -0,0 +1,117 @@
+//! Membership changes on the tree.
+use crate::tree::*;
+use crate::key_package::KeyPackage;
+
+impl<S: NodeStore> Tree<S> {
+    pub fn num_leaves(&self) -> u32 {
+        self.leaf_id_ctr
+    }
+
+    pub fn get_ciphersuite(&self) -> &Ciphersuite {
+        &self.ciphersuite
+    }
+
+    pub(crate) fn root_id(&self) -> u32 {
+        util::root(self.leaf_id_ctr)
+    }
+
+    /// The ids of the direct path of `node_id`, including the root.
+    pub(crate) fn direct_path_ids(&self, node_id: u32) -> Vec<u32> {
+        util::direct_path_in(node_id, self.leaf_id_ctr)
+    }
+
+    /// The ids of the copath of `node_id`.
+    pub(crate) fn copath_ids(&self, node_id: u32) -> Vec<u32> {
+        util::copath_in(node_id, self.leaf_id_ctr)
+    }
+
+    /// The key package of the member at `leaf_index` if the leaf isn't blank.
+    pub fn get_key_package(&self, leaf_index: u32) -> Option<&KeyPackage> {
+        self.get_leaf_node(leaf_index).ok()?.key_package.as_ref()
+    }
+
+    /// All members of the group as `(leaf_index, key_package)`.
+    pub fn members(&self) -> impl Iterator<Item = (u32, &KeyPackage)> {
+        self.iter_nodes()
+            .filter_map(|n| match (n.leaf_id, n.key_package.as_ref()) {
+                (Some(leaf_id), Some(key_package)) => Some((leaf_id, key_package)),
+                _ => None,
+            })
+    }
+
+    /// Blank the node `node_id`.
+    pub(crate) fn blank_node(&mut self, node_id: u32) -> Result<(), Error> {
+        let node = self.get_node_mut(node_id)?;
+        node.blank = true;
+        node.private_key = Vec::new();
+        node.public_key = Vec::new();
+        node.key_package = None;
+        node.unmerged_leaves = Vec::new();
+        node.parent_hash = Vec::new();
+        Ok(())
+    }
+
+    /// Put `key_package` into the leaf at `leaf_index`.
+    /// If the key package has the private init key it's used as the leaf's
+    /// private key.
+    pub(crate) fn set_leaf(&mut self, leaf_index: u32, key_package: KeyPackage) -> Result<(), Error> {
+        let node = self.get_node_mut(2 * leaf_index)?;
+        node.public_key = key_package.get_public_hpke_init_key().to_vec();
+        node.private_key = key_package.get_private_hpke_init_key().to_vec();
+        node.key_package = Some(key_package);
+        node.blank = false;
+        Ok(())
+    }
+
+    // Section 11.1.1 Add
+    /// Add a new member to the leftmost blank leaf, or extend the tree if
+    /// there's none. Returns the leaf index of the new member.
+    pub fn add_member(&mut self, key_package: KeyPackage) -> Result<u32, Error> {
+        let leaf_index = match (0..self.leaf_id_ctr).find(|&i| self.get_key_package(i).is_none()) {
+            Some(i) => i,
+            None => {
+                self.add_leaf();
+                self.leaf_id_ctr - 1
+            }
+        };
+        self.set_leaf(leaf_index, key_package)?;
+
+        // The new member doesn't know the private keys of its direct path.
+        for id in self.direct_path_ids(2 * leaf_index) {
+            if !self.get_node(id)?.blank {
+                self.get_node_mut(id)?.unmerged_leaves.push(leaf_index);
+            }
+        }
+        Ok(leaf_index)
+    }
+
+    // Section 11.1.2 Update
+    /// Replace the key package at `leaf_index` and blank its direct path.
+    pub(crate) fn update_member(
+        &mut self,
+        leaf_index: u32,
+        key_package: KeyPackage,
+    ) -> Result<(), Error> {
+        if self.get_key_package(leaf_index).is_none() {
+            return Err(Error::BlankLeaf);
+        }
+        self.set_leaf(leaf_index, key_package)?;
+        for id in self.direct_path_ids(2 * leaf_index) {
+            self.blank_node(id)?;
+        }
+        Ok(())
+    }
+
+    // Section 11.1.3 Remove
+    /// Blank the leaf at `leaf_index` and its direct path.
+    pub fn remove_member(&mut self, leaf_index: u32) -> Result<(), Error> {
+        if self.get_key_package(leaf_index).is_none() {
+            return Err(Error::BlankLeaf);
+        }
+        self.blank_node(2 * leaf_index)?;
+        for id in self.direct_path_ids(2 * leaf_index) {
+            self.blank_node(id)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,195 @@
+//! Encoding of the public tree for the `ratchet_tree` extension.
+use crate::encode_util::*;
+use crate::tree::tree_hash::ParentNode;
+use crate::tree::*;
+use crate::{
+    clock::Clock,
+    key_package::KeyPackage,
+    traits::{Decode, DecodeError},
+};
+
+// From Section 7.6.
+// enum {
+//     reserved(0),
+//     leaf(1),
+//     parent(2),
+//     (255)
+// } NodeType;
+const LEAF: u8 = 1;
+const PARENT: u8 = 2;
+
+// struct {
+//     NodeType node_type;
+//     select (Node.node_type) {
+//         case leaf:   KeyPackage key_package;
+//         case parent: ParentNode node;
+//     };
+// } Node;
+//
+// optional<Node> tree<1..2^32-1>;
+pub(crate) enum PublicNode {
+    Leaf(KeyPackage),
+    Parent {
+        public_key: Vec<u8>,
+        unmerged_leaves: Vec<u32>,
+        parent_hash: Vec<u8>,
+    },
+}
+
+impl Decode for PublicNode {
+    fn decode(cursor: &mut Cursor) -> Result<Self, DecodeError> {
+        match u8::decode(cursor)? {
+            LEAF => Ok(PublicNode::Leaf(KeyPackage::decode(cursor)?)),
+            PARENT => Ok(PublicNode::Parent {
+                public_key: decode_slice(LenType::L16, cursor)?,
+                unmerged_leaves: decode_u32_slice(LenType::L32, cursor)?,
+                parent_hash: decode_slice(LenType::L8, cursor)?,
+            }),
+            _ => Err(DecodeError::InvalidValue),
+        }
+    }
+}
+
+/// Encode the public part of `node` as `optional<Node>`.
+/// A non-blank leaf without key package can't be encoded.
+fn encode_node(node: &Node, out: &mut Vec<u8>) -> Result<(), Error> {
+    if node.blank {
+        out.push(0x0);
+        return Ok(());
+    }
+    out.push(0x1);
+    match (node.node_type, node.key_package.as_ref()) {
+        (NodeType::Leaf, Some(key_package)) => {
+            out.push(LEAF);
+            out.extend(key_package.encode());
+        }
+        (NodeType::Parent, _) => {
+            out.push(PARENT);
+            out.extend(
+                ParentNode::new(&node.public_key, &node.unmerged_leaves, &node.parent_hash)
+                    .encode(),
+            );
+        }
+        _ => return Err(Error::InconsistentTreeState),
+    }
+    Ok(())
+}
+
+impl<S: NodeStore> Tree<S> {
+    /// Export the public tree as `optional<Node> tree<1..2^32-1>`.
+    pub fn export_public(&self) -> Result<Vec<u8>, Error> {
+        let mut nodes = Vec::new();
+        for node in self.iter_nodes() {
+            encode_node(node, &mut nodes)?;
+        }
+        let mut out = Vec::new();
+        encode_slice(LenType::L32, &nodes, &mut out);
+        Ok(out)
+    }
+}
+
+impl Tree {
+    /// Build a tree from its public encoding.
+    /// The key packages of all leaves are validated at the current time of
+    /// `clock`. The resulting tree holds no private keys.
+    pub fn import_public(csuite: Name, bytes: &[u8], clock: &dyn Clock) -> Result<Self, Error> {
+        let public_nodes: Vec<Option<PublicNode>> = {
+            let mut cursor = Cursor::new(bytes);
+            let nodes = decode_vec(LenType::L32, &mut cursor).map_err(|_| Error::DecodingError)?;
+            if !cursor.is_empty() {
+                return Err(Error::DecodingError);
+            }
+            nodes
+        };
+        if public_nodes.len().is_multiple_of(2) {
+            return Err(Error::InconsistentTreeState);
+        }
+
+        let mut tree = Tree::new(csuite);
+        for _ in 0..public_nodes.len() / 2 + 1 {
+            tree.add_leaf();
+        }
+        debug_assert_eq!(tree.nodes.len(), public_nodes.len());
+
+        for (node, public_node) in tree.nodes.iter_mut().zip(public_nodes) {
+            let public_node = match public_node {
+                Some(n) => n,
+                None => continue,
+            };
+            match (node.node_type, public_node) {
+                (NodeType::Leaf, PublicNode::Leaf(key_package)) => {
+                    key_package
+                        .validate(clock)
+                        .map_err(Error::InvalidKeyPackage)?;
+                    node.public_key = key_package.get_public_hpke_init_key().to_vec();
+                    node.key_package = Some(key_package);
+                }
+                (
+                    NodeType::Parent,
+                    PublicNode::Parent {
+                        public_key,
+                        unmerged_leaves,
+                        parent_hash,
+                    },
+                ) => {
+                    node.public_key = public_key;
+                    node.unmerged_leaves = unmerged_leaves;
+                    node.parent_hash = parent_hash;
+                }
+                _ => return Err(Error::InconsistentTreeState),
+            }
+            node.blank = false;
+        }
+        Ok(tree)
+    }
+}
+
+#[test]
+fn test_public_tree_round_trip() {
+    use crate::clock::FixedClock;
+    use crate::credentials::{BasicCredential, Credential};
+    use crate::key_package::{Error as KeyPackageError, ProtocolVersion};
+
+    let ciphersuite = Name::MLS10_128_DHKEMX25519_AES128GCM_SHA256_Ed25519;
+    let key_package = || {
+        KeyPackage::new_with_lifetime(
+            ProtocolVersion::Mls10,
+            ciphersuite.into(),
+            Credential::basic(BasicCredential::ed25519()),
+            60,
+            &FixedClock(1000),
+        )
+        .unwrap()
+    };
+    let mut tree = Tree::new(ciphersuite);
+    for _ in 0..3 {
+        tree.add_member(key_package()).unwrap();
+    }
+    for &id in &[1, 3] {
+        let node = tree.get_node_mut(id).unwrap();
+        node.public_key = vec![id as u8; 32];
+        node.private_key = vec![id as u8 + 1; 32];
+        node.parent_hash = vec![id as u8 + 2; 32];
+        node.blank = false;
+    }
+    // Leaf 3 is added below node 3 and leaf 5 stays blank.
+    tree.add_member(key_package()).unwrap();
+    tree.add_member(key_package()).unwrap();
+    tree.add_leaf();
+    assert_eq!(tree.get_node(3).unwrap().unmerged_leaves, [3]);
+    assert!(tree.iter_nodes().any(|n| !n.private_key.is_empty()));
+
+    let exported = tree.export_public().unwrap();
+    let mut imported = Tree::import_public(ciphersuite, &exported, &FixedClock(1000)).unwrap();
+    assert_eq!(imported.export_public().unwrap(), exported);
+    assert_eq!(imported.tree_hash().unwrap(), tree.tree_hash().unwrap());
+    assert_eq!(imported.get_node(3).unwrap().unmerged_leaves, [3]);
+    assert!(imported.get_node(10).unwrap().blank);
+    assert!(imported.iter_nodes().all(|n| n.private_key.is_empty()));
+
+    // Key packages in the tree are validated.
+    assert_eq!(
+        Tree::import_public(ciphersuite, &exported, &FixedClock(2000)).unwrap_err(),
+        Error::InvalidKeyPackage(KeyPackageError::Expired)
+    );
+}
@@ -2,6 +2,11 @@ use crate::encode_util::*;
 use crate::{key_package::KeyPackage, traits::Encode};
 
 // From Section 7.5.
+// struct {
+//     HPKEPublicKey public_key;
+//     uint32 unmerged_leaves<0..2^32-1>;
+//     opaque parent_hash<0..255>;
+// } ParentNode;
 // Only used for encoding
 pub(crate) struct ParentNode<'a> {
     hpke_public_key: &'a [u8],
@@ -26,13 +31,20 @@ impl<'a> ParentNode<'a> {
 impl<'a> Encode for ParentNode<'a> {
     fn encode(&self) -> Vec<u8> {
         let mut out = Vec::new();
-        encode_slice(LenType::L16, &self.hpke_public_key, &mut out);
-        encode_u32_slice(LenType::L32, &self.unmerged_leaves, &mut out);
+        encode_slice(LenType::L16, self.hpke_public_key, &mut out);
+        encode_u32_slice(LenType::L32, self.unmerged_leaves, &mut out);
+        encode_slice(LenType::L8, self.parent_hash, &mut out);
         out
     }
 }
 
 // From Section 7.5.
+// struct {
+//     uint32 node_index;
+//     optional<ParentNode> parent_node;
+//     opaque left_hash<0..255>;
+//     opaque right_hash<0..255>;
+// } ParentNodeHashInput;
 // Only used for encoding
 pub(crate) struct ParentNodeHashInput<'a> {
     node_index: u32,
@@ -62,6 +74,8 @@ impl<'a> Encode for ParentNodeHashInput<'a> {
         let mut out = Vec::new();
         out.extend(&self.node_index.to_be_bytes());
         out.extend(self.parent_node.encode());
+        encode_slice(LenType::L8, self.left_hash, &mut out);
+        encode_slice(LenType::L8, self.right_hash, &mut out);
         out
     }
 }
@@ -88,3 +102,48 @@ impl<'a> Encode for LeafNodeHashInput<'a> {
         out
     }
 }
+
+// From Section 7.4.
+// struct {
+//     HPKEPublicKey public_key;
+//     opaque parent_hash<0..255>;
+// } ParentHashInput;
+pub(crate) struct ParentHashInput<'a> {
+    public_key: &'a [u8],
+    parent_hash: &'a [u8],
+}
+
+impl<'a> ParentHashInput<'a> {
+    pub(crate) fn new(public_key: &'a [u8], parent_hash: &'a [u8]) -> Self {
+        Self {
+            public_key,
+            parent_hash,
+        }
+    }
+}
+
+impl<'a> Encode for ParentHashInput<'a> {
+    fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        encode_slice(LenType::L16, self.public_key, &mut out);
+        encode_slice(LenType::L8, self.parent_hash, &mut out);
+        out
+    }
+}
+
+#[test]
+fn test_parent_node_hash_input_encoding() {
+    let parent_node = ParentNode::new(&[1, 2], &[3], &[4]);
+    let input = ParentNodeHashInput::new(5, Some(parent_node), &[6], &[7, 8]);
+    #[rustfmt::skip]
+    let expected = [
+        0, 0, 0, 5,             // node_index
+        1,                      // parent_node present
+        0, 2, 1, 2,             // public_key
+        0, 0, 0, 4, 0, 0, 0, 3, // unmerged_leaves
+        1, 4,                   // parent_hash
+        1, 6,                   // left_hash
+        2, 7, 8,                // right_hash
+    ];
+    assert_eq!(input.encode(), expected);
+}
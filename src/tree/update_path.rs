@@ -0,0 +1,396 @@
+//! Generating and applying update paths.
+use crate::ciphersuites::HpkeCiphertext;
+use crate::encode_util::*;
+use crate::extensions::{Extension, ExtensionType, ParentHashExtension};
+use crate::key_package::KeyPackage;
+use crate::key_schedule::KeySchedule;
+use crate::traits::{Decode, DecodeError};
+use crate::tree::*;
+
+// Section 7.7 Update Paths
+// struct {
+//     HPKEPublicKey public_key;
+//     HPKECiphertext encrypted_path_secret<0..2^32-1>;
+// } UpdatePathNode;
+#[derive(Debug, Clone)]
+pub struct UpdatePathNode {
+    pub(crate) public_key: Vec<u8>,
+    pub(crate) encrypted_path_secret: Vec<HpkeCiphertext>,
+}
+
+impl Encode for UpdatePathNode {
+    fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        encode_slice(LenType::L16, &self.public_key, &mut out);
+        encode_vec(LenType::L32, &self.encrypted_path_secret, &mut out);
+        out
+    }
+}
+
+impl Decode for UpdatePathNode {
+    fn decode(cursor: &mut Cursor) -> Result<Self, DecodeError> {
+        Ok(Self {
+            public_key: decode_slice(LenType::L16, cursor)?,
+            encrypted_path_secret: decode_vec(LenType::L32, cursor)?,
+        })
+    }
+}
+
+// struct {
+//     KeyPackage leaf_key_package;
+//     UpdatePathNode nodes<0..2^32-1>;
+// } UpdatePath;
+#[derive(Debug, Clone)]
+pub struct UpdatePath {
+    pub(crate) leaf_key_package: KeyPackage,
+    pub(crate) nodes: Vec<UpdatePathNode>,
+}
+
+impl UpdatePath {
+    pub fn get_leaf_key_package(&self) -> &KeyPackage {
+        &self.leaf_key_package
+    }
+}
+
+impl Encode for UpdatePath {
+    fn encode(&self) -> Vec<u8> {
+        let mut out = self.leaf_key_package.encode();
+        encode_vec(LenType::L32, &self.nodes, &mut out);
+        out
+    }
+}
+
+impl Decode for UpdatePath {
+    fn decode(cursor: &mut Cursor) -> Result<Self, DecodeError> {
+        Ok(Self {
+            leaf_key_package: KeyPackage::decode(cursor)?,
+            nodes: decode_vec(LenType::L32, cursor)?,
+        })
+    }
+}
+
+/// The secrets of a path we generated ourselves.
+pub(crate) struct OwnPath {
+    /// The path secrets for the nodes on the direct path, leaf to root.
+    pub(crate) path_secrets: Vec<Vec<u8>>,
+    pub(crate) commit_secret: Vec<u8>,
+}
+
+impl<S: NodeStore> Tree<S> {
+    /// The parent hash of `node_id` as stored in its children.
+    pub(crate) fn parent_hash_of(&self, node_id: u32) -> Result<Vec<u8>, Error> {
+        let node = self.get_node(node_id)?;
+        let input = ParentHashInput::new(&node.public_key, &node.parent_hash);
+        Ok(hash(self.ciphersuite.hash, &input.encode()))
+    }
+
+    /// Set the parent hashes along the direct path of `leaf_index`, top down.
+    /// Returns the parent hash for the leaf.
+    fn set_parent_hashes(&mut self, leaf_index: u32) -> Result<Vec<u8>, Error> {
+        // The root has an empty parent hash.
+        let mut parent_hash = Vec::new();
+        for id in self.direct_path_ids(2 * leaf_index).into_iter().rev() {
+            self.get_node_mut(id)?.parent_hash = parent_hash;
+            parent_hash = self.parent_hash_of(id)?;
+        }
+        Ok(parent_hash)
+    }
+
+    // Section 7.4 Synchronizing Views of the Tree
+    //   path_secret[0] = DeriveSecret(leaf_secret, "path")
+    //   path_secret[n] = DeriveSecret(path_secret[n-1], "path")
+    //   node_secret[n] = DeriveSecret(path_secret[n], "node")
+    //   node_priv[n], node_pub[n] = KEM.DeriveKeyPair(node_secret[n])
+    /// Replace the keys on the direct path of our leaf `leaf_index` with keys
+    /// derived from `leaf_secret`.
+    /// The leaf gets a new init key and parent hash and is signed again.
+    pub(crate) fn apply_own_path(
+        &mut self,
+        leaf_index: u32,
+        leaf_secret: &[u8],
+    ) -> Result<OwnPath, Error> {
+        let key_schedule = KeySchedule::new(&self.ciphersuite);
+        let mut path_secrets = Vec::new();
+        let mut path_secret = leaf_secret.to_vec();
+        for id in self.direct_path_ids(2 * leaf_index) {
+            path_secret = key_schedule.derive_secret(&path_secret, "path");
+            let (private_key, public_key) = self
+                .ciphersuite
+                .derive_hpke_key_pair(&key_schedule.derive_secret(&path_secret, "node"));
+            let node = self.get_node_mut(id)?;
+            node.private_key = private_key;
+            node.public_key = public_key;
+            node.unmerged_leaves = Vec::new();
+            node.blank = false;
+            path_secrets.push(path_secret.clone());
+        }
+        let commit_secret = key_schedule.derive_secret(&path_secret, "path");
+        let leaf_parent_hash = self.set_parent_hashes(leaf_index)?;
+
+        let mut key_package = self
+            .get_key_package(leaf_index)
+            .ok_or(Error::BlankLeaf)?
+            .clone();
+        let (private_key, public_key) = self
+            .ciphersuite
+            .derive_hpke_key_pair(&key_schedule.derive_secret(leaf_secret, "node"));
+        key_package.set_hpke_init_key(&private_key, &public_key);
+        key_package.set_parent_hash(ParentHashExtension::new(&leaf_parent_hash));
+        key_package.sign()?;
+        self.set_leaf(leaf_index, key_package)?;
+
+        Ok(OwnPath {
+            path_secrets,
+            commit_secret,
+        })
+    }
+
+    /// Encrypt the path secrets of `own_path` to the resolution of the
+    /// copath of `leaf_index`.
+    /// The `group_context` is used as HPKE info. Leaves in `exclude` (new
+    /// members) don't get the path secrets.
+    pub(crate) fn encrypt_path_secrets(
+        &self,
+        leaf_index: u32,
+        own_path: &OwnPath,
+        group_context: &[u8],
+        exclude: &[u32],
+    ) -> Result<UpdatePath, Error> {
+        let path = self.direct_path_ids(2 * leaf_index);
+        let copath = self.copath_ids(2 * leaf_index);
+        debug_assert_eq!(path.len(), own_path.path_secrets.len());
+
+        let mut nodes = Vec::new();
+        for ((&id, &copath_id), path_secret) in path
+            .iter()
+            .zip(copath.iter())
+            .zip(own_path.path_secrets.iter())
+        {
+            let mut encrypted_path_secret = Vec::new();
+            for node in self.resolution(copath_id)? {
+                if let Some(leaf_id) = node.leaf_id {
+                    if exclude.contains(&leaf_id) {
+                        continue;
+                    }
+                }
+                encrypted_path_secret.push(self.ciphersuite.hpke_seal(
+                    &node.public_key,
+                    group_context,
+                    &[],
+                    path_secret,
+                ));
+            }
+            nodes.push(UpdatePathNode {
+                public_key: self.get_node(id)?.public_key.clone(),
+                encrypted_path_secret,
+            });
+        }
+
+        Ok(UpdatePath {
+            leaf_key_package: self
+                .get_key_package(leaf_index)
+                .ok_or(Error::BlankLeaf)?
+                .clone(),
+            nodes,
+        })
+    }
+}
+
+impl<S: NodeStore> Tree<S> {
+    /// Apply the public part of an `UpdatePath` from the member at
+    /// `sender_leaf`: its new key package and the new public keys on its
+    /// direct path. The parent hash in the leaf is checked.
+    pub(crate) fn apply_update_path(
+        &mut self,
+        sender_leaf: u32,
+        path: &UpdatePath,
+    ) -> Result<(), Error> {
+        let direct_path = self.direct_path_ids(2 * sender_leaf);
+        if direct_path.len() != path.nodes.len() || !path.leaf_key_package.verify() {
+            return Err(Error::InvalidUpdatePath);
+        }
+        let identity = path.leaf_key_package.get_credential().get_identity();
+        match self.get_key_package(sender_leaf) {
+            None => return Err(Error::BlankLeaf),
+            Some(kp) if kp.get_credential().get_identity() != identity => {
+                return Err(Error::IdentityChanged)
+            }
+            Some(_) => {}
+        }
+
+        for (&id, path_node) in direct_path.iter().zip(path.nodes.iter()) {
+            let node = self.get_node_mut(id)?;
+            node.private_key = Vec::new();
+            node.public_key = path_node.public_key.clone();
+            node.unmerged_leaves = Vec::new();
+            node.blank = false;
+        }
+        let leaf_parent_hash = self.set_parent_hashes(sender_leaf)?;
+        match path
+            .leaf_key_package
+            .get_extension(ExtensionType::ParentHash)
+        {
+            Some(Extension::ParentHash(e)) if e.get_parent_hash() == leaf_parent_hash.as_slice() => {}
+            _ => return Err(Error::InvalidParentHash),
+        }
+
+        let mut key_package = path.leaf_key_package.clone();
+        key_package.set_hpke_init_key(&[], path.leaf_key_package.get_public_hpke_init_key());
+        self.set_leaf(sender_leaf, key_package)
+    }
+
+    /// Decrypt the path secret for us (`own_leaf`) from an `UpdatePath`
+    /// that was applied with `apply_update_path`, and derive the private keys
+    /// on the direct path of `sender_leaf` from our common ancestor on.
+    /// Returns the commit secret.
+    pub(crate) fn decrypt_update_path(
+        &mut self,
+        sender_leaf: u32,
+        own_leaf: u32,
+        path: &UpdatePath,
+        group_context: &[u8],
+        exclude: &[u32],
+    ) -> Result<Vec<u8>, Error> {
+        let direct_path = self.direct_path_ids(2 * sender_leaf);
+        let copath = self.copath_ids(2 * sender_leaf);
+        let own_direct_path = self.direct_path_ids(2 * own_leaf);
+
+        // The first node on the sender's direct path that's also on ours is
+        // our common ancestor. We are below the copath node at that position.
+        let i = direct_path
+            .iter()
+            .position(|id| own_direct_path.contains(id))
+            .ok_or(Error::InvalidUpdatePath)?;
+
+        // Find the node in the copath node's resolution we have the private key of.
+        let resolution: Vec<&Node> = self
+            .resolution(copath[i])?
+            .into_iter()
+            .filter(|n| match n.leaf_id {
+                Some(leaf_id) => !exclude.contains(&leaf_id),
+                None => true,
+            })
+            .collect();
+        let (k, private_key) = resolution
+            .iter()
+            .enumerate()
+            .find(|(_, n)| !n.private_key.is_empty())
+            .map(|(k, n)| (k, n.private_key.clone()))
+            .ok_or(Error::DecryptionFailed)?;
+        let ciphertext = path.nodes[i]
+            .encrypted_path_secret
+            .get(k)
+            .ok_or(Error::InvalidUpdatePath)?;
+        let path_secret = self
+            .ciphersuite
+            .hpke_open(&private_key, group_context, &[], ciphertext)
+            .map_err(|_| Error::DecryptionFailed)?;
+
+        // The public keys were set by `apply_update_path` already.
+        self.apply_path_secret(&direct_path[i..], &path_secret)
+    }
+
+    /// Derive the private keys for `node_ids` (a part of a direct path,
+    /// bottom up) starting with `path_secret` for the first node.
+    /// The derived public keys must match the ones in the tree.
+    /// Returns the commit secret.
+    pub(crate) fn apply_path_secret(
+        &mut self,
+        node_ids: &[u32],
+        path_secret: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        let key_schedule = KeySchedule::new(&self.ciphersuite);
+        let mut path_secret = path_secret.to_vec();
+        for (j, &id) in node_ids.iter().enumerate() {
+            if j > 0 {
+                path_secret = key_schedule.derive_secret(&path_secret, "path");
+            }
+            let (private_key, public_key) = self
+                .ciphersuite
+                .derive_hpke_key_pair(&key_schedule.derive_secret(&path_secret, "node"));
+            let node = self.get_node_mut(id)?;
+            if public_key != node.public_key {
+                return Err(Error::InvalidUpdatePath);
+            }
+            node.private_key = private_key;
+        }
+        Ok(key_schedule.derive_secret(&path_secret, "path"))
+    }
+
+    // Section 7.5 Parent Hash
+    /// Check that every non-blank parent node was set by an update path
+    /// from below, i.e. there's a node directly below it on a direct path
+    /// that holds its parent hash.
+    pub(crate) fn verify_parent_hashes(&self) -> Result<(), Error> {
+        let mut valid = vec![false; self.nodes.len()];
+        for node in self.iter_nodes().filter(|n| !n.blank) {
+            let parent_id = match self.direct_path_ids(node.id).first() {
+                Some(&id) => id,
+                None => continue,
+            };
+            let parent_hash = if node.is_leaf() {
+                match node
+                    .key_package
+                    .as_ref()
+                    .and_then(|kp| kp.get_extension(ExtensionType::ParentHash))
+                {
+                    Some(Extension::ParentHash(e)) => e.get_parent_hash().to_vec(),
+                    _ => continue,
+                }
+            } else {
+                node.parent_hash.clone()
+            };
+            if !self.get_node(parent_id)?.blank && parent_hash == self.parent_hash_of(parent_id)? {
+                valid[parent_id as usize] = true;
+            }
+        }
+        let all_valid = self
+            .iter_nodes()
+            .filter(|n| !n.is_leaf() && !n.blank)
+            .all(|n| valid[n.id as usize]);
+        if all_valid {
+            Ok(())
+        } else {
+            Err(Error::InvalidParentHash)
+        }
+    }
+}
+
+#[test]
+fn test_update_path_identity() {
+    use crate::ciphersuites::Name;
+    use crate::clock::FixedClock;
+    use crate::credentials::{BasicCredential, Credential};
+    use crate::key_package::ProtocolVersion;
+
+    let ciphersuite = Name::MLS10_128_DHKEMX25519_AES128GCM_SHA256_Ed25519;
+    let key_package = || {
+        KeyPackage::new_with_lifetime(
+            ProtocolVersion::Mls10,
+            ciphersuite.into(),
+            Credential::basic(BasicCredential::ed25519()),
+            60,
+            &FixedClock(1000),
+        )
+        .unwrap()
+    };
+    let mut tree = Tree::new(ciphersuite);
+    tree.add_member(key_package()).unwrap();
+    tree.add_member(key_package()).unwrap();
+    let mut receiver = tree.clone();
+
+    let own_path = tree.apply_own_path(0, &[7; 32]).unwrap();
+    let path = tree
+        .encrypt_path_secrets(0, &own_path, b"context", &[])
+        .unwrap();
+
+    // The path must not replace the leaf with somebody else's key package.
+    let mut forged = path.clone();
+    forged.leaf_key_package = key_package();
+    assert_eq!(
+        receiver.clone().apply_update_path(0, &forged).unwrap_err(),
+        Error::IdentityChanged
+    );
+    receiver.apply_update_path(0, &path).unwrap();
+    assert_eq!(receiver.tree_hash().unwrap(), tree.tree_hash().unwrap());
+}
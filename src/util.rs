@@ -84,3 +84,81 @@ pub(crate) fn direct_path(x: u32, r: u32) -> Vec<u32> {
     }
     d
 }
+
+/// The root of a tree with n_leaves leaves.
+pub(crate) fn root(n_leaves: u32) -> u32 {
+    (1 << log2(num_nodes(n_leaves))) - 1
+}
+
+/// The parent of x in a tree with n_leaves leaves.
+/// Other than `parent` this skips nodes that are not in the tree because it
+/// isn't full.
+pub(crate) fn parent_in(x: u32, n_leaves: u32) -> u32 {
+    let mut p = parent(x);
+    while p >= num_nodes(n_leaves) {
+        p = parent(p);
+    }
+    p
+}
+
+/// The sibling of x in a tree with n_leaves leaves.
+pub(crate) fn sibling_in(x: u32, n_leaves: u32) -> u32 {
+    let p = parent_in(x, n_leaves);
+    if x < p {
+        right(p, n_leaves)
+    } else {
+        left(p)
+    }
+}
+
+/// The direct path of x in a tree with n_leaves leaves, including the root.
+pub(crate) fn direct_path_in(x: u32, n_leaves: u32) -> Vec<u32> {
+    let r = root(n_leaves);
+    let mut d = Vec::new();
+    let mut n = x;
+    while n != r {
+        n = parent_in(n, n_leaves);
+        d.push(n);
+    }
+    d
+}
+
+/// The copath of x in a tree with n_leaves leaves.
+pub(crate) fn copath_in(x: u32, n_leaves: u32) -> Vec<u32> {
+    let mut path = vec![x];
+    path.extend(direct_path_in(x, n_leaves));
+    path.pop();
+    path.iter().map(|&v| sibling_in(v, n_leaves)).collect()
+}
+
+/// Compare `a` and `b` in constant time for equal lengths.
+/// The lengths themselves aren't secret.
+pub(crate) fn equal_ct(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b.iter()).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[test]
+fn test_equal_ct() {
+    assert!(equal_ct(&[], &[]));
+    assert!(equal_ct(&[1, 2, 3], &[1, 2, 3]));
+    assert!(!equal_ct(&[1, 2, 3], &[1, 2, 4]));
+    assert!(!equal_ct(&[1, 2, 3], &[1, 2]));
+}
+
+#[test]
+fn test_paths() {
+    // 5 leaves:
+    //               7
+    //       3
+    //   1       5
+    // 0   2   4   6   8
+    assert_eq!(root(5), 7);
+    assert_eq!(direct_path_in(8, 5), vec![7]);
+    assert_eq!(copath_in(8, 5), vec![3]);
+    assert_eq!(direct_path_in(2, 5), vec![1, 3, 7]);
+    assert_eq!(copath_in(2, 5), vec![0, 5, 8]);
+    assert_eq!(sibling_in(3, 5), 8);
+}
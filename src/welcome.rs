@@ -0,0 +1,557 @@
+//! Welcoming new members
+
+use crate::ciphersuites::{Ciphersuite, HpkeCiphertext};
+use crate::clock::Clock;
+use crate::commit::{interim_transcript_hash_from_tag, verify_confirmation_tag, CreateCommitResult};
+use crate::credentials::{Credential, Error as CredentialError};
+use crate::encode_util::*;
+use crate::extensions::{
+    decode_extensions, encode_extensions, Extension, ExtensionType, RatchetTreeExtension,
+};
+use crate::group_context::GroupContext;
+use crate::key_package::{KeyPackage, ProtocolVersion};
+use crate::key_schedule::{EpochSecrets, KeySchedule};
+use crate::psk::{psk_secret, Error as PskError, PreSharedKeyId, PskStore};
+use crate::traits::{Decode, DecodeError, Encode};
+use crate::tree::{Error as TreeError, Tree};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// None of the secrets is for our key package.
+    NoMatchingKeyPackage,
+    CiphersuiteMismatch,
+    VersionMismatch,
+    DecryptionFailed,
+    DecodingError(DecodeError),
+    /// There's neither a `ratchet_tree` extension nor an out-of-band tree.
+    MissingRatchetTree,
+    TreeHashMismatch,
+    InvalidSigner,
+    InvalidSignature,
+    /// Our key package is not in the tree.
+    OwnLeafNotFound,
+    InvalidConfirmationTag,
+    TreeError(TreeError),
+    PskError(PskError),
+    CredentialError(CredentialError),
+}
+
+impl From<PskError> for Error {
+    fn from(e: PskError) -> Self {
+        Error::PskError(e)
+    }
+}
+
+impl From<DecodeError> for Error {
+    fn from(e: DecodeError) -> Self {
+        Error::DecodingError(e)
+    }
+}
+
+impl From<TreeError> for Error {
+    fn from(e: TreeError) -> Self {
+        Error::TreeError(e)
+    }
+}
+
+impl From<CredentialError> for Error {
+    fn from(e: CredentialError) -> Self {
+        Error::CredentialError(e)
+    }
+}
+
+// Section 12 Group Creation
+// struct {
+//   opaque group_id<0..255>;
+//   uint64 epoch;
+//   opaque tree_hash<0..255>;
+//   opaque confirmed_transcript_hash<0..255>;
+//   Extension group_context_extensions<0..2^32-1>;
+//   Extension other_extensions<0..2^32-1>;
+//   MAC confirmation_tag;
+//   uint32 signer_index;
+//   opaque signature<0..2^16-1>;
+// } GroupInfo;
+#[derive(Debug, Clone)]
+pub struct GroupInfo {
+    pub(crate) group_id: Vec<u8>,
+    pub(crate) epoch: u64,
+    pub(crate) tree_hash: Vec<u8>,
+    pub(crate) confirmed_transcript_hash: Vec<u8>,
+    pub(crate) group_context_extensions: Vec<Extension>,
+    pub(crate) other_extensions: Vec<Extension>,
+    pub(crate) confirmation_tag: Vec<u8>,
+    pub(crate) signer_index: u32,
+    pub(crate) signature: Vec<u8>,
+}
+
+impl GroupInfo {
+    /// Create the GroupInfo of the epoch described by `group_context`,
+    /// signed by the member at `signer_index`.
+    pub(crate) fn new(
+        group_context: &GroupContext,
+        other_extensions: Vec<Extension>,
+        confirmation_tag: &[u8],
+        signer_index: u32,
+        credential: &Credential,
+    ) -> Result<Self, CredentialError> {
+        let mut group_info = Self {
+            group_id: group_context.group_id.clone(),
+            epoch: group_context.epoch,
+            tree_hash: group_context.tree_hash.clone(),
+            confirmed_transcript_hash: group_context.confirmed_transcript_hash.clone(),
+            group_context_extensions: group_context.extensions.clone(),
+            other_extensions,
+            confirmation_tag: confirmation_tag.to_vec(),
+            signer_index,
+            signature: Vec::new(),
+        };
+        group_info.sign(credential)?;
+        Ok(group_info)
+    }
+
+    /// Everything but the signature.
+    fn encode_tbs(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        encode_slice(LenType::L8, &self.group_id, &mut out);
+        out.extend(self.epoch.encode());
+        encode_slice(LenType::L8, &self.tree_hash, &mut out);
+        encode_slice(LenType::L8, &self.confirmed_transcript_hash, &mut out);
+        out.extend(encode_extensions(&self.group_context_extensions));
+        out.extend(encode_extensions(&self.other_extensions));
+        encode_slice(LenType::L8, &self.confirmation_tag, &mut out);
+        out.extend(self.signer_index.encode());
+        out
+    }
+
+    pub(crate) fn sign(&mut self, credential: &Credential) -> Result<(), CredentialError> {
+        self.signature = credential.credential.sign(&self.encode_tbs())?;
+        Ok(())
+    }
+
+    pub(crate) fn verify(&self, credential: &Credential) -> bool {
+        credential
+            .credential
+            .verify(&self.encode_tbs(), &self.signature)
+    }
+
+    pub fn get_group_id(&self) -> &[u8] {
+        &self.group_id
+    }
+    pub fn get_epoch(&self) -> u64 {
+        self.epoch
+    }
+    pub fn get_other_extensions(&self) -> &[Extension] {
+        &self.other_extensions
+    }
+
+    /// The group context of the epoch.
+    pub fn group_context(&self) -> GroupContext {
+        GroupContext::new(
+            &self.group_id,
+            self.epoch,
+            &self.tree_hash,
+            &self.confirmed_transcript_hash,
+            &self.group_context_extensions,
+        )
+    }
+
+    /// The public tree of the epoch, taken from the `ratchet_tree`
+    /// extension or `ratchet_tree` if it was distributed out of band.
+    /// The key packages of the leaves (at the current time of `clock`), the
+    /// tree hash, the parent hashes and the signature of the GroupInfo are
+    /// checked.
+    pub(crate) fn verified_tree(
+        &self,
+        ciphersuite: &Ciphersuite,
+        ratchet_tree: Option<&Tree>,
+        clock: &dyn Clock,
+    ) -> Result<Tree, Error> {
+        let mut tree = match self
+            .other_extensions
+            .iter()
+            .find(|e| e.get_type() == ExtensionType::RatchetTree)
+        {
+            Some(Extension::RatchetTree(e)) => e.to_tree(*ciphersuite.get_name(), clock)?,
+            // Only take the public part of a tree we got out of band.
+            _ => Tree::import_public(
+                *ciphersuite.get_name(),
+                &ratchet_tree.ok_or(Error::MissingRatchetTree)?.export_public()?,
+                clock,
+            )?,
+        };
+        if tree.tree_hash()? != self.tree_hash {
+            return Err(Error::TreeHashMismatch);
+        }
+        tree.verify_parent_hashes()?;
+        let signer = tree
+            .get_key_package(self.signer_index)
+            .ok_or(Error::InvalidSigner)?;
+        if !self.verify(signer.get_credential()) {
+            return Err(Error::InvalidSignature);
+        }
+        Ok(tree)
+    }
+}
+
+impl Encode for GroupInfo {
+    fn encode(&self) -> Vec<u8> {
+        let mut out = self.encode_tbs();
+        encode_slice(LenType::L16, &self.signature, &mut out);
+        out
+    }
+}
+
+impl Decode for GroupInfo {
+    fn decode(cursor: &mut Cursor) -> Result<Self, DecodeError> {
+        Ok(Self {
+            group_id: decode_slice(LenType::L8, cursor)?,
+            epoch: u64::decode(cursor)?,
+            tree_hash: decode_slice(LenType::L8, cursor)?,
+            confirmed_transcript_hash: decode_slice(LenType::L8, cursor)?,
+            group_context_extensions: decode_extensions(cursor)?,
+            other_extensions: decode_extensions(cursor)?,
+            confirmation_tag: decode_slice(LenType::L8, cursor)?,
+            signer_index: u32::decode(cursor)?,
+            signature: decode_slice(LenType::L16, cursor)?,
+        })
+    }
+}
+
+// struct {
+//   opaque path_secret<1..255>;
+// } PathSecret;
+//
+// struct {
+//   opaque joiner_secret<1..255>;
+//   optional<PathSecret> path_secret;
+//   PreSharedKeyID psks<0..2^16-1>;
+// } GroupSecrets;
+#[derive(Debug, Clone)]
+pub(crate) struct GroupSecrets {
+    pub(crate) joiner_secret: Vec<u8>,
+    pub(crate) path_secret: Option<Vec<u8>>,
+    pub(crate) psks: Vec<PreSharedKeyId>,
+}
+
+impl Encode for GroupSecrets {
+    fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        encode_slice(LenType::L8, &self.joiner_secret, &mut out);
+        match &self.path_secret {
+            Some(path_secret) => {
+                out.push(0x1);
+                encode_slice(LenType::L8, path_secret, &mut out);
+            }
+            None => out.push(0x0),
+        }
+        encode_vec(LenType::L16, &self.psks, &mut out);
+        out
+    }
+}
+
+impl Decode for GroupSecrets {
+    fn decode(cursor: &mut Cursor) -> Result<Self, DecodeError> {
+        let joiner_secret = decode_slice(LenType::L8, cursor)?;
+        let path_secret = match u8::decode(cursor)? {
+            0x0 => None,
+            0x1 => Some(decode_slice(LenType::L8, cursor)?),
+            _ => return Err(DecodeError::InvalidValue),
+        };
+        Ok(Self {
+            joiner_secret,
+            path_secret,
+            psks: decode_vec(LenType::L16, cursor)?,
+        })
+    }
+}
+
+// struct {
+//   opaque key_package_hash<1..255>;
+//   HPKECiphertext encrypted_group_secrets;
+// } EncryptedGroupSecrets;
+#[derive(Debug, Clone)]
+pub struct EncryptedGroupSecrets {
+    pub(crate) key_package_hash: Vec<u8>,
+    pub(crate) encrypted_group_secrets: HpkeCiphertext,
+}
+
+impl Encode for EncryptedGroupSecrets {
+    fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        encode_slice(LenType::L8, &self.key_package_hash, &mut out);
+        out.extend(self.encrypted_group_secrets.encode());
+        out
+    }
+}
+
+impl Decode for EncryptedGroupSecrets {
+    fn decode(cursor: &mut Cursor) -> Result<Self, DecodeError> {
+        Ok(Self {
+            key_package_hash: decode_slice(LenType::L8, cursor)?,
+            encrypted_group_secrets: HpkeCiphertext::decode(cursor)?,
+        })
+    }
+}
+
+// struct {
+//   ProtocolVersion version = mls10;
+//   CipherSuite cipher_suite;
+//   EncryptedGroupSecrets secrets<0..2^32-1>;
+//   opaque encrypted_group_info<1..2^32-1>;
+// } Welcome;
+#[derive(Debug, Clone)]
+pub struct Welcome {
+    pub(crate) version: ProtocolVersion,
+    pub(crate) cipher_suite: Ciphersuite,
+    pub(crate) secrets: Vec<EncryptedGroupSecrets>,
+    pub(crate) encrypted_group_info: Vec<u8>,
+}
+
+impl Welcome {
+    pub fn get_cipher_suite(&self) -> &Ciphersuite {
+        &self.cipher_suite
+    }
+    pub fn get_secrets(&self) -> &[EncryptedGroupSecrets] {
+        &self.secrets
+    }
+}
+
+impl Encode for Welcome {
+    fn encode(&self) -> Vec<u8> {
+        let mut out = self.version.encode();
+        out.extend(self.cipher_suite.encode());
+        encode_vec(LenType::L32, &self.secrets, &mut out);
+        encode_slice(LenType::L32, &self.encrypted_group_info, &mut out);
+        out
+    }
+}
+
+impl Decode for Welcome {
+    fn decode(cursor: &mut Cursor) -> Result<Self, DecodeError> {
+        Ok(Self {
+            version: ProtocolVersion::decode_supported(cursor)?,
+            cipher_suite: Ciphersuite::decode(cursor)?,
+            secrets: decode_vec(LenType::L32, cursor)?,
+            encrypted_group_info: decode_slice(LenType::L32, cursor)?,
+        })
+    }
+}
+
+// welcome_nonce = ExpandWithLabel(welcome_secret, "nonce", "", AEAD.Nn)
+// welcome_key = ExpandWithLabel(welcome_secret, "key", "", AEAD.Nk)
+pub(crate) fn welcome_key_nonce(
+    ciphersuite: &Ciphersuite,
+    joiner_secret: &[u8],
+) -> (Vec<u8>, Vec<u8>) {
+    let key_schedule = KeySchedule::new(ciphersuite);
+    let welcome_secret = key_schedule.welcome_secret(joiner_secret);
+    let key = key_schedule.expand_with_label(
+        &welcome_secret,
+        "key",
+        &[],
+        ciphersuite.aead_key_length(),
+    );
+    let nonce = key_schedule.expand_with_label(
+        &welcome_secret,
+        "nonce",
+        &[],
+        ciphersuite.aead_nonce_length(),
+    );
+    (key, nonce)
+}
+
+impl CreateCommitResult<'_> {
+    /// Create the `Welcome` for the members added by this commit.
+    /// Returns `None` if the commit doesn't add anybody.
+    /// With `include_ratchet_tree` the public tree is sent in the GroupInfo.
+    pub fn create_welcome(&self, include_ratchet_tree: bool) -> Result<Option<Welcome>, Error> {
+        if self.added.is_empty() {
+            return Ok(None);
+        }
+        let tree = &self.tree;
+        let ciphersuite = tree.get_ciphersuite();
+        let credential = tree
+            .get_key_package(self.own_leaf)
+            .ok_or(TreeError::BlankLeaf)?
+            .get_credential();
+
+        let mut other_extensions = Vec::new();
+        if include_ratchet_tree {
+            other_extensions.push(Extension::RatchetTree(RatchetTreeExtension::new(tree)?));
+        }
+        let group_info = GroupInfo::new(
+            &self.group_context,
+            other_extensions,
+            self.plaintext
+                .get_confirmation_tag()
+                .expect("own commits always have a confirmation tag"),
+            self.own_leaf,
+            credential,
+        )?;
+        let (key, nonce) = welcome_key_nonce(ciphersuite, &self.joiner_secret);
+        let encrypted_group_info = ciphersuite.aead_seal(&key, &nonce, &[], &group_info.encode());
+
+        let own_direct_path = tree.direct_path_ids(2 * self.own_leaf);
+        let mut secrets = Vec::new();
+        for (leaf_index, key_package) in self.added.iter() {
+            // The new member gets the path secret of our common ancestor.
+            let path_secret = match &self.path_secrets {
+                Some(path_secrets) => {
+                    let new_member_direct_path = tree.direct_path_ids(2 * leaf_index);
+                    own_direct_path
+                        .iter()
+                        .position(|id| new_member_direct_path.contains(id))
+                        .map(|i| path_secrets[i].clone())
+                }
+                None => None,
+            };
+            let group_secrets = GroupSecrets {
+                joiner_secret: self.joiner_secret.clone(),
+                path_secret,
+                psks: self.psks.clone(),
+            };
+            secrets.push(EncryptedGroupSecrets {
+                key_package_hash: key_package.hash(),
+                encrypted_group_secrets: ciphersuite.hpke_seal(
+                    key_package.get_public_hpke_init_key(),
+                    &[],
+                    &[],
+                    &group_secrets.encode(),
+                ),
+            });
+        }
+
+        Ok(Some(Welcome {
+            version: ProtocolVersion::Mls10,
+            cipher_suite: ciphersuite.clone(),
+            secrets,
+            encrypted_group_info,
+        }))
+    }
+}
+
+/// The state of the group after joining it with a `Welcome`.
+#[derive(Debug)]
+pub struct ProcessWelcomeResult {
+    pub(crate) tree: Tree,
+    pub(crate) group_context: GroupContext,
+    pub(crate) epoch_secrets: EpochSecrets,
+    pub(crate) interim_transcript_hash: Vec<u8>,
+    pub(crate) confirmation_tag: Vec<u8>,
+    pub(crate) own_leaf: u32,
+    pub(crate) psks: Vec<PreSharedKeyId>,
+}
+
+impl ProcessWelcomeResult {
+    pub fn get_tree(&self) -> &Tree {
+        &self.tree
+    }
+    pub fn get_group_context(&self) -> &GroupContext {
+        &self.group_context
+    }
+    pub fn get_own_leaf(&self) -> u32 {
+        self.own_leaf
+    }
+}
+
+// Section 12 Group Creation (Welcome)
+/// Join a group with a `Welcome` using our `key_package` (with its private
+/// init key).
+/// The ratchet tree is taken from the `ratchet_tree` extension in the
+/// GroupInfo, or `ratchet_tree` if it was distributed out of band.
+/// PSKs used in the epoch are looked up in `psk_store`. The key packages in
+/// the tree are validated at the current time of `clock`.
+pub fn process_welcome(
+    welcome: &Welcome,
+    key_package: &KeyPackage,
+    ratchet_tree: Option<&Tree>,
+    psk_store: &dyn PskStore,
+    clock: &dyn Clock,
+) -> Result<ProcessWelcomeResult, Error> {
+    let ciphersuite = &welcome.cipher_suite;
+    if ciphersuite.get_name() != key_package.get_cipher_suite().get_name() {
+        return Err(Error::CiphersuiteMismatch);
+    }
+    if welcome.version != key_package.get_version() {
+        return Err(Error::VersionMismatch);
+    }
+    let key_schedule = KeySchedule::new(ciphersuite);
+
+    // Decrypt our GroupSecrets.
+    let key_package_hash = key_package.hash();
+    let encrypted_group_secrets = welcome
+        .secrets
+        .iter()
+        .find(|s| s.key_package_hash == key_package_hash)
+        .ok_or(Error::NoMatchingKeyPackage)?;
+    let group_secrets = ciphersuite
+        .hpke_open(
+            key_package.get_private_hpke_init_key(),
+            &[],
+            &[],
+            &encrypted_group_secrets.encrypted_group_secrets,
+        )
+        .map_err(|_| Error::DecryptionFailed)?;
+    let group_secrets = GroupSecrets::decode_all(&group_secrets)?;
+
+    // Decrypt the GroupInfo.
+    let (key, nonce) = welcome_key_nonce(ciphersuite, &group_secrets.joiner_secret);
+    let group_info = ciphersuite
+        .aead_open(&key, &nonce, &[], &welcome.encrypted_group_info)
+        .map_err(|_| Error::DecryptionFailed)?;
+    let group_info = GroupInfo::decode_all(&group_info)?;
+
+    // Rebuild and check the tree.
+    let mut tree = group_info.verified_tree(ciphersuite, ratchet_tree, clock)?;
+
+    // Find our leaf and install our private keys.
+    let own_leaf = tree
+        .members()
+        .find(|(_, kp)| kp.hash() == key_package_hash)
+        .map(|(i, _)| i)
+        .ok_or(Error::OwnLeafNotFound)?;
+    tree.set_leaf(own_leaf, key_package.clone())?;
+    if let Some(path_secret) = &group_secrets.path_secret {
+        let signer_direct_path = tree.direct_path_ids(2 * group_info.signer_index);
+        let own_direct_path = tree.direct_path_ids(2 * own_leaf);
+        let i = signer_direct_path
+            .iter()
+            .position(|id| own_direct_path.contains(id))
+            .ok_or(TreeError::InvalidUpdatePath)?;
+        tree.apply_path_secret(&signer_direct_path[i..], path_secret)?;
+    }
+
+    // Derive the epoch secrets and check the confirmation tag.
+    let group_context = group_info.group_context();
+    let psk_secret = psk_secret(ciphersuite, &group_secrets.psks, psk_store)?;
+    let epoch_secrets = key_schedule.epoch_secrets(
+        &group_secrets.joiner_secret,
+        psk_secret.as_deref(),
+        &group_context,
+    );
+    if !verify_confirmation_tag(
+        ciphersuite,
+        &epoch_secrets.confirmation_key,
+        &group_info.confirmed_transcript_hash,
+        &group_info.confirmation_tag,
+    ) {
+        return Err(Error::InvalidConfirmationTag);
+    }
+
+    let interim_transcript_hash = interim_transcript_hash_from_tag(
+        ciphersuite,
+        &group_info.confirmed_transcript_hash,
+        &group_info.confirmation_tag,
+    );
+
+    Ok(ProcessWelcomeResult {
+        tree,
+        group_context,
+        epoch_secrets,
+        interim_transcript_hash,
+        confirmation_tag: group_info.confirmation_tag,
+        own_leaf,
+        psks: group_secrets.psks,
+    })
+}
@@ -0,0 +1,276 @@
+use std::collections::HashMap;
+
+use mls_protospec::prelude::*;
+
+const DEFAULT_CIPHERSUITE: CiphersuiteName =
+    CiphersuiteName::MLS10_128_DHKEMX25519_AES128GCM_SHA256_Ed25519;
+
+fn key_package() -> KeyPackage {
+    KeyPackage::new_with_lifetime(
+        ProtocolVersion::Mls10,
+        DEFAULT_CIPHERSUITE.into(),
+        Credential::basic(BasicCredential::ed25519()),
+        60 * 60,
+        &SystemClock,
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_create_commit() {
+    let mut tree = Tree::new(DEFAULT_CIPHERSUITE);
+    tree.add_member(key_package()).unwrap();
+    let group_context = GroupContext::new(b"group", 0, &tree.tree_hash().unwrap(), &[], &[]);
+    let epoch_secrets = EpochSecrets::default();
+    let proposal_store = ProposalStore::new(b"group", 0);
+    let psk_store: HashMap<Vec<u8>, Vec<u8>> = HashMap::new();
+
+    // Adding a member doesn't require a path.
+    let add = Proposal::Add(AddProposal::new(key_package()));
+    let result = create_commit(
+        CommitParams {
+            tree: &tree,
+            group_context: &group_context,
+            epoch_secrets: &epoch_secrets,
+            interim_transcript_hash: &[],
+            own_leaf: 0,
+            proposal_store: &proposal_store,
+            proposal_refs: &[],
+            proposals: &[add],
+            authenticated_data: &[],
+            psk_store: &psk_store,
+            clock: &SystemClock,
+        },
+        false,
+    )
+    .unwrap();
+    assert_eq!(result.get_group_context().get_epoch(), 1);
+    assert_eq!(result.get_tree().members().count(), 2);
+    assert_eq!(tree.members().count(), 1);
+    let plaintext = result.get_plaintext();
+    assert!(plaintext.get_confirmation_tag().is_some());
+    match plaintext.get_content() {
+        MLSPlaintextContent::Commit(commit) => {
+            assert_eq!(commit.get_proposals().len(), 1);
+            assert!(commit.get_path().is_none());
+        }
+        _ => panic!("Expected a commit"),
+    }
+
+    // An empty commit needs a path.
+    let result = create_commit(
+        CommitParams {
+            tree: &tree,
+            group_context: &group_context,
+            epoch_secrets: &epoch_secrets,
+            interim_transcript_hash: &[],
+            own_leaf: 0,
+            proposal_store: &proposal_store,
+            proposal_refs: &[],
+            proposals: &[],
+            authenticated_data: &[],
+            psk_store: &psk_store,
+            clock: &SystemClock,
+        },
+        false,
+    )
+    .unwrap();
+    match result.get_plaintext().get_content() {
+        MLSPlaintextContent::Commit(commit) => assert!(commit.get_path().is_some()),
+        _ => panic!("Expected a commit"),
+    }
+}
+
+#[test]
+fn test_process_commit() {
+    let alice = key_package();
+    let bob = key_package();
+    let charlie = key_package();
+
+    // Everybody starts with the same public tree and secrets.
+    let mut trees = Vec::new();
+    for _ in 0..3 {
+        let mut tree = Tree::new(DEFAULT_CIPHERSUITE);
+        tree.add_member(alice.clone()).unwrap();
+        tree.add_member(bob.clone()).unwrap();
+        tree.add_member(charlie.clone()).unwrap();
+        trees.push(tree);
+    }
+    let tree_hash = trees[0].tree_hash().unwrap();
+    let group_context = GroupContext::new(b"group", 0, &tree_hash, &[], &[]);
+    let epoch_secrets = EpochSecrets::default();
+    let proposal_store = ProposalStore::new(b"group", 0);
+    let psk_store: HashMap<Vec<u8>, Vec<u8>> = HashMap::new();
+
+    // Alice removes Charlie.
+    let remove = Proposal::Remove(RemoveProposal::new(2));
+    let created = create_commit(
+        CommitParams {
+            tree: &trees[0],
+            group_context: &group_context,
+            epoch_secrets: &epoch_secrets,
+            interim_transcript_hash: &[],
+            own_leaf: 0,
+            proposal_store: &proposal_store,
+            proposal_refs: &[],
+            proposals: &[remove],
+            authenticated_data: &[],
+            psk_store: &psk_store,
+            clock: &SystemClock,
+        },
+        false,
+    )
+    .unwrap();
+    let plaintext = MLSPlaintext::decode_all(&created.get_plaintext().encode()).unwrap();
+
+    let bob_params = || ProcessCommitParams {
+        tree: &trees[1],
+        group_context: &group_context,
+        epoch_secrets: &epoch_secrets,
+        interim_transcript_hash: &[],
+        own_leaf: 1,
+        proposal_store: &proposal_store,
+        psk_store: &psk_store,
+        clock: &SystemClock,
+    };
+    let processed = process_commit(bob_params(), &plaintext).unwrap();
+    assert_eq!(processed.get_group_context(), created.get_group_context());
+    assert_eq!(processed.get_tree().members().count(), 2);
+
+    // Charlie is removed and can't process the commit.
+    let charlie_params = ProcessCommitParams {
+        tree: &trees[2],
+        group_context: &group_context,
+        epoch_secrets: &epoch_secrets,
+        interim_transcript_hash: &[],
+        own_leaf: 2,
+        proposal_store: &proposal_store,
+        psk_store: &psk_store,
+        clock: &SystemClock,
+    };
+    assert_eq!(
+        process_commit(charlie_params, &plaintext).unwrap_err(),
+        mls_protospec::commit::Error::OwnLeafRemoved
+    );
+
+    // A modified commit is rejected.
+    let mut encoded = created.get_plaintext().encode();
+    let last = encoded.len() - 1;
+    encoded[last] ^= 0xff;
+    let tampered = MLSPlaintext::decode_all(&encoded).unwrap();
+    assert_eq!(
+        process_commit(bob_params(), &tampered).unwrap_err(),
+        mls_protospec::commit::Error::InvalidMembershipTag
+    );
+}
+
+#[test]
+fn test_create_welcome() {
+    let mut tree = Tree::new(DEFAULT_CIPHERSUITE);
+    tree.add_member(key_package()).unwrap();
+    tree.add_member(key_package()).unwrap();
+    let group_context = GroupContext::new(b"group", 0, &tree.tree_hash().unwrap(), &[], &[]);
+    let epoch_secrets = EpochSecrets::default();
+    let proposal_store = ProposalStore::new(b"group", 0);
+    let psk_store: HashMap<Vec<u8>, Vec<u8>> = HashMap::new();
+
+    let new_members = [key_package(), key_package()];
+    let adds: Vec<Proposal> = new_members
+        .iter()
+        .map(|kp| Proposal::Add(AddProposal::new(kp.clone())))
+        .collect();
+    let result = create_commit(
+        CommitParams {
+            tree: &tree,
+            group_context: &group_context,
+            epoch_secrets: &epoch_secrets,
+            interim_transcript_hash: &[],
+            own_leaf: 0,
+            proposal_store: &proposal_store,
+            proposal_refs: &[],
+            proposals: &adds,
+            authenticated_data: &[],
+            psk_store: &psk_store,
+            clock: &SystemClock,
+        },
+        true,
+    )
+    .unwrap();
+    let welcome = result.create_welcome(true).unwrap().unwrap();
+    let welcome = Welcome::decode_all(&welcome.encode()).unwrap();
+    assert_eq!(welcome.get_secrets().len(), 2);
+}
+
+#[test]
+fn test_process_welcome() {
+    let mut tree = Tree::new(DEFAULT_CIPHERSUITE);
+    tree.add_member(key_package()).unwrap();
+    tree.add_member(key_package()).unwrap();
+    let group_context = GroupContext::new(b"group", 0, &tree.tree_hash().unwrap(), &[], &[]);
+    let epoch_secrets = EpochSecrets::default();
+    let proposal_store = ProposalStore::new(b"group", 0);
+    let psk_store: HashMap<Vec<u8>, Vec<u8>> = HashMap::new();
+
+    let new_member = key_package();
+    let add = Proposal::Add(AddProposal::new(new_member.clone()));
+    let result = create_commit(
+        CommitParams {
+            tree: &tree,
+            group_context: &group_context,
+            epoch_secrets: &epoch_secrets,
+            interim_transcript_hash: &[],
+            own_leaf: 0,
+            proposal_store: &proposal_store,
+            proposal_refs: &[],
+            proposals: &[add],
+            authenticated_data: &[],
+            psk_store: &psk_store,
+            clock: &SystemClock,
+        },
+        true,
+    )
+    .unwrap();
+
+    // The ratchet tree is in the GroupInfo.
+    let welcome = result.create_welcome(true).unwrap().unwrap();
+    let joined = process_welcome(&welcome, &new_member, None, &psk_store, &SystemClock).unwrap();
+    assert_eq!(joined.get_own_leaf(), 2);
+    assert_eq!(
+        joined.get_group_context().encode(),
+        result.get_group_context().encode()
+    );
+
+    // The ratchet tree is sent out of band.
+    let welcome = result.create_welcome(false).unwrap().unwrap();
+    assert_eq!(
+        process_welcome(&welcome, &new_member, None, &psk_store, &SystemClock).unwrap_err(),
+        mls_protospec::welcome::Error::MissingRatchetTree
+    );
+    let exported = result.get_tree().export_public().unwrap();
+    let tree = Tree::import_public(DEFAULT_CIPHERSUITE, &exported, &SystemClock).unwrap();
+    let joined =
+        process_welcome(&welcome, &new_member, Some(&tree), &psk_store, &SystemClock).unwrap();
+    assert_eq!(joined.get_own_leaf(), 2);
+
+    // Somebody else's key package can't be used.
+    assert_eq!(
+        process_welcome(
+            &welcome,
+            &key_package(),
+            Some(&tree),
+            &psk_store,
+            &SystemClock
+        )
+        .unwrap_err(),
+        mls_protospec::welcome::Error::NoMatchingKeyPackage
+    );
+
+    // The key packages in the tree expire after an hour.
+    let later = FixedClock(SystemClock.now() + 2 * 60 * 60);
+    assert_eq!(
+        process_welcome(&welcome, &new_member, Some(&tree), &psk_store, &later).unwrap_err(),
+        mls_protospec::welcome::Error::TreeError(TreeError::InvalidKeyPackage(
+            mls_protospec::key_package::Error::Expired
+        ))
+    );
+}
@@ -0,0 +1,529 @@
+use std::collections::HashMap;
+
+use mls_protospec::prelude::*;
+
+const DEFAULT_CIPHERSUITE: CiphersuiteName =
+    CiphersuiteName::MLS10_128_DHKEMX25519_AES128GCM_SHA256_Ed25519;
+
+fn key_package_for(credential: &Credential, ciphersuite: CiphersuiteName) -> KeyPackage {
+    KeyPackage::new_with_lifetime(
+        ProtocolVersion::Mls10,
+        ciphersuite.into(),
+        credential.clone(),
+        60 * 60,
+        &SystemClock,
+    )
+    .unwrap()
+}
+
+fn key_package(credential: &Credential) -> KeyPackage {
+    key_package_for(credential, DEFAULT_CIPHERSUITE)
+}
+
+/// Alice creates a group and adds Bob, both with key packages from
+/// `key_package`.
+fn two_member_group(
+    key_package: impl Fn(&Credential) -> KeyPackage,
+) -> (Group, Group, Credential, Credential) {
+    let alice_credential = Credential::basic(BasicCredential::ed25519());
+    let bob_credential = Credential::basic(BasicCredential::ed25519());
+    let mut alice = Group::create(b"group", key_package(&alice_credential)).unwrap();
+    let bob_key_package = key_package(&bob_credential);
+    alice.propose_add(bob_key_package.clone()).unwrap();
+    let pending_commit = alice.commit(&[], false).unwrap();
+    let welcome = pending_commit.get_welcome().unwrap().clone();
+    alice.merge_pending_commit(pending_commit).unwrap();
+    let bob = Group::join(&welcome, &bob_key_package, None, HashMap::new()).unwrap();
+    (alice, bob, alice_credential, bob_credential)
+}
+
+#[test]
+fn test_group() {
+    // Alice creates the group and adds Bob.
+    let (mut alice, mut bob, _, bob_credential) = two_member_group(key_package);
+    assert_eq!(alice.get_epoch(), 1);
+    assert_eq!(bob.get_own_leaf(), 1);
+    assert_eq!(bob.get_group_context(), alice.get_group_context());
+
+    // Application messages in both directions.
+    let ciphertext = alice.encrypt_application(b"Hi Bob", b"aad").unwrap();
+    let ciphertext = MLSCiphertext::decode_all(&ciphertext.encode()).unwrap();
+    let message = bob.decrypt_application(&ciphertext).unwrap();
+    assert_eq!(message.get_sender(), 0);
+    assert_eq!(message.get_data(), b"Hi Bob");
+    assert_eq!(message.get_authenticated_data(), b"aad");
+    let ciphertext = bob.encrypt_application(b"Hi Alice", &[]).unwrap();
+    assert_eq!(
+        alice.decrypt_application(&ciphertext).unwrap().get_data(),
+        b"Hi Alice"
+    );
+    // Replays are rejected.
+    assert!(alice.decrypt_application(&ciphertext).is_err());
+    // A corrupted message doesn't use up the key of its generation.
+    let ciphertext = alice.encrypt_application(b"Intact", &[]).unwrap();
+    let mut corrupted = ciphertext.encode();
+    *corrupted.last_mut().unwrap() ^= 1;
+    let corrupted = MLSCiphertext::decode_all(&corrupted).unwrap();
+    assert!(bob.decrypt_application(&corrupted).is_err());
+    assert_eq!(
+        bob.decrypt_application(&ciphertext).unwrap().get_data(),
+        b"Intact"
+    );
+
+    // Bob updates, Alice commits.
+    let update = bob.propose_update(key_package(&bob_credential)).unwrap();
+    match alice.process_message(&update).unwrap() {
+        GroupEvent::ProposalReceived(proposal) => {
+            assert_eq!(proposal.get_sender(), Sender::Member(1))
+        }
+        e => panic!("Unexpected event {:?}", e),
+    }
+    let pending_commit = alice.commit(&[], false).unwrap();
+    assert!(pending_commit.get_welcome().is_none());
+    assert_eq!(alice.get_epoch(), 1);
+    match bob.process_message(pending_commit.get_plaintext()).unwrap() {
+        GroupEvent::NewEpoch(change) => {
+            assert_eq!(change.get_epoch(), 2);
+            assert_eq!(change.get_committer(), 0);
+            assert_eq!(change.get_updated(), &[1]);
+        }
+        e => panic!("Unexpected event {:?}", e),
+    }
+    alice.merge_pending_commit(pending_commit).unwrap();
+    assert_eq!(bob.get_group_context(), alice.get_group_context());
+    let ciphertext = bob.encrypt_application(b"Still here", &[]).unwrap();
+    assert_eq!(
+        alice.decrypt_application(&ciphertext).unwrap().get_data(),
+        b"Still here"
+    );
+
+    // Alice removes Bob. A commit with a broken confirmation tag doesn't
+    // remove him. The confirmation tag is followed by the membership tag.
+    let pending_commit = alice
+        .commit(&[Proposal::Remove(RemoveProposal::new(1))], false)
+        .unwrap();
+    let mut broken = pending_commit.get_plaintext().encode();
+    let confirmation_tag_end = broken.len() - 34;
+    broken[confirmation_tag_end - 1] ^= 1;
+    let broken = MLSPlaintext::decode_all(&broken).unwrap();
+    assert_eq!(
+        bob.process_message(&broken).unwrap_err(),
+        mls_protospec::group::Error::CommitError(
+            mls_protospec::commit::Error::InvalidMembershipTag
+        )
+    );
+    assert!(bob.is_active());
+    match bob.process_message(pending_commit.get_plaintext()).unwrap() {
+        GroupEvent::Removed => (),
+        e => panic!("Unexpected event {:?}", e),
+    }
+    assert!(!bob.is_active());
+    assert!(bob.encrypt_application(b"Hello?", &[]).is_err());
+}
+
+#[test]
+fn test_competing_commits() {
+    let (mut alice, mut bob, _, _) = two_member_group(key_package);
+
+    // Both commit in the same epoch and Bob's commit wins.
+    let alice_commit = alice.commit(&[], true).unwrap();
+    let bob_commit = bob.commit(&[], true).unwrap();
+    assert_eq!(alice.get_epoch(), 1);
+    match alice.process_message(bob_commit.get_plaintext()).unwrap() {
+        GroupEvent::NewEpoch(change) => assert_eq!(change.get_committer(), 1),
+        e => panic!("Unexpected event {:?}", e),
+    }
+    bob.merge_pending_commit(bob_commit).unwrap();
+    assert_eq!(alice.get_group_context(), bob.get_group_context());
+
+    // Alice's commit is outdated now.
+    assert_eq!(
+        alice.merge_pending_commit(alice_commit).unwrap_err(),
+        mls_protospec::group::Error::WrongEpoch
+    );
+}
+
+#[test]
+fn test_external_psk() {
+    let (mut alice, mut bob, _, _) = two_member_group(key_package);
+    alice.add_external_psk(b"psk", &[1u8; 32]);
+    bob.add_external_psk(b"psk", &[1u8; 32]);
+
+    // The PSK is injected into the next epoch.
+    let proposal = alice.propose_external_psk(b"psk").unwrap();
+    bob.process_message(&proposal).unwrap();
+    let pending_commit = alice.commit(&[], false).unwrap();
+    bob.process_message(pending_commit.get_plaintext()).unwrap();
+    alice.merge_pending_commit(pending_commit).unwrap();
+    assert_eq!(bob.get_group_context(), alice.get_group_context());
+    let ciphertext = alice.encrypt_application(b"PSK", &[]).unwrap();
+    assert_eq!(bob.decrypt_application(&ciphertext).unwrap().get_data(), b"PSK");
+
+    // Unknown PSKs can't be committed.
+    alice.propose_external_psk(b"unknown").unwrap();
+    match alice.commit(&[], false).unwrap_err() {
+        mls_protospec::group::Error::CommitError(mls_protospec::commit::Error::PskError(_)) => (),
+        e => panic!("Unexpected error {:?}", e),
+    }
+}
+
+#[test]
+fn test_epoch_history() {
+    let (mut alice, mut bob, _, _) = two_member_group(key_package);
+    alice.set_history_limits(2, true);
+    bob.set_history_limits(2, true);
+    assert_eq!(alice.get_history().len(), 1);
+    assert!(alice.get_history().get(b"group", 0).is_some());
+
+    // Bob sends a message that arrives after Alice moved to the next epoch.
+    let late_message = bob.encrypt_application(b"Late", &[]).unwrap();
+    let pending_commit = alice.commit(&[], true).unwrap();
+    bob.process_message(pending_commit.get_plaintext()).unwrap();
+    alice.merge_pending_commit(pending_commit).unwrap();
+    assert_eq!(
+        alice.decrypt_application(&late_message).unwrap().get_data(),
+        b"Late"
+    );
+
+    // Both can inject the resumption secret of a past epoch.
+    let proposal = alice.propose_resumption_psk(1).unwrap();
+    bob.process_message(&proposal).unwrap();
+    let pending_commit = alice.commit(&[], false).unwrap();
+    bob.process_message(pending_commit.get_plaintext()).unwrap();
+    alice.merge_pending_commit(pending_commit).unwrap();
+    assert_eq!(alice.get_group_context(), bob.get_group_context());
+
+    // Only the last two epochs are kept.
+    assert_eq!(alice.get_history().len(), 2);
+    assert!(alice.get_history().get(b"group", 0).is_none());
+    assert_eq!(
+        alice.get_history().get_resumption_secret(b"group", 1),
+        bob.get_history().get_resumption_secret(b"group", 1)
+    );
+    assert_eq!(
+        alice.decrypt_application(&late_message).unwrap_err(),
+        mls_protospec::group::Error::CiphertextError(
+            mls_protospec::ciphertext::Error::SecretTreeError(
+                mls_protospec::secret_tree::Error::GenerationTooOld
+            )
+        )
+    );
+}
+
+#[test]
+fn test_reinit() {
+    let old_ciphersuite = CiphersuiteName::MLS10_128_DHKEMP256_AES128GCM_SHA256_P256;
+    let (mut alice, mut bob, alice_credential, bob_credential) =
+        two_member_group(|credential| key_package_for(credential, old_ciphersuite));
+
+    // Bob proposes to move the group to the default ciphersuite, Alice
+    // commits.
+    let proposal = bob
+        .propose_reinit(b"new group", ProtocolVersion::Mls10, DEFAULT_CIPHERSUITE, Vec::new())
+        .unwrap();
+    alice.process_message(&proposal).unwrap();
+    let pending_commit = alice.commit(&[], false).unwrap();
+    match bob.process_message(pending_commit.get_plaintext()).unwrap() {
+        GroupEvent::NewEpoch(change) => {
+            assert_eq!(change.get_reinit().unwrap().get_group_id(), b"new group")
+        }
+        e => panic!("Unexpected event {:?}", e),
+    }
+    alice.merge_pending_commit(pending_commit).unwrap();
+    assert_eq!(
+        bob.encrypt_application(b"Hello?", &[]).unwrap_err(),
+        mls_protospec::group::Error::ReInitPending
+    );
+
+    // The successor group must match the ReInit.
+    assert_eq!(
+        alice
+            .create_reinit_group(key_package_for(&alice_credential, old_ciphersuite), Vec::new())
+            .unwrap_err(),
+        mls_protospec::group::Error::ReInitMismatch
+    );
+    let bob_key_package = key_package(&bob_credential);
+    let (mut new_alice, welcome) = alice
+        .create_reinit_group(key_package(&alice_credential), vec![bob_key_package.clone()])
+        .unwrap();
+    let welcome = welcome.unwrap();
+
+    // The Welcome can't be processed without the resumption secret of the
+    // old group.
+    assert!(Group::join(&welcome, &bob_key_package, None, HashMap::new()).is_err());
+    let mut new_bob = bob.join_reinit_group(&welcome, &bob_key_package, None).unwrap();
+    assert_eq!(new_bob.get_group_id(), b"new group");
+    assert_eq!(new_bob.get_group_context(), new_alice.get_group_context());
+    assert_eq!(*new_bob.get_tree().get_ciphersuite().get_name(), DEFAULT_CIPHERSUITE);
+    let ciphertext = new_alice.encrypt_application(b"Hi Bob", &[]).unwrap();
+    assert_eq!(
+        new_bob.decrypt_application(&ciphertext).unwrap().get_data(),
+        b"Hi Bob"
+    );
+    new_bob.encrypt_application(b"Hi Alice", &[]).unwrap();
+}
+
+#[test]
+fn test_branch() {
+    let (mut alice, mut bob, alice_credential, bob_credential) = two_member_group(key_package);
+    let charlie = key_package(&Credential::basic(BasicCredential::ed25519()));
+    let pending_commit = alice
+        .commit(&[Proposal::Add(AddProposal::new(charlie))], false)
+        .unwrap();
+    bob.process_message(pending_commit.get_plaintext()).unwrap();
+    alice.merge_pending_commit(pending_commit).unwrap();
+
+    // Only members can be in the subgroup.
+    let outsider = key_package(&Credential::basic(BasicCredential::ed25519()));
+    assert_eq!(
+        alice
+            .branch(b"subgroup", key_package(&alice_credential), vec![outsider])
+            .unwrap_err(),
+        mls_protospec::group::Error::NotAMember
+    );
+
+    // Alice branches a subgroup with Bob but without Charlie.
+    let bob_key_package = key_package(&bob_credential);
+    let (mut subgroup_alice, welcome) = alice
+        .branch(b"subgroup", key_package(&alice_credential), vec![bob_key_package.clone()])
+        .unwrap();
+    let welcome = welcome.unwrap();
+    let mut subgroup_bob = bob.join_branch(&welcome, &bob_key_package, None).unwrap();
+    assert_eq!(subgroup_bob.get_group_id(), b"subgroup");
+    assert_eq!(subgroup_bob.get_group_context(), subgroup_alice.get_group_context());
+    assert_eq!(subgroup_bob.get_tree().members().count(), 2);
+    assert!(subgroup_bob.get_history().get(b"group", 2).is_some());
+    let ciphertext = subgroup_alice.encrypt_application(b"Hi Bob", &[]).unwrap();
+    assert_eq!(
+        subgroup_bob.decrypt_application(&ciphertext).unwrap().get_data(),
+        b"Hi Bob"
+    );
+
+    // The Welcome can't be processed without the parent group's secret.
+    assert!(Group::join(&welcome, &bob_key_package, None, HashMap::new()).is_err());
+}
+
+#[test]
+fn test_external_commit() {
+    let (mut alice, mut bob, _, bob_credential) = two_member_group(key_package);
+    let charlie_credential = Credential::basic(BasicCredential::ed25519());
+
+    // Charlie joins with the published GroupInfo.
+    let group_info = alice.group_info(true).unwrap();
+    let group_info = GroupInfo::decode_all(&group_info.encode()).unwrap();
+    let (mut charlie, commit) =
+        Group::join_external(&group_info, None, key_package(&charlie_credential), false).unwrap();
+    assert_eq!(charlie.get_own_leaf(), 2);
+    assert_eq!(charlie.get_epoch(), 2);
+    let commit = MLSPlaintext::decode_all(&commit.encode()).unwrap();
+    assert_eq!(commit.get_sender(), Sender::NewMember);
+    for group in [&mut alice, &mut bob].iter_mut() {
+        match group.process_message(&commit).unwrap() {
+            GroupEvent::NewEpoch(change) => {
+                assert_eq!(change.get_committer(), 2);
+                assert_eq!(change.get_added(), &[2]);
+            }
+            e => panic!("Unexpected event {:?}", e),
+        }
+        assert_eq!(group.get_group_context(), charlie.get_group_context());
+    }
+    let ciphertext = charlie.encrypt_application(b"Hi all", &[]).unwrap();
+    assert_eq!(
+        bob.decrypt_application(&ciphertext).unwrap().get_data(),
+        b"Hi all"
+    );
+
+    // Bob lost his state and rejoins, replacing his old leaf. The tree is
+    // sent out of band.
+    let group_info = charlie.group_info(false).unwrap();
+    let exported = charlie.get_tree().export_public().unwrap();
+    let ratchet_tree = Tree::import_public(DEFAULT_CIPHERSUITE, &exported, &SystemClock).unwrap();
+    assert!(Group::join_external(&group_info, None, key_package(&bob_credential), true).is_err());
+    let (new_bob, commit) = Group::join_external(
+        &group_info,
+        Some(&ratchet_tree),
+        key_package(&bob_credential),
+        true,
+    )
+    .unwrap();
+    assert_eq!(new_bob.get_tree().members().count(), 3);
+    for group in [&mut alice, &mut charlie].iter_mut() {
+        match group.process_message(&commit).unwrap() {
+            GroupEvent::NewEpoch(change) => {
+                assert_eq!(change.get_removed(), &[1]);
+                assert_eq!(change.get_added(), &[new_bob.get_own_leaf()]);
+            }
+            e => panic!("Unexpected event {:?}", e),
+        }
+        assert_eq!(group.get_group_context(), new_bob.get_group_context());
+    }
+    match bob.process_message(&commit).unwrap() {
+        GroupEvent::Removed => (),
+        e => panic!("Unexpected event {:?}", e),
+    }
+}
+
+#[test]
+fn test_external_sender() {
+    let alice_credential = Credential::basic(BasicCredential::ed25519());
+    let bob_credential = Credential::basic(BasicCredential::ed25519());
+    let server_credential = Credential::basic(BasicCredential::ed25519());
+
+    let external_senders = Extension::ExternalSenders(ExternalSendersExtension::new(vec![
+        server_credential.clone(),
+    ]));
+    let mut alice = Group::create_with_extensions(
+        b"group",
+        key_package(&alice_credential),
+        &[external_senders],
+    )
+    .unwrap();
+    let bob_key_package = key_package(&bob_credential);
+    let pending_commit = alice
+        .commit(&[Proposal::Add(AddProposal::new(bob_key_package.clone()))], false)
+        .unwrap();
+    let welcome = pending_commit.get_welcome().unwrap().clone();
+    alice.merge_pending_commit(pending_commit).unwrap();
+    let mut bob = Group::join(&welcome, &bob_key_package, None, HashMap::new()).unwrap();
+
+    // The server can't update, it has no leaf.
+    let update = MLSPlaintext::external_proposal(
+        b"group",
+        1,
+        0,
+        Proposal::Update(UpdateProposal::new(key_package(&server_credential))),
+        &server_credential,
+    )
+    .unwrap();
+    assert_eq!(
+        alice.process_message(&update).unwrap_err(),
+        mls_protospec::group::Error::ExternalProposalNotAllowed
+    );
+
+    // Only the configured senders are accepted.
+    let remove = Proposal::Remove(RemoveProposal::new(1));
+    let unknown =
+        MLSPlaintext::external_proposal(b"group", 1, 1, remove.clone(), &server_credential)
+            .unwrap();
+    assert_eq!(
+        alice.process_message(&unknown).unwrap_err(),
+        mls_protospec::group::Error::InvalidSender
+    );
+    let forged =
+        MLSPlaintext::external_proposal(b"group", 1, 0, remove.clone(), &bob_credential).unwrap();
+    assert_eq!(
+        alice.process_message(&forged).unwrap_err(),
+        mls_protospec::group::Error::InvalidSignature
+    );
+
+    // The server removes Bob.
+    let remove =
+        MLSPlaintext::external_proposal(b"group", 1, 0, remove, &server_credential).unwrap();
+    let remove = MLSPlaintext::decode_all(&remove.encode()).unwrap();
+    match alice.process_message(&remove).unwrap() {
+        GroupEvent::ProposalReceived(proposal) => {
+            assert_eq!(proposal.get_sender(), Sender::External(0))
+        }
+        e => panic!("Unexpected event {:?}", e),
+    }
+    bob.process_message(&remove).unwrap();
+    let pending_commit = alice.commit(&[], false).unwrap();
+    match bob.process_message(pending_commit.get_plaintext()).unwrap() {
+        GroupEvent::Removed => (),
+        e => panic!("Unexpected event {:?}", e),
+    }
+    assert_eq!(
+        alice.merge_pending_commit(pending_commit).unwrap().get_removed(),
+        &[1]
+    );
+}
+
+#[test]
+fn test_group_context_extensions() {
+    let capable_key_package = |credential: &Credential| {
+        let mut key_package = key_package(credential);
+        key_package.set_capabilities(CapabilitiesExtension::new(
+            vec![ProtocolVersion::Mls10],
+            vec![DEFAULT_CIPHERSUITE],
+            vec![ExtensionType::ExternalSenders],
+            Vec::new(),
+            Vec::new(),
+        ));
+        key_package.sign().unwrap();
+        key_package
+    };
+    let (mut alice, mut bob, _, _) = two_member_group(capable_key_package);
+    let charlie_credential = Credential::basic(BasicCredential::ed25519());
+
+    // Both members support the external_senders extension.
+    let required = Extension::RequiredCapabilities(RequiredCapabilitiesExtension::new(
+        vec![ExtensionType::ExternalSenders],
+        Vec::new(),
+        Vec::new(),
+    ));
+    let proposal = alice
+        .propose_group_context_extensions(vec![required.clone()])
+        .unwrap();
+    bob.process_message(&proposal).unwrap();
+    let pending_commit = alice.commit(&[], false).unwrap();
+    bob.process_message(pending_commit.get_plaintext()).unwrap();
+    alice.merge_pending_commit(pending_commit).unwrap();
+    assert_eq!(alice.get_group_context().get_extensions(), &[required]);
+    assert_eq!(bob.get_group_context(), alice.get_group_context());
+
+    // Charlie doesn't, so he can't be added.
+    let add = Proposal::Add(AddProposal::new(key_package(&charlie_credential)));
+    match alice.commit(&[add], false).unwrap_err() {
+        mls_protospec::group::Error::CommitError(mls_protospec::commit::Error::InvalidProposals(
+            invalid,
+        )) => assert_eq!(
+            invalid[0].error,
+            ProposalError::UnsupportedRequiredCapabilities
+        ),
+        e => panic!("Unexpected error {:?}", e),
+    }
+    let add = Proposal::Add(AddProposal::new(capable_key_package(&charlie_credential)));
+    assert!(alice.commit(&[add], false).is_ok());
+}
+
+#[test]
+fn test_export_secret() {
+    let (mut alice, mut bob, _, _) = two_member_group(key_package);
+
+    // Both members export the same secrets.
+    let media_key = alice.export_secret("media", b"call 1", 16).unwrap();
+    assert_eq!(media_key.len(), 16);
+    assert_eq!(bob.export_secret("media", b"call 1", 16).unwrap(), media_key);
+    assert_ne!(alice.export_secret("media", b"call 2", 16).unwrap(), media_key);
+    assert_ne!(alice.export_secret("chat", b"call 1", 16).unwrap(), media_key);
+    assert_eq!(
+        alice.export_secret("media", b"call 1", 255 * 32 + 1).unwrap_err(),
+        mls_protospec::group::Error::ExportTooLong
+    );
+
+    // A new epoch has new secrets.
+    let pending_commit = alice.commit(&[], false).unwrap();
+    bob.process_message(pending_commit.get_plaintext()).unwrap();
+    alice.merge_pending_commit(pending_commit).unwrap();
+    let new_media_key = alice.export_secret("media", b"call 1", 16).unwrap();
+    assert_ne!(new_media_key, media_key);
+    assert_eq!(bob.export_secret("media", b"call 1", 16).unwrap(), new_media_key);
+}
+
+#[test]
+fn test_epoch_authenticator() {
+    let (mut alice, mut bob, _, _) = two_member_group(key_package);
+
+    assert_eq!(alice.get_epoch_authenticator(), bob.get_epoch_authenticator());
+    let number = alice.safety_number();
+    assert_eq!(number, bob.safety_number());
+    assert_eq!(number.len(), 6 * 5 + 5);
+    assert!(number.split(' ').all(|g| g.len() == 5 && g.chars().all(|c| c.is_ascii_digit())));
+
+    // The number changes with the epoch.
+    let pending_commit = alice.commit(&[], false).unwrap();
+    bob.process_message(pending_commit.get_plaintext()).unwrap();
+    alice.merge_pending_commit(pending_commit).unwrap();
+    assert_ne!(alice.safety_number(), number);
+    assert_eq!(alice.safety_number(), bob.safety_number());
+}
@@ -40,3 +40,108 @@ fn test_tree_hash() {
     let tree_hash = tree.hash_node(root);
     println!("Tree hash: {:?}", tree_hash);
 }
+
+#[test]
+fn test_incremental_tree_hash() {
+    let mut tree = Tree::new(DEFAULT_CIPHERSUITE);
+    for _ in 0..5 {
+        tree.add_leaf();
+    }
+    let hash_5 = tree.tree_hash().unwrap();
+    assert_eq!(hash_5, tree.hash_node(tree.get_root()).unwrap());
+
+    // Growing the tree only re-hashes the new direct path and the old root.
+    for _ in 0..4 {
+        tree.add_leaf();
+    }
+    let hash_9 = tree.tree_hash().unwrap();
+    assert_ne!(hash_5, hash_9);
+
+    // The incremental hash has to match a tree hashed from scratch.
+    let mut fresh_tree = Tree::new(DEFAULT_CIPHERSUITE);
+    for _ in 0..9 {
+        fresh_tree.add_leaf();
+    }
+    assert_eq!(hash_9, fresh_tree.tree_hash().unwrap());
+}
+
+#[test]
+fn test_public_tree_export_import() {
+    let mut tree = Tree::new(DEFAULT_CIPHERSUITE);
+    for _ in 0..5 {
+        tree.add_leaf();
+    }
+    let exported = tree.export_public().unwrap();
+    let mut imported = Tree::import_public(DEFAULT_CIPHERSUITE, &exported, &SystemClock).unwrap();
+    assert_eq!(imported.num_nodes(), tree.num_nodes());
+    assert_eq!(imported.export_public().unwrap(), exported);
+    assert_eq!(imported.tree_hash().unwrap(), tree.tree_hash().unwrap());
+
+    // An even number of nodes is not a valid tree.
+    let mut broken = exported.clone();
+    broken.pop();
+    broken[3] -= 1;
+    assert_eq!(
+        Tree::import_public(DEFAULT_CIPHERSUITE, &broken, &SystemClock).unwrap_err(),
+        TreeError::InconsistentTreeState
+    );
+}
+
+#[test]
+fn test_tree_diff() {
+    let mut tree = Tree::new(DEFAULT_CIPHERSUITE);
+    for _ in 0..5 {
+        tree.add_leaf();
+    }
+    let hash_5 = tree.tree_hash().unwrap();
+
+    // Grow the tree in a diff.
+    let mut diff = tree.diff();
+    for _ in 0..4 {
+        diff.add_leaf();
+    }
+    let hash_9 = diff.tree_hash().unwrap();
+    assert_eq!(diff.num_nodes(), 17);
+    assert!(diff.num_changes() < 17);
+
+    let mut fresh_tree = Tree::new(DEFAULT_CIPHERSUITE);
+    for _ in 0..9 {
+        fresh_tree.add_leaf();
+    }
+    assert_eq!(hash_9, fresh_tree.tree_hash().unwrap());
+    assert_eq!(diff.export_public().unwrap(), fresh_tree.export_public().unwrap());
+
+    // The base tree is unchanged until the diff is merged.
+    let staged_diff = diff.into_staged().unwrap();
+    assert_eq!(tree.num_nodes(), 9);
+    assert_eq!(tree.tree_hash().unwrap(), hash_5);
+
+    // The diff can only be merged into its base.
+    let mut other = Tree::new(DEFAULT_CIPHERSUITE);
+    for _ in 0..5 {
+        other.add_leaf();
+    }
+    let key_package = KeyPackage::new_with_lifetime(
+        ProtocolVersion::Mls10,
+        DEFAULT_CIPHERSUITE.into(),
+        Credential::basic(BasicCredential::ed25519()),
+        60,
+        &SystemClock,
+    )
+    .unwrap();
+    other.add_member(key_package).unwrap();
+    assert_eq!(other.num_nodes(), 9);
+    assert_eq!(
+        other.merge_diff(staged_diff.clone()).unwrap_err(),
+        TreeError::DiffBaseMismatch
+    );
+
+    tree.merge_diff(staged_diff.clone()).unwrap();
+    assert_eq!(tree.num_nodes(), 17);
+    assert_eq!(
+        tree.merge_diff(staged_diff).unwrap_err(),
+        TreeError::DiffBaseMismatch
+    );
+    assert_eq!(tree.tree_hash().unwrap(), hash_9);
+    assert_eq!(tree.export_public().unwrap(), fresh_tree.export_public().unwrap());
+}